@@ -0,0 +1,311 @@
+//! Header decoding for captured frames: Ethernet (with VLAN tags), BSD
+//! loopback, Linux cooked and raw IP link layers, IPv4 and IPv6, then TCP
+//! or UDP.
+//!
+//! IP fragments are not reassembled; fragmented packets are skipped
+//! ([`is_fragment`] tells them apart from other packets that don't decode).
+
+use crate::reassembly::{Segment, TcpFlags};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+pub const LINKTYPE_NULL: u32 = 0;
+pub const LINKTYPE_ETHERNET: u32 = 1;
+pub const LINKTYPE_RAW: u32 = 101;
+/// Raw IP as `DLT_RAW` is numbered on most BSDs.
+pub const LINKTYPE_BSD_RAW: u32 = 12;
+/// Raw IP as `DLT_RAW` is numbered on OpenBSD.
+pub const LINKTYPE_OPENBSD_RAW: u32 = 14;
+pub const LINKTYPE_LOOP: u32 = 108;
+pub const LINKTYPE_LINUX_SLL: u32 = 113;
+pub const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: [u16; 3] = [0x8100, 0x88a8, 0x9100];
+
+const PROTO_TCP: u8 = 6;
+const PROTO_UDP: u8 = 17;
+const PROTO_IPV6_FRAGMENT: u8 = 44;
+
+/// Transport layer of a decoded packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Tcp { seq: u32, flags: TcpFlags },
+    Udp,
+}
+
+/// A TCP or UDP packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decoded<'a> {
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+    pub transport: Transport,
+    /// Transport payload (cut short if the capture was).
+    pub payload: &'a [u8],
+    /// Payload bytes the capture cut off after `payload`, by the IP length.
+    pub missing: usize,
+}
+
+impl<'a> Decoded<'a> {
+    /// The packet as a TCP segment for reassembly.
+    pub fn segment(&self) -> Option<Segment<'a>> {
+        match self.transport {
+            Transport::Tcp { seq, flags } => Some(Segment {
+                src: self.src,
+                dst: self.dst,
+                seq,
+                flags,
+                payload: self.payload,
+                missing: self.missing,
+            }),
+            Transport::Udp => None,
+        }
+    }
+}
+
+/// Decode a frame of the given link type down to TCP or UDP. Returns `None`
+/// for anything else, and for frames too short for their headers.
+pub fn decode(link_type: u32, frame: &[u8]) -> Option<Decoded<'_>> {
+    let ip = network_layer(link_type, frame)?;
+    match ip.first()? >> 4 {
+        4 => ipv4(ip),
+        6 => ipv6(ip),
+        _ => None,
+    }
+}
+
+/// Whether a frame of the given link type holds an IPv4 or IPv6 fragment,
+/// which [`decode`] skips.
+pub fn is_fragment(link_type: u32, frame: &[u8]) -> bool {
+    let Some(ip) = network_layer(link_type, frame) else {
+        return false;
+    };
+    match ip.first().map(|b| b >> 4) {
+        Some(4) => be16(ip, 6).is_some_and(|f| f & 0x3fff != 0),
+        Some(6) => ipv6_upper_layer(ip).is_some_and(|(next, _)| next == PROTO_IPV6_FRAGMENT),
+        _ => false,
+    }
+}
+
+/// The IP packet in a frame, if the link layer carries one.
+fn network_layer(link_type: u32, frame: &[u8]) -> Option<&[u8]> {
+    Some(match link_type {
+        LINKTYPE_ETHERNET => {
+            let mut ethertype = be16(frame, 12)?;
+            let mut at = 14;
+            while ETHERTYPE_VLAN.contains(&ethertype) {
+                ethertype = be16(frame, at + 2)?;
+                at += 4;
+            }
+            if ![ETHERTYPE_IPV4, ETHERTYPE_IPV6].contains(&ethertype) {
+                return None;
+            }
+            frame.get(at..)?
+        }
+        // The address family is in host byte order; the IP version says
+        // enough.
+        LINKTYPE_NULL | LINKTYPE_LOOP => frame.get(4..)?,
+        LINKTYPE_RAW | LINKTYPE_BSD_RAW | LINKTYPE_OPENBSD_RAW => frame,
+        LINKTYPE_LINUX_SLL => frame.get(16..)?,
+        LINKTYPE_LINUX_SLL2 => frame.get(20..)?,
+        _ => return None,
+    })
+}
+
+fn ipv4(data: &[u8]) -> Option<Decoded<'_>> {
+    let header_len = (data.first()? & 0x0f) as usize * 4;
+    let total_len = be16(data, 2)? as usize;
+    // More fragments, or not the first fragment.
+    if be16(data, 6)? & 0x3fff != 0 || header_len < 20 || total_len < header_len {
+        return None;
+    }
+    let src: [u8; 4] = data.get(12..16)?.try_into().ok()?;
+    let dst: [u8; 4] = data.get(16..20)?.try_into().ok()?;
+    let end = total_len.min(data.len());
+    transport(
+        data[9],
+        Ipv4Addr::from(src).into(),
+        Ipv4Addr::from(dst).into(),
+        data.get(header_len..end)?,
+        total_len - end,
+    )
+}
+
+fn ipv6(data: &[u8]) -> Option<Decoded<'_>> {
+    let payload_len = be16(data, 4)? as usize;
+    let src: [u8; 16] = data.get(8..24)?.try_into().ok()?;
+    let dst: [u8; 16] = data.get(24..40)?.try_into().ok()?;
+    let end = (40 + payload_len).min(data.len());
+    let (next, at) = ipv6_upper_layer(data)?;
+    transport(
+        next,
+        Ipv6Addr::from(src).into(),
+        Ipv6Addr::from(dst).into(),
+        data.get(at..end)?,
+        40 + payload_len - end,
+    )
+}
+
+/// Protocol and offset of the header after the IPv6 extension headers.
+fn ipv6_upper_layer(data: &[u8]) -> Option<(u8, usize)> {
+    let mut next = *data.get(6)?;
+    let mut at = 40;
+    loop {
+        let ext_len = match next {
+            // Hop-by-hop, routing and destination options.
+            0 | 43 | 60 => (*data.get(at + 1)? as usize + 1) * 8,
+            // Authentication header.
+            51 => (*data.get(at + 1)? as usize + 2) * 4,
+            _ => return Some((next, at)),
+        };
+        next = *data.get(at)?;
+        at += ext_len;
+    }
+}
+
+/// Decode the transport header in `data`, an IP payload with `missing`
+/// bytes cut off at the end.
+fn transport(
+    protocol: u8,
+    src: IpAddr,
+    dst: IpAddr,
+    data: &[u8],
+    missing: usize,
+) -> Option<Decoded<'_>> {
+    let src_port = be16(data, 0)?;
+    let dst_port = be16(data, 2)?;
+    let (transport, payload) = match protocol {
+        PROTO_TCP => {
+            let header_len = (data.get(12)? >> 4) as usize * 4;
+            let seq = u32::from_be_bytes(data.get(4..8)?.try_into().ok()?);
+            let flags = TcpFlags(*data.get(13)?);
+            if header_len < 20 {
+                return None;
+            }
+            (Transport::Tcp { seq, flags }, data.get(header_len..)?)
+        }
+        PROTO_UDP => {
+            let end = (be16(data, 4)? as usize).min(data.len()).max(8);
+            (Transport::Udp, data.get(8..end)?)
+        }
+        _ => return None,
+    };
+    Some(Decoded {
+        src: SocketAddr::new(src, src_port),
+        dst: SocketAddr::new(dst, dst_port),
+        transport,
+        payload,
+        missing,
+    })
+}
+
+fn be16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tcp(payload: &[u8]) -> Vec<u8> {
+        let mut t = vec![0x9c, 0x40, 0, 80]; // 40000 -> 80
+        t.extend(1000u32.to_be_bytes());
+        t.extend([0, 0, 0, 0, 0x50, 0x18, 0xff, 0xff, 0, 0, 0, 0]);
+        t.extend(payload);
+        t
+    }
+
+    fn ipv4(protocol: u8, body: &[u8]) -> Vec<u8> {
+        let mut ip = vec![0x45, 0];
+        ip.extend((20 + body.len() as u16).to_be_bytes());
+        ip.extend([0, 0, 0x40, 0, 64, protocol, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+        ip.extend(body);
+        ip
+    }
+
+    fn ethernet(ethertype: u16, body: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0; 12];
+        frame.extend(ethertype.to_be_bytes());
+        frame.extend(body);
+        frame
+    }
+
+    #[test]
+    fn ethernet_ipv4_tcp() {
+        let mut frame = ethernet(ETHERTYPE_IPV4, &ipv4(PROTO_TCP, &tcp(b"GET /")));
+        frame.extend([0; 6]); // Ethernet padding beyond the IP length
+        let d = decode(LINKTYPE_ETHERNET, &frame).unwrap();
+        assert_eq!(d.src, "10.0.0.1:40000".parse().unwrap());
+        assert_eq!(d.dst, "10.0.0.2:80".parse().unwrap());
+        assert_eq!(d.payload, b"GET /");
+        let segment = d.segment().unwrap();
+        assert_eq!(segment.seq, 1000);
+        assert!(segment.flags.contains(TcpFlags::ACK | TcpFlags::PSH));
+    }
+
+    #[test]
+    fn counts_payload_cut_off_by_the_capture() {
+        let frame = ethernet(ETHERTYPE_IPV4, &ipv4(PROTO_TCP, &tcp(b"GET / HTTP/1.1")));
+        let d = decode(LINKTYPE_ETHERNET, &frame[..frame.len() - 9]).unwrap();
+        assert_eq!((d.payload, d.missing), (&b"GET /"[..], 9));
+        assert_eq!(d.segment().unwrap().missing, 9);
+    }
+
+    #[test]
+    fn vlan_tagged_udp() {
+        let mut udp = vec![0, 53, 0x30, 0x39, 0, 12, 0, 0];
+        udp.extend(b"abcd");
+        let mut tagged = vec![0, 7];
+        tagged.extend(ETHERTYPE_IPV4.to_be_bytes());
+        tagged.extend(ipv4(PROTO_UDP, &udp));
+        let frame = ethernet(0x8100, &tagged);
+        let d = decode(LINKTYPE_ETHERNET, &frame).unwrap();
+        assert_eq!(d.transport, Transport::Udp);
+        assert_eq!(d.dst.port(), 12345);
+        assert_eq!(d.payload, b"abcd");
+        assert!(d.segment().is_none());
+    }
+
+    #[test]
+    fn ipv6_with_extension_header() {
+        let body = tcp(b"hi");
+        let mut ip = vec![0x60, 0, 0, 0];
+        ip.extend((8 + body.len() as u16).to_be_bytes());
+        ip.extend([0, 64]); // hop-by-hop options first
+        ip.extend(Ipv6Addr::LOCALHOST.octets());
+        ip.extend(Ipv6Addr::LOCALHOST.octets());
+        ip.extend([PROTO_TCP, 0, 0, 0, 0, 0, 0, 0]);
+        ip.extend(body);
+        let d = decode(LINKTYPE_RAW, &ip).unwrap();
+        assert_eq!(d.src, "[::1]:40000".parse().unwrap());
+        assert_eq!(d.payload, b"hi");
+    }
+
+    #[test]
+    fn skips_fragments_and_other_protocols() {
+        let mut fragment = ipv4(PROTO_TCP, &tcp(b"x"));
+        fragment[6] = 0x20; // more fragments
+        assert!(decode(LINKTYPE_RAW, &fragment).is_none());
+        assert!(is_fragment(LINKTYPE_RAW, &fragment));
+        let ping = ipv4(1, &[8, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(decode(LINKTYPE_RAW, &ping).is_none());
+        assert!(!is_fragment(LINKTYPE_RAW, &ping));
+        assert!(decode(LINKTYPE_ETHERNET, &ethernet(0x0806, &[0; 28])).is_none());
+        assert!(decode(LINKTYPE_ETHERNET, &[0; 10]).is_none());
+    }
+
+    #[test]
+    fn other_link_layers() {
+        let ip = ipv4(PROTO_TCP, &tcp(b"x"));
+        let mut null = vec![2, 0, 0, 0];
+        null.extend(&ip);
+        assert!(decode(LINKTYPE_NULL, &null).is_some());
+        let mut sll = vec![0; 14];
+        sll.extend(ETHERTYPE_IPV4.to_be_bytes());
+        sll.extend(&ip);
+        assert!(decode(LINKTYPE_LINUX_SLL, &sll).is_some());
+        for link_type in [LINKTYPE_BSD_RAW, LINKTYPE_OPENBSD_RAW] {
+            assert!(decode(link_type, &ip).is_some());
+        }
+    }
+}
@@ -1,8 +1,13 @@
 /// Simple byte-level mutation fuzzer for packet payloads.
 ///
-/// Applies random mutations to a seed payload so that downstream matchers
+/// Applies random mutations to a base payload so that downstream matchers
 /// can be stress-tested against malformed or adversarial input.
-use rand::Rng;
+///
+/// Every run is driven by a 64-bit PRNG seed, and every payload by a case
+/// index: `(seed, index)` regenerates the same payload on any platform.
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::ops::Range;
 
 /// Available mutation strategies.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -32,24 +37,40 @@ impl MutationStrategy {
     }
 }
 
-/// Payload fuzzer that mutates a seed byte vector.
+/// One generated payload and how it was made.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzCase {
+    /// Case index; `Fuzzer::case(index)` with the same seed regenerates it.
+    pub index: u64,
+    /// The base payload after `steps`, ready to send or match.
+    pub payload: Vec<u8>,
+    /// Mutations applied to the base payload, in order. A step taken while
+    /// the payload is empty is always `ByteInsert`, even if that strategy is
+    /// not enabled, since nothing else applies to an empty payload.
+    pub steps: Vec<MutationStrategy>,
+}
+
+/// Payload fuzzer that mutates a base byte vector.
 pub struct Fuzzer {
-    seed: Vec<u8>,
+    base: Vec<u8>,
     strategies: Vec<MutationStrategy>,
     mutations_per_round: usize,
+    rng_seed: u64,
 }
 
 impl Fuzzer {
-    /// Create a fuzzer from a seed payload.
-    pub fn new(seed: &[u8]) -> Self {
+    /// Create a fuzzer that mutates `base`, with a random PRNG seed.
+    pub fn new(base: &[u8]) -> Self {
         Self {
-            seed: seed.to_vec(),
+            base: base.to_vec(),
             strategies: MutationStrategy::all().to_vec(),
             mutations_per_round: 3,
+            rng_seed: rand::thread_rng().gen(),
         }
     }
 
-    /// Limit the strategies to use.
+    /// Limit the strategies to use. An empty payload still gets a
+    /// `ByteInsert`; see [`FuzzCase::steps`].
     pub fn with_strategies(mut self, strategies: &[MutationStrategy]) -> Self {
         self.strategies = strategies.to_vec();
         self
@@ -61,52 +82,94 @@ impl Fuzzer {
         self
     }
 
-    /// Generate `count` mutated payloads.
+    /// Use a fixed PRNG seed so runs can be reproduced.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng_seed = seed;
+        self
+    }
+
+    /// The PRNG seed; log it to reproduce a run started with `new`.
+    pub fn seed(&self) -> u64 {
+        self.rng_seed
+    }
+
+    /// Generate `count` mutated payloads (cases `0..count`).
     pub fn generate(&self, count: usize) -> Vec<Vec<u8>> {
-        let mut rng = rand::thread_rng();
-        (0..count).map(|_| self.mutate_once(&mut rng)).collect()
+        self.cases(0..count as u64)
+            .into_iter()
+            .map(|case| case.payload)
+            .collect()
     }
 
-    fn mutate_once(&self, rng: &mut impl Rng) -> Vec<u8> {
-        let mut data = self.seed.clone();
+    /// Generate the cases in `indices`, with their mutation steps.
+    pub fn cases(&self, indices: Range<u64>) -> Vec<FuzzCase> {
+        indices.map(|index| self.case(index)).collect()
+    }
+
+    /// Regenerate case `index` without generating the ones before it.
+    pub fn case(&self, index: u64) -> FuzzCase {
+        // Each case reads its own ChaCha stream, so cases are independent of
+        // each other and of how many were generated before.
+        let mut rng = ChaCha8Rng::seed_from_u64(self.rng_seed);
+        rng.set_stream(index);
+        let (payload, steps) = self.mutate_once(&mut rng);
+        FuzzCase {
+            index,
+            payload,
+            steps,
+        }
+    }
+
+    fn mutate_once(&self, rng: &mut impl Rng) -> (Vec<u8>, Vec<MutationStrategy>) {
+        let mut data = self.base.clone();
+        let mut steps = Vec::with_capacity(self.mutations_per_round);
         for _ in 0..self.mutations_per_round {
             if data.is_empty() {
-                // Can only insert into an empty payload
+                // Can only insert into an empty payload, whatever the
+                // enabled strategies; recorded so the steps stay truthful.
                 data.push(rng.gen());
+                steps.push(MutationStrategy::ByteInsert);
                 continue;
             }
-            let strategy = self.strategies[rng.gen_range(0..self.strategies.len())];
+            let strategy = self.strategies[index(rng, self.strategies.len())];
+            steps.push(strategy);
             match strategy {
                 MutationStrategy::BitFlip => {
-                    let idx = rng.gen_range(0..data.len());
+                    let idx = index(rng, data.len());
                     let bit = 1u8 << rng.gen_range(0..8u32);
                     data[idx] ^= bit;
                 }
                 MutationStrategy::ByteReplace => {
-                    let idx = rng.gen_range(0..data.len());
+                    let idx = index(rng, data.len());
                     data[idx] = rng.gen();
                 }
                 MutationStrategy::ByteInsert => {
-                    let idx = rng.gen_range(0..=data.len());
+                    let idx = index(rng, data.len() + 1);
                     data.insert(idx, rng.gen());
                 }
                 MutationStrategy::ByteDelete => {
-                    let idx = rng.gen_range(0..data.len());
+                    let idx = index(rng, data.len());
                     data.remove(idx);
                 }
                 MutationStrategy::ChunkShuffle => {
                     if data.len() >= 2 {
-                        let a = rng.gen_range(0..data.len());
-                        let b = rng.gen_range(0..data.len());
+                        let a = index(rng, data.len());
+                        let b = index(rng, data.len());
                         data.swap(a, b);
                     }
                 }
             }
         }
-        data
+        (data, steps)
     }
 }
 
+/// Random index below `len`. Sampled as `u64` because sampling `usize`
+/// draws a different amount of randomness on 32-bit targets.
+fn index(rng: &mut impl Rng, len: usize) -> usize {
+    rng.gen_range(0..len as u64) as usize
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -120,11 +183,11 @@ mod tests {
 
     #[test]
     fn mutations_change_payload() {
-        let seed = b"HELLO WORLD";
-        let f = Fuzzer::new(seed).with_mutations_per_round(5);
+        let base = b"HELLO WORLD";
+        let f = Fuzzer::new(base).with_mutations_per_round(5);
         let payloads = f.generate(20);
-        // At least some payloads should differ from the seed
-        let differs = payloads.iter().filter(|p| p.as_slice() != seed).count();
+        // At least some payloads should differ from the base
+        let differs = payloads.iter().filter(|p| p.as_slice() != base).count();
         assert!(differs > 0, "expected at least one mutated payload");
     }
 
@@ -141,10 +204,70 @@ mod tests {
     }
 
     #[test]
-    fn empty_seed() {
+    fn empty_base() {
         let f = Fuzzer::new(b"");
         let payloads = f.generate(5);
         // Should not panic; payloads will have some bytes from inserts
         assert_eq!(payloads.len(), 5);
     }
+
+    #[test]
+    fn same_seed_same_payloads() {
+        let f = Fuzzer::new(b"GET / HTTP/1.1").with_seed(42);
+        let g = Fuzzer::new(b"GET / HTTP/1.1").with_seed(42);
+        assert_eq!(f.seed(), 42);
+        assert_eq!(f.generate(50), g.generate(50));
+        assert_eq!(f.generate(50), f.generate(50));
+        assert_ne!(f.generate(50), f.with_seed(43).generate(50));
+    }
+
+    #[test]
+    fn case_regenerates_in_isolation() {
+        let f = Fuzzer::new(b"HELLO")
+            .with_seed(7)
+            .with_mutations_per_round(4);
+        let cases = f.cases(0..20);
+        assert_eq!(cases[13], f.case(13));
+        assert_eq!(f.cases(10..20), cases[10..]);
+        for (i, case) in cases.iter().enumerate() {
+            assert_eq!(case.index, i as u64);
+            assert_eq!(case.steps.len(), 4);
+        }
+    }
+
+    #[test]
+    fn records_steps() {
+        let f = Fuzzer::new(b"ABCDEFGH")
+            .with_seed(1)
+            .with_strategies(&[MutationStrategy::ByteDelete])
+            .with_mutations_per_round(3);
+        let case = f.case(0);
+        assert_eq!(case.steps, vec![MutationStrategy::ByteDelete; 3]);
+        assert_eq!(case.payload.len(), 5);
+
+        // An empty payload can only grow, whatever the enabled strategies.
+        let case = Fuzzer::new(b"")
+            .with_seed(1)
+            .with_strategies(&[MutationStrategy::ByteDelete])
+            .with_mutations_per_round(2)
+            .case(0);
+        assert_eq!(case.steps[0], MutationStrategy::ByteInsert);
+        assert_eq!(case.steps[1], MutationStrategy::ByteDelete);
+    }
+
+    #[test]
+    fn seeded_output_is_stable() {
+        // Pinned so a change to the PRNG or the sampling order is noticed:
+        // saved (seed, index) pairs must keep reproducing the same payload.
+        let case = Fuzzer::new(b"PAYLOAD").with_seed(0x5eed).case(3);
+        assert_eq!(case.payload, [80, 65, 89, 76, 30, 65, 139, 124]);
+        assert_eq!(
+            case.steps,
+            [
+                MutationStrategy::ByteInsert,
+                MutationStrategy::ByteReplace,
+                MutationStrategy::ByteReplace,
+            ]
+        );
+    }
 }
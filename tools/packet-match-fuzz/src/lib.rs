@@ -1,8 +1,14 @@
 //! KMP-based packet pattern matcher with streaming support and payload fuzzing.
 //!
+//! Payloads can come from byte slices or from pcap/pcapng captures via
+//! [`CaptureReader`] and [`decode`]; the `pmf` binary scans captures from the
+//! command line.
+//!
 //! # Example
 //! ```
-//! use packet_match_fuzz::{KmpMatcher, StreamMatcher};
+//! use packet_match_fuzz::{
+//!     KmpMatcher, MultiMatcher, MultiStreamMatcher, PatternMatcher, StreamMatcher,
+//! };
 //!
 //! let matcher = KmpMatcher::new(b"HTTP");
 //! assert_eq!(matcher.find_all(b"GET / HTTP/1.1\r\nHTTP"), vec![6, 16]);
@@ -10,12 +16,42 @@
 //! let mut stream = StreamMatcher::new(b"AB");
 //! assert_eq!(stream.feed(b"xxA"), vec![]);
 //! assert_eq!(stream.feed(b"Byy"), vec![2]); // global offset 2
+//!
+//! // Many patterns in one pass: (pattern_id, offset) pairs.
+//! let multi = MultiMatcher::new(["GET ", "HTTP/"]);
+//! assert_eq!(multi.find_all(b"GET / HTTP/1.1"), vec![(0, 0), (1, 6)]);
+//!
+//! let mut stream = MultiStreamMatcher::new(["GET ", "HTTP/"]);
+//! assert_eq!(stream.feed(b"GET / HT"), vec![(0, 0)]);
+//! assert_eq!(stream.feed(b"TP/1.1"), vec![(1, 6)]);
+//!
+//! // Wildcards, byte classes and bounded gaps.
+//! let sig = PatternMatcher::new(r#""GET " ??{1,16} " HTTP/1." [30 31]"#).unwrap();
+//! assert_eq!(sig.find_all(b"GET /index HTTP/1.1"), vec![0]);
 //! ```
 
+mod decode;
 mod fuzz;
 mod kmp;
+mod multi;
+mod pattern;
+mod pcap;
+mod reassembly;
+mod rules;
 mod stream;
 
-pub use fuzz::{Fuzzer, MutationStrategy};
+pub use decode::{
+    decode, is_fragment, Decoded, Transport, LINKTYPE_BSD_RAW, LINKTYPE_ETHERNET,
+    LINKTYPE_LINUX_SLL, LINKTYPE_LINUX_SLL2, LINKTYPE_LOOP, LINKTYPE_NULL, LINKTYPE_OPENBSD_RAW,
+    LINKTYPE_RAW,
+};
+pub use fuzz::{FuzzCase, Fuzzer, MutationStrategy};
 pub use kmp::KmpMatcher;
-pub use stream::StreamMatcher;
+pub use multi::{MultiMatcher, MultiStreamMatcher};
+pub use pattern::{PatternError, PatternMatcher, PatternStreamMatcher};
+pub use pcap::{CaptureError, CaptureReader, Packet};
+pub use reassembly::{
+    Direction, FlowKey, FlowMatch, OverlapPolicy, Reassembler, Segment, TcpFlags,
+};
+pub use rules::{parse_rules, Content, Rule, RuleError, RuleSet, RuleStreamMatcher};
+pub use stream::{ChunkMatcher, StreamMatcher};
@@ -0,0 +1,198 @@
+use clap::{Args, Parser, Subcommand};
+use packet_match_fuzz::{
+    decode, is_fragment, CaptureError, CaptureReader, ChunkMatcher, Direction, FlowMatch,
+    KmpMatcher, Reassembler, StreamMatcher, Transport,
+};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+#[derive(Parser, Debug)]
+#[command(name = "pmf")]
+#[command(about = "Match byte patterns against packet captures")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Scan a pcap or pcapng file for patterns in TCP and UDP payloads
+    Scan(ScanArgs),
+}
+
+#[derive(Args, Debug)]
+struct ScanArgs {
+    /// Capture file (pcap or pcapng)
+    capture: PathBuf,
+
+    /// Pattern to look for; may be repeated. Supports \xHH, \r, \n, \t and \\
+    #[arg(short, long = "pattern", required = true, value_parser = parse_pattern)]
+    patterns: Vec<Vec<u8>>,
+
+    /// Match each TCP payload on its own instead of reassembling streams
+    #[arg(long)]
+    per_packet: bool,
+}
+
+/// Every pattern's stream matcher, reporting `(pattern index, offset)`.
+struct Patterns(Vec<StreamMatcher>);
+
+impl ChunkMatcher for Patterns {
+    type Match = (usize, usize);
+
+    fn feed(&mut self, chunk: &[u8]) -> Vec<(usize, usize)> {
+        let mut found: Vec<_> = self
+            .0
+            .iter_mut()
+            .enumerate()
+            .flat_map(|(i, m)| m.feed(chunk).into_iter().map(move |at| (i, at)))
+            .collect();
+        found.sort_by_key(|&(i, at)| (at, i));
+        found
+    }
+
+    fn skip(&mut self, len: usize) {
+        for m in &mut self.0 {
+            m.skip(len);
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let Command::Scan(args) = cli.command;
+    match scan(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("pmf: {}: {e}", args.capture.display());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn scan(args: &ScanArgs) -> Result<(), CaptureError> {
+    let reader = CaptureReader::open(&args.capture)?;
+    let kmp: Vec<_> = args.patterns.iter().map(|p| KmpMatcher::new(p)).collect();
+    let mut reassembler = Reassembler::new(|| {
+        Patterns(
+            args.patterns
+                .iter()
+                .map(|p| StreamMatcher::new(p))
+                .collect(),
+        )
+    });
+    let (mut packets, mut matches) = (0u64, 0u64);
+    // Packets that didn't decode: fragments, frames cut short by the
+    // capture's snap length, and everything else.
+    let (mut fragments, mut truncated, mut other) = (0u64, 0u64, 0u64);
+
+    for packet in reader {
+        let packet = packet?;
+        packets += 1;
+        let Some(decoded) = decode(packet.link_type, &packet.data) else {
+            if is_fragment(packet.link_type, &packet.data) {
+                fragments += 1;
+            } else if (packet.data.len() as u64) < u64::from(packet.original_len) {
+                truncated += 1;
+            } else {
+                other += 1;
+            }
+            continue;
+        };
+        let label = format!("#{}", packet.number);
+        match decoded.segment() {
+            Some(segment) if !args.per_packet => {
+                for found in reassembler.process(&segment) {
+                    // Held-back data of a flow dropped to make room.
+                    let evicted = ![segment.src, segment.dst].contains(&found.flow.client)
+                        || ![segment.src, segment.dst].contains(&found.flow.server);
+                    let label = if evicted { "evicted" } else { &label };
+                    report_stream(label, &found, &args.patterns);
+                    matches += 1;
+                }
+            }
+            _ => {
+                let proto = match decoded.transport {
+                    Transport::Tcp { .. } => "tcp",
+                    Transport::Udp => "udp",
+                };
+                for (i, matcher) in kmp.iter().enumerate() {
+                    for at in matcher.find_all(decoded.payload) {
+                        println!(
+                            "{label} {proto} {} -> {} offset {at}: {}",
+                            decoded.src,
+                            decoded.dst,
+                            escape(&args.patterns[i])
+                        );
+                        matches += 1;
+                    }
+                }
+            }
+        }
+    }
+    // Data still held back behind gaps when the capture ends.
+    for found in reassembler.flush() {
+        report_stream("end", &found, &args.patterns);
+        matches += 1;
+    }
+
+    eprintln!(
+        "{packets} packets ({other} not TCP/UDP, {fragments} IP fragments, {truncated} truncated), {matches} match{}",
+        if matches == 1 { "" } else { "es" }
+    );
+    Ok(())
+}
+
+fn report_stream(label: &str, found: &FlowMatch<(usize, usize)>, patterns: &[Vec<u8>]) {
+    let (src, dst): (SocketAddr, SocketAddr) = match found.direction {
+        Direction::ToServer => (found.flow.client, found.flow.server),
+        Direction::ToClient => (found.flow.server, found.flow.client),
+    };
+    let (i, at) = found.matched;
+    println!(
+        "{label} tcp {src} -> {dst} stream offset {at}: {}",
+        escape(&patterns[i])
+    );
+}
+
+/// Parse a `--pattern` argument, expanding escapes.
+fn parse_pattern(s: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+        out.push(match bytes.next() {
+            Some(b'x') => {
+                let hex: Vec<u8> = bytes.by_ref().take(2).collect();
+                std::str::from_utf8(&hex)
+                    .ok()
+                    .filter(|h| h.len() == 2)
+                    .and_then(|h| u8::from_str_radix(h, 16).ok())
+                    .ok_or("\\x must be followed by two hex digits")?
+            }
+            Some(b'r') => b'\r',
+            Some(b'n') => b'\n',
+            Some(b't') => b'\t',
+            Some(b'\\') => b'\\',
+            Some(c) => return Err(format!("unknown escape '\\{}'", c as char)),
+            None => return Err("trailing backslash".into()),
+        });
+    }
+    if out.is_empty() {
+        return Err("pattern must not be empty".into());
+    }
+    Ok(out)
+}
+
+fn escape(pattern: &[u8]) -> String {
+    let escaped: String = pattern
+        .iter()
+        .flat_map(|&b| std::ascii::escape_default(b))
+        .map(char::from)
+        .collect();
+    format!("\"{escaped}\"")
+}
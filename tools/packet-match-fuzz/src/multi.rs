@@ -0,0 +1,312 @@
+//! Multi-pattern matching (Aho-Corasick).
+//!
+//! Generalises the KMP failure table to a trie of patterns: every state gets
+//! a failure link to the longest proper suffix that is also a trie prefix,
+//! and the links are folded into a full transition table, so each input byte
+//! costs one lookup no matter how many patterns are loaded.
+
+use crate::stream::ChunkMatcher;
+
+/// Root state of the automaton.
+pub(crate) const ROOT: usize = 0;
+
+/// Matcher for many byte patterns in a single pass.
+///
+/// Matches are `(pattern_id, offset)` pairs, where `pattern_id` is the
+/// pattern's index in the list passed to [`MultiMatcher::new`] and `offset`
+/// is where the match starts. They are reported in the order they end;
+/// matches ending at the same byte come longest pattern first.
+#[derive(Debug, Clone)]
+pub struct MultiMatcher {
+    patterns: Vec<Vec<u8>>,
+    /// `delta[state][byte]` is the next state.
+    delta: Vec<[usize; 256]>,
+    /// Ids of all patterns that end in each state, own pattern first, then
+    /// those reached through failure links.
+    outputs: Vec<Vec<usize>>,
+}
+
+impl MultiMatcher {
+    /// Build a matcher for `patterns`.
+    ///
+    /// # Panics
+    /// Panics if any pattern is empty.
+    pub fn new<I, P>(patterns: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<[u8]>,
+    {
+        let patterns: Vec<Vec<u8>> = patterns.into_iter().map(|p| p.as_ref().to_vec()).collect();
+        assert!(
+            patterns.iter().all(|p| !p.is_empty()),
+            "pattern must not be empty"
+        );
+
+        // Trie of all patterns; missing edges are `ROOT` for now.
+        let mut delta = vec![[ROOT; 256]];
+        let mut outputs = vec![Vec::new()];
+        for (id, pattern) in patterns.iter().enumerate() {
+            let mut state = ROOT;
+            for &byte in pattern {
+                let next = delta[state][byte as usize];
+                state = if next == ROOT {
+                    delta.push([ROOT; 256]);
+                    outputs.push(Vec::new());
+                    let new = delta.len() - 1;
+                    delta[state][byte as usize] = new;
+                    new
+                } else {
+                    next
+                };
+            }
+            outputs[state].push(id);
+        }
+
+        // Breadth-first, so a state's failure target is always finished
+        // before the state itself. Missing edges are borrowed from the
+        // failure target, which turns the trie into a DFA.
+        let mut failure = vec![ROOT; delta.len()];
+        let mut queue = std::collections::VecDeque::new();
+        queue.extend(delta[ROOT].iter().copied().filter(|&child| child != ROOT));
+        while let Some(state) = queue.pop_front() {
+            let inherited = outputs[failure[state]].clone();
+            outputs[state].extend(inherited);
+            let fallbacks = delta[failure[state]];
+            for (next, fallback) in delta[state].iter_mut().zip(fallbacks) {
+                // Children always have a higher index than their parent;
+                // a lower-or-equal one is a borrowed edge.
+                if *next > state {
+                    failure[*next] = fallback;
+                    queue.push_back(*next);
+                } else {
+                    *next = fallback;
+                }
+            }
+        }
+
+        Self {
+            patterns,
+            delta,
+            outputs,
+        }
+    }
+
+    /// Return every `(pattern_id, offset)` match in `text`.
+    pub fn find_all(&self, text: &[u8]) -> Vec<(usize, usize)> {
+        let mut matches = Vec::new();
+        self.scan(ROOT, 0, text, &mut matches);
+        matches
+    }
+
+    /// Return the first match to end in `text`, or `None`.
+    pub fn find_first(&self, text: &[u8]) -> Option<(usize, usize)> {
+        let mut state = ROOT;
+        for (i, &byte) in text.iter().enumerate() {
+            state = self.delta[state][byte as usize];
+            if let Some(&id) = self.outputs[state].first() {
+                return Some((id, i + 1 - self.patterns[id].len()));
+            }
+        }
+        None
+    }
+
+    /// Check whether `text` contains any of the patterns.
+    pub fn contains(&self, text: &[u8]) -> bool {
+        self.find_first(text).is_some()
+    }
+
+    /// Return the pattern with the given id.
+    pub fn pattern(&self, id: usize) -> &[u8] {
+        &self.patterns[id]
+    }
+
+    /// Number of patterns loaded.
+    pub fn pattern_count(&self) -> usize {
+        self.patterns.len()
+    }
+
+    /// Run the automaton over `text` from `state`, pushing matches with
+    /// offsets relative to `base`, and return the final state.
+    pub(crate) fn scan(
+        &self,
+        mut state: usize,
+        base: usize,
+        text: &[u8],
+        matches: &mut Vec<(usize, usize)>,
+    ) -> usize {
+        for (i, &byte) in text.iter().enumerate() {
+            state = self.delta[state][byte as usize];
+            for &id in &self.outputs[state] {
+                matches.push((id, base + i + 1 - self.patterns[id].len()));
+            }
+        }
+        state
+    }
+}
+
+/// Streaming counterpart of [`MultiMatcher`] that keeps its automaton state
+/// across successive `feed()` calls, like [`StreamMatcher`](crate::StreamMatcher)
+/// does for a single pattern.
+#[derive(Debug, Clone)]
+pub struct MultiStreamMatcher {
+    inner: MultiMatcher,
+    /// Automaton state carried over between feeds.
+    state: usize,
+    /// Total bytes fed so far (for global offset reporting).
+    global_offset: usize,
+}
+
+impl MultiStreamMatcher {
+    /// Create a new streaming matcher for `patterns`.
+    ///
+    /// # Panics
+    /// Panics if any pattern is empty.
+    pub fn new<I, P>(patterns: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<[u8]>,
+    {
+        Self::from_matcher(MultiMatcher::new(patterns))
+    }
+
+    /// Stream with an already built matcher.
+    pub fn from_matcher(inner: MultiMatcher) -> Self {
+        Self {
+            inner,
+            state: ROOT,
+            global_offset: 0,
+        }
+    }
+
+    /// Feed a chunk of data and return `(pattern_id, global_offset)` for
+    /// every match found, including matches that span several chunks.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<(usize, usize)> {
+        let mut matches = Vec::new();
+        self.state = self
+            .inner
+            .scan(self.state, self.global_offset, chunk, &mut matches);
+        self.global_offset += chunk.len();
+        matches
+    }
+
+    /// Reset the matcher state (but keep the same patterns).
+    pub fn reset(&mut self) {
+        self.state = ROOT;
+        self.global_offset = 0;
+    }
+
+    /// Total bytes processed so far.
+    pub fn bytes_processed(&self) -> usize {
+        self.global_offset
+    }
+
+    /// The underlying matcher.
+    pub fn matcher(&self) -> &MultiMatcher {
+        &self.inner
+    }
+}
+
+impl ChunkMatcher for MultiStreamMatcher {
+    type Match = (usize, usize);
+
+    fn feed(&mut self, chunk: &[u8]) -> Vec<(usize, usize)> {
+        MultiStreamMatcher::feed(self, chunk)
+    }
+
+    fn skip(&mut self, len: usize) {
+        self.state = ROOT;
+        self.global_offset += len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KmpMatcher;
+
+    #[test]
+    fn classic_example() {
+        let m = MultiMatcher::new(["he", "she", "his", "hers"]);
+        assert_eq!(
+            m.find_all(b"ushers"),
+            vec![(1, 1), (0, 2), (3, 2)] // "she", "he", "hers"
+        );
+    }
+
+    #[test]
+    fn overlapping_and_duplicate_patterns() {
+        let m = MultiMatcher::new([&b"AA"[..], b"A", b"AA"]);
+        assert_eq!(
+            m.find_all(b"AAA"),
+            vec![(1, 0), (0, 0), (2, 0), (1, 1), (0, 1), (2, 1), (1, 2)]
+        );
+    }
+
+    #[test]
+    fn agrees_with_kmp_per_pattern() {
+        let patterns: [&[u8]; 5] = [b"abab", b"bab", b"b", b"\x00\xff", b"abba"];
+        let text = b"ababbabab\x00\xffabbab\x00\x00\xff";
+        let m = MultiMatcher::new(patterns);
+        let found = m.find_all(text);
+        for (id, p) in patterns.iter().enumerate() {
+            let mut offsets: Vec<_> = found
+                .iter()
+                .filter(|(i, _)| *i == id)
+                .map(|&(_, o)| o)
+                .collect();
+            offsets.sort_unstable();
+            assert_eq!(offsets, KmpMatcher::new(p).find_all(text), "pattern {id}");
+        }
+    }
+
+    #[test]
+    fn find_first_and_contains() {
+        let m = MultiMatcher::new(["stack", "needle"]);
+        assert_eq!(m.find_first(b"hay needle stack"), Some((1, 4)));
+        assert!(!m.contains(b"hay needl"));
+        assert_eq!(m.pattern(0), b"stack");
+        assert_eq!(m.pattern_count(), 2);
+    }
+
+    #[test]
+    fn no_patterns_never_match() {
+        let m = MultiMatcher::new(Vec::<&[u8]>::new());
+        assert!(m.find_all(b"anything").is_empty());
+    }
+
+    #[test]
+    fn patterns_span_chunks() {
+        let mut sm = MultiStreamMatcher::new(["ABCD", "CDE", "D"]);
+        assert_eq!(sm.feed(b"xxAB"), vec![]);
+        assert_eq!(sm.feed(b"C"), vec![]);
+        assert_eq!(sm.feed(b"DEy"), vec![(0, 2), (2, 5), (1, 4)]);
+        assert_eq!(sm.bytes_processed(), 8);
+    }
+
+    #[test]
+    fn streaming_matches_one_shot() {
+        let patterns = ["GET ", "HTTP/1.", "\r\n\r\n", "Host:"];
+        let text = b"GET / HTTP/1.1\r\nHost: a\r\n\r\nGET /x HTTP/1.0\r\n\r\n";
+        let expected = MultiMatcher::new(patterns).find_all(text);
+        for size in 1..8 {
+            let mut sm = MultiStreamMatcher::new(patterns);
+            let found: Vec<_> = text.chunks(size).flat_map(|c| sm.feed(c)).collect();
+            assert_eq!(found, expected, "chunk size {size}");
+        }
+    }
+
+    #[test]
+    fn reset_clears_state() {
+        let mut sm = MultiStreamMatcher::new(["AB"]);
+        sm.feed(b"A");
+        sm.reset();
+        assert_eq!(sm.feed(b"Bxx"), vec![]);
+        assert_eq!(sm.bytes_processed(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "must not be empty")]
+    fn empty_pattern_panics() {
+        MultiMatcher::new(["ok", ""]);
+    }
+}
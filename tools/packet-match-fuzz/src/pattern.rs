@@ -0,0 +1,991 @@
+//! Byte pattern language for IDS-style signatures, compiled to a DFA.
+//!
+//! A pattern is a sequence of items, optionally separated by whitespace:
+//!
+//! | Syntax                                   | Matches                                       |
+//! |------------------------------------------|-----------------------------------------------|
+//! | `47`, `0x47`, `474554`                   | hex bytes                                     |
+//! | `"GET /"`                                | literal bytes (escapes `\" \\ \r \n \t \0 \xHH`) |
+//! | `??`                                     | any byte                                      |
+//! | `[0x0d 0x0a]`, `[30-39 "_-"]`, `[^ 00]`  | one byte from (or not from) a class           |
+//! | `( A \| B )`                             | either alternative                            |
+//! | `X{n}`, `X{n,m}`, `X{,m}`                | `X` repeated, e.g. `??{0,16}` for a gap       |
+//!
+//! Repetition must be bounded, so every match has a maximum length, and
+//! groups nest at most 64 deep.
+//!
+//! The pattern is compiled to an NFA, from which DFA states are built lazily
+//! as input is scanned and kept for later searches. Each DFA state remembers how many bytes ago every
+//! partial match started, so matches are reported by start offset just like
+//! [`KmpMatcher`](crate::KmpMatcher). For each start offset only the
+//! shortest match counts; it is reported as soon as it ends, so offsets of
+//! variable-length patterns come in the order their matches end.
+
+use crate::stream::ChunkMatcher;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// Largest count allowed in `{n,m}`.
+const MAX_REPEAT: usize = 1024;
+/// Deepest nesting of `( )` groups.
+const MAX_NESTING: usize = 64;
+/// Largest NFA a pattern may expand to.
+const MAX_INSTS: usize = 100_000;
+/// DFA states cached before the cache is flushed and rebuilt.
+const MAX_CACHED_STATES: usize = 4096;
+/// Not yet computed DFA transition.
+const UNKNOWN: usize = usize::MAX;
+
+/// Error compiling a pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternError {
+    /// Malformed pattern; `offset` is the byte position in the pattern text.
+    Syntax { offset: usize, message: String },
+    /// The pattern can match zero bytes.
+    MatchesEmpty,
+    /// Repetition expands the pattern beyond what we are willing to compile.
+    TooLarge,
+    /// Groups nest deeper than we are willing to parse; `offset` is the
+    /// `(` that went over the limit.
+    TooDeep { offset: usize },
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Syntax { offset, message } => write!(f, "{message} at offset {offset}"),
+            Self::MatchesEmpty => write!(f, "pattern can match zero bytes"),
+            Self::TooLarge => write!(f, "pattern expands to more than {MAX_INSTS} states"),
+            Self::TooDeep { offset } => {
+                write!(
+                    f,
+                    "groups nest more than {MAX_NESTING} deep at offset {offset}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+/// Set of byte values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct ByteSet([u64; 4]);
+
+impl ByteSet {
+    const EMPTY: Self = Self([0; 4]);
+    const ALL: Self = Self([u64::MAX; 4]);
+
+    fn single(b: u8) -> Self {
+        let mut s = Self::EMPTY;
+        s.insert(b);
+        s
+    }
+
+    fn insert(&mut self, b: u8) {
+        self.0[b as usize / 64] |= 1 << (b % 64);
+    }
+
+    fn contains(&self, b: u8) -> bool {
+        self.0[b as usize / 64] & (1 << (b % 64)) != 0
+    }
+
+    fn invert(&mut self) {
+        for w in &mut self.0 {
+            *w = !*w;
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0 == [0; 4]
+    }
+}
+
+/// Parsed pattern.
+#[derive(Debug, Clone)]
+enum Node {
+    Set(ByteSet),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat(Box<Node>, usize, usize),
+}
+
+impl Node {
+    fn min_len(&self) -> usize {
+        match self {
+            Self::Set(_) => 1,
+            Self::Concat(items) => items
+                .iter()
+                .fold(0, |len, item| len.saturating_add(item.min_len())),
+            Self::Alt(branches) => branches.iter().map(Self::min_len).min().unwrap_or(0),
+            Self::Repeat(node, min, _) => node.min_len().saturating_mul(*min),
+        }
+    }
+}
+
+/// Recursive-descent parser over the pattern text.
+struct Parser<'a> {
+    src: &'a [u8],
+    pos: usize,
+    /// Groups open at `pos`.
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error<T>(&self, offset: usize, message: impl Into<String>) -> Result<T, PatternError> {
+        Err(PatternError::Syntax {
+            offset,
+            message: message.into(),
+        })
+    }
+
+    /// Next non-whitespace byte, without consuming it.
+    fn peek(&mut self) -> Option<u8> {
+        while self.src.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+        self.src.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn parse(mut self) -> Result<Node, PatternError> {
+        let node = self.alt()?;
+        match self.peek() {
+            None => Ok(node),
+            Some(_) => self.error(self.pos, "unmatched ')'"),
+        }
+    }
+
+    fn alt(&mut self) -> Result<Node, PatternError> {
+        let mut branches = vec![self.seq()?];
+        while self.eat(b'|') {
+            branches.push(self.seq()?);
+        }
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            Node::Alt(branches)
+        })
+    }
+
+    fn seq(&mut self) -> Result<Node, PatternError> {
+        let mut items = Vec::new();
+        while !matches!(self.peek(), None | Some(b'|' | b')')) {
+            let atom = self.atom()?;
+            items.push(if self.peek() == Some(b'{') {
+                let (min, max) = self.bounds()?;
+                Node::Repeat(Box::new(atom), min, max)
+            } else {
+                atom
+            });
+        }
+        Ok(Node::Concat(items))
+    }
+
+    fn atom(&mut self) -> Result<Node, PatternError> {
+        let start = self.pos;
+        match self.src[start] {
+            b'"' => {
+                self.pos += 1;
+                let bytes = self.string()?;
+                if bytes.is_empty() {
+                    return self.error(start, "empty string");
+                }
+                Ok(Node::Concat(
+                    bytes
+                        .into_iter()
+                        .map(|b| Node::Set(ByteSet::single(b)))
+                        .collect(),
+                ))
+            }
+            b'[' => {
+                self.pos += 1;
+                self.class(start).map(Node::Set)
+            }
+            b'(' => {
+                if self.depth == MAX_NESTING {
+                    return Err(PatternError::TooDeep { offset: start });
+                }
+                self.pos += 1;
+                self.depth += 1;
+                let node = self.alt()?;
+                self.depth -= 1;
+                if !self.eat(b')') {
+                    return self.error(start, "unclosed '('");
+                }
+                Ok(node)
+            }
+            b'?' if self.src.get(start + 1) == Some(&b'?') => {
+                self.pos += 2;
+                Ok(Node::Set(ByteSet::ALL))
+            }
+            b'0' if self.src.get(start + 1) == Some(&b'x') => {
+                self.hex_byte().map(|b| Node::Set(ByteSet::single(b)))
+            }
+            c if c.is_ascii_hexdigit() => {
+                let len = self.src[start..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_hexdigit())
+                    .count();
+                if len % 2 != 0 {
+                    return self.error(start, "odd number of hex digits");
+                }
+                self.pos += len;
+                Ok(Node::Concat(
+                    self.src[start..start + len]
+                        .chunks(2)
+                        .map(|pair| Node::Set(ByteSet::single(hex_value(pair))))
+                        .collect(),
+                ))
+            }
+            c => self.error(start, format!("unexpected '{}'", c as char)),
+        }
+    }
+
+    /// One byte written as `HH` or `0xHH`.
+    fn hex_byte(&mut self) -> Result<u8, PatternError> {
+        self.peek();
+        let start = self.pos;
+        if self.src[start..].starts_with(b"0x") {
+            self.pos += 2;
+        }
+        match self.src.get(self.pos..self.pos + 2) {
+            Some(pair)
+                if pair.iter().all(u8::is_ascii_hexdigit)
+                    && !self
+                        .src
+                        .get(self.pos + 2)
+                        .is_some_and(u8::is_ascii_hexdigit) =>
+            {
+                self.pos += 2;
+                Ok(hex_value(pair))
+            }
+            _ => self.error(start, "expected a hex byte"),
+        }
+    }
+
+    /// Body of a quoted string, after the opening quote.
+    fn string(&mut self) -> Result<Vec<u8>, PatternError> {
+        let start = self.pos - 1;
+        let mut bytes = Vec::new();
+        loop {
+            let Some(&c) = self.src.get(self.pos) else {
+                return self.error(start, "unterminated string");
+            };
+            self.pos += 1;
+            match c {
+                b'"' => return Ok(bytes),
+                b'\\' => {
+                    let escape = self.pos - 1;
+                    let b = match self.src.get(self.pos) {
+                        Some(b'"') => b'"',
+                        Some(b'\\') => b'\\',
+                        Some(b'r') => b'\r',
+                        Some(b'n') => b'\n',
+                        Some(b't') => b'\t',
+                        Some(b'0') => 0,
+                        Some(b'x') => match self.src.get(self.pos + 1..self.pos + 3) {
+                            Some(pair) if pair.iter().all(u8::is_ascii_hexdigit) => {
+                                self.pos += 2;
+                                hex_value(pair)
+                            }
+                            _ => return self.error(escape, "invalid \\x escape"),
+                        },
+                        _ => return self.error(escape, "invalid escape"),
+                    };
+                    self.pos += 1;
+                    bytes.push(b);
+                }
+                _ => bytes.push(c),
+            }
+        }
+    }
+
+    /// Body of a byte class, after the opening bracket.
+    fn class(&mut self, start: usize) -> Result<ByteSet, PatternError> {
+        let negate = self.eat(b'^');
+        let mut set = ByteSet::EMPTY;
+        loop {
+            match self.peek() {
+                None => return self.error(start, "unclosed '['"),
+                Some(b']') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(b'"') => {
+                    self.pos += 1;
+                    for b in self.string()? {
+                        set.insert(b);
+                    }
+                }
+                Some(_) => {
+                    let lo = self.hex_byte()?;
+                    let hi = if self.eat(b'-') { self.hex_byte()? } else { lo };
+                    if hi < lo {
+                        return self.error(self.pos, "class range is reversed");
+                    }
+                    for b in lo..=hi {
+                        set.insert(b);
+                    }
+                }
+            }
+        }
+        if negate {
+            set.invert();
+        }
+        if set.is_empty() {
+            return self.error(start, "class matches no bytes");
+        }
+        Ok(set)
+    }
+
+    /// `{n}`, `{n,m}` or `{,m}`.
+    fn bounds(&mut self) -> Result<(usize, usize), PatternError> {
+        let start = self.pos;
+        self.pos += 1;
+        let min = self.number()?;
+        let max = if self.eat(b',') {
+            match self.number()? {
+                Some(max) => max,
+                None => return self.error(start, "unbounded repetition is not supported"),
+            }
+        } else {
+            match min {
+                Some(n) => n,
+                None => return self.error(start, "expected a repetition count"),
+            }
+        };
+        let min = min.unwrap_or(0);
+        if !self.eat(b'}') {
+            return self.error(start, "unclosed '{'");
+        }
+        if max < min || max == 0 {
+            return self.error(start, format!("invalid repetition {{{min},{max}}}"));
+        }
+        if max > MAX_REPEAT {
+            return self.error(start, format!("repetition above {MAX_REPEAT}"));
+        }
+        Ok((min, max))
+    }
+
+    fn number(&mut self) -> Result<Option<usize>, PatternError> {
+        self.peek();
+        let start = self.pos;
+        let len = self.src[start..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        if len == 0 {
+            return Ok(None);
+        }
+        self.pos += len;
+        // Digits only, so this fails just on overflow.
+        match std::str::from_utf8(&self.src[start..self.pos])
+            .unwrap()
+            .parse()
+        {
+            Ok(n) => Ok(Some(n)),
+            Err(_) => self.error(start, format!("repetition above {MAX_REPEAT}")),
+        }
+    }
+}
+
+/// Value of two ASCII hex digits.
+fn hex_value(pair: &[u8]) -> u8 {
+    let digit = |c: u8| (c as char).to_digit(16).unwrap() as u8;
+    digit(pair[0]) << 4 | digit(pair[1])
+}
+
+/// NFA instruction. Every edge points to a lower index, so the NFA is a DAG.
+#[derive(Debug, Clone)]
+enum Inst {
+    Match,
+    Byte(ByteSet, usize),
+    Split(Vec<usize>),
+}
+
+/// Index of `Inst::Match`.
+const MATCH: usize = 0;
+
+struct Compiler {
+    insts: Vec<Inst>,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> Result<usize, PatternError> {
+        if self.insts.len() >= MAX_INSTS {
+            return Err(PatternError::TooLarge);
+        }
+        self.insts.push(inst);
+        Ok(self.insts.len() - 1)
+    }
+
+    /// Compile `node` so that it continues at `next`; returns its entry.
+    fn compile(&mut self, node: &Node, next: usize) -> Result<usize, PatternError> {
+        match node {
+            Node::Set(set) => self.push(Inst::Byte(*set, next)),
+            Node::Concat(items) => items
+                .iter()
+                .rev()
+                .try_fold(next, |next, item| self.compile(item, next)),
+            Node::Alt(branches) => {
+                let entries = branches
+                    .iter()
+                    .map(|b| self.compile(b, next))
+                    .collect::<Result<_, _>>()?;
+                self.push(Inst::Split(entries))
+            }
+            Node::Repeat(node, min, max) => {
+                // Optional copies nest as (X (X ...)?)?, then the mandatory ones.
+                let mut entry = next;
+                for _ in *min..*max {
+                    let body = self.compile(node, entry)?;
+                    entry = self.push(Inst::Split(vec![body, next]))?;
+                }
+                for _ in 0..*min {
+                    entry = self.compile(node, entry)?;
+                }
+                Ok(entry)
+            }
+        }
+    }
+}
+
+/// Lazily built DFA. A state is the set of live NFA threads, each a byte
+/// instruction plus the number of bytes since the thread's match started,
+/// and the lengths of the matches that ended on entering the state.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct StateKey {
+    threads: Vec<(usize, usize)>,
+    matched: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct Dfa {
+    /// State keys by id; `ids` shares them rather than holding copies.
+    keys: Vec<Arc<StateKey>>,
+    ids: HashMap<Arc<StateKey>, usize>,
+    /// `delta[state * classes + class]`, or `UNKNOWN`.
+    delta: Vec<usize>,
+    classes: usize,
+    limit: usize,
+}
+
+impl Dfa {
+    /// Cache with only the start state (no threads), which is state 0.
+    fn new(classes: usize, limit: usize) -> Self {
+        let mut dfa = Self {
+            keys: Vec::new(),
+            ids: HashMap::new(),
+            delta: Vec::new(),
+            classes,
+            limit,
+        };
+        dfa.clear();
+        dfa
+    }
+
+    fn clear(&mut self) {
+        self.keys.clear();
+        self.ids.clear();
+        self.delta.clear();
+        self.intern(StateKey {
+            threads: Vec::new(),
+            matched: Vec::new(),
+        });
+    }
+
+    fn intern(&mut self, key: StateKey) -> usize {
+        if let Some(&id) = self.ids.get(&key) {
+            return id;
+        }
+        self.add(Arc::new(key))
+    }
+
+    /// Add a state that is not cached yet.
+    fn add(&mut self, key: Arc<StateKey>) -> usize {
+        let id = self.keys.len();
+        self.keys.push(Arc::clone(&key));
+        self.ids.insert(key, id);
+        self.delta
+            .extend(std::iter::repeat_n(UNKNOWN, self.classes));
+        id
+    }
+
+    /// State after `byte`. Flushing the cache renumbers states, so only the
+    /// returned id stays valid.
+    fn next(&mut self, m: &PatternMatcher, mut state: usize, byte: u8) -> usize {
+        let (classes, class) = (self.classes, m.classes[byte as usize] as usize);
+        let slot = move |state| state * classes + class;
+        let cached = self.delta[slot(state)];
+        if cached != UNKNOWN {
+            return cached;
+        }
+        let key = m.step(&self.keys[state], byte);
+        if self.keys.len() >= self.limit {
+            let from = Arc::clone(&self.keys[state]);
+            self.clear();
+            state = match self.ids.get(&from) {
+                Some(&id) => id,
+                None => self.add(from),
+            };
+        }
+        let target = self.intern(key);
+        let slot = slot(state);
+        self.delta[slot] = target;
+        target
+    }
+}
+
+/// Compiled byte pattern.
+///
+/// Searches share one DFA cache behind a lock, so concurrent searches on
+/// the same matcher take turns; clone it to search in parallel.
+///
+/// # Example
+/// ```
+/// use packet_match_fuzz::PatternMatcher;
+///
+/// let m = PatternMatcher::new(r#""GET " ?? ?? "/admin" [0x0d 0x0a]{2}"#).unwrap();
+/// assert_eq!(m.find_all(b"xxGET ab/admin\r\n"), vec![2]);
+/// ```
+#[derive(Debug)]
+pub struct PatternMatcher {
+    pattern: String,
+    insts: Vec<Inst>,
+    /// Byte and match instructions reachable from each instruction without
+    /// consuming input.
+    closures: Vec<Vec<usize>>,
+    /// Byte instructions a new match starts in.
+    start: Vec<usize>,
+    /// Bytes no instruction tells apart share a class.
+    classes: [u8; 256],
+    class_count: usize,
+    /// DFA states built by earlier searches, so later ones start warm.
+    cache: Mutex<Dfa>,
+}
+
+impl Clone for PatternMatcher {
+    fn clone(&self) -> Self {
+        Self {
+            pattern: self.pattern.clone(),
+            insts: self.insts.clone(),
+            closures: self.closures.clone(),
+            start: self.start.clone(),
+            classes: self.classes,
+            class_count: self.class_count,
+            cache: Mutex::new(self.cache.lock().unwrap().clone()),
+        }
+    }
+}
+
+impl PatternMatcher {
+    /// Compile `pattern`.
+    pub fn new(pattern: &str) -> Result<Self, PatternError> {
+        let node = Parser {
+            src: pattern.as_bytes(),
+            pos: 0,
+            depth: 0,
+        }
+        .parse()?;
+        if node.min_len() == 0 {
+            return Err(PatternError::MatchesEmpty);
+        }
+
+        let mut compiler = Compiler {
+            insts: vec![Inst::Match],
+        };
+        let entry = compiler.compile(&node, MATCH)?;
+        let insts = compiler.insts;
+
+        let mut closures: Vec<Vec<usize>> = Vec::with_capacity(insts.len());
+        for (i, inst) in insts.iter().enumerate() {
+            let closure = match inst {
+                Inst::Split(targets) => {
+                    let mut c: Vec<usize> =
+                        targets.iter().flat_map(|&t| closures[t].clone()).collect();
+                    c.sort_unstable();
+                    c.dedup();
+                    c
+                }
+                _ => vec![i],
+            };
+            closures.push(closure);
+        }
+        let start = closures[entry].clone();
+
+        let mut sets: Vec<ByteSet> = insts
+            .iter()
+            .filter_map(|inst| match inst {
+                Inst::Byte(set, _) => Some(*set),
+                _ => None,
+            })
+            .collect();
+        sets.sort_unstable_by_key(|s| s.0);
+        sets.dedup();
+        let mut classes = [0u8; 256];
+        let mut signatures: HashMap<Vec<bool>, u8> = HashMap::new();
+        for b in 0..=255u8 {
+            let signature: Vec<bool> = sets.iter().map(|s| s.contains(b)).collect();
+            let next = signatures.len() as u8;
+            classes[b as usize] = *signatures.entry(signature).or_insert(next);
+        }
+
+        Ok(Self {
+            pattern: pattern.to_string(),
+            insts,
+            closures,
+            start,
+            classes,
+            class_count: signatures.len(),
+            cache: Mutex::new(Dfa::new(signatures.len(), MAX_CACHED_STATES)),
+        })
+    }
+
+    /// Return all starting indices where the pattern matches in `text`.
+    pub fn find_all(&self, text: &[u8]) -> Vec<usize> {
+        let mut matches = Vec::new();
+        self.scan(&mut self.cache.lock().unwrap(), 0, 0, text, &mut matches);
+        matches
+    }
+
+    /// Return the start of the first match to end, or `None`.
+    pub fn find_first(&self, text: &[u8]) -> Option<usize> {
+        let mut dfa = self.cache.lock().unwrap();
+        let mut state = 0;
+        for (i, &byte) in text.iter().enumerate() {
+            state = dfa.next(self, state, byte);
+            if let Some(&len) = dfa.keys[state].matched.first() {
+                return Some(i + 1 - len);
+            }
+        }
+        None
+    }
+
+    /// Check whether `text` contains a match.
+    pub fn contains(&self, text: &[u8]) -> bool {
+        self.find_first(text).is_some()
+    }
+
+    /// Return the pattern text.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// A copy of the states built so far, for a stream of its own.
+    fn dfa(&self) -> Dfa {
+        self.cache.lock().unwrap().clone()
+    }
+
+    /// Run `text` through `dfa` from `state`, pushing match starts relative
+    /// to `base`, and return the final state.
+    fn scan(
+        &self,
+        dfa: &mut Dfa,
+        mut state: usize,
+        base: usize,
+        text: &[u8],
+        matches: &mut Vec<usize>,
+    ) -> usize {
+        for (i, &byte) in text.iter().enumerate() {
+            state = dfa.next(self, state, byte);
+            for &len in &dfa.keys[state].matched {
+                matches.push(base + i + 1 - len);
+            }
+        }
+        state
+    }
+
+    /// DFA transition: advance every thread, start a new one at this byte,
+    /// and retire the starts whose shortest match just ended.
+    fn step(&self, key: &StateKey, byte: u8) -> StateKey {
+        let mut threads = Vec::new();
+        let mut matched = Vec::new();
+        let fresh = self.start.iter().map(|&inst| (inst, 0));
+        for (inst, age) in key.threads.iter().copied().chain(fresh) {
+            let Inst::Byte(set, next) = &self.insts[inst] else {
+                continue;
+            };
+            if !set.contains(byte) {
+                continue;
+            }
+            for &target in &self.closures[*next] {
+                if target == MATCH {
+                    matched.push(age + 1);
+                } else {
+                    threads.push((target, age + 1));
+                }
+            }
+        }
+        // Longest first, so starts are reported in ascending order.
+        matched.sort_unstable_by(|a, b| b.cmp(a));
+        matched.dedup();
+        threads.retain(|(_, age)| !matched.contains(age));
+        threads.sort_unstable();
+        threads.dedup();
+        StateKey { threads, matched }
+    }
+}
+
+impl FromStr for PatternMatcher {
+    type Err = PatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+/// Streaming counterpart of [`PatternMatcher`] that keeps its DFA state
+/// across successive `feed()` calls, like
+/// [`StreamMatcher`](crate::StreamMatcher) does for a literal pattern.
+#[derive(Debug, Clone)]
+pub struct PatternStreamMatcher {
+    inner: PatternMatcher,
+    dfa: Dfa,
+    /// DFA state carried over between feeds.
+    state: usize,
+    /// Total bytes fed so far (for global offset reporting).
+    global_offset: usize,
+}
+
+impl PatternStreamMatcher {
+    /// Compile `pattern` for streaming.
+    pub fn new(pattern: &str) -> Result<Self, PatternError> {
+        PatternMatcher::new(pattern).map(Self::from_matcher)
+    }
+
+    /// Stream with an already compiled matcher.
+    pub fn from_matcher(inner: PatternMatcher) -> Self {
+        Self {
+            dfa: inner.dfa(),
+            inner,
+            state: 0,
+            global_offset: 0,
+        }
+    }
+
+    /// Feed a chunk of data and return **global** byte offsets of every match
+    /// start found (including matches that span several chunks).
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<usize> {
+        let mut matches = Vec::new();
+        self.state = self.inner.scan(
+            &mut self.dfa,
+            self.state,
+            self.global_offset,
+            chunk,
+            &mut matches,
+        );
+        self.global_offset += chunk.len();
+        matches
+    }
+
+    /// Reset the matcher state (but keep the pattern and the DFA states
+    /// built so far).
+    pub fn reset(&mut self) {
+        self.state = 0;
+        self.global_offset = 0;
+    }
+
+    /// Total bytes processed so far.
+    pub fn bytes_processed(&self) -> usize {
+        self.global_offset
+    }
+
+    /// The underlying matcher.
+    pub fn matcher(&self) -> &PatternMatcher {
+        &self.inner
+    }
+}
+
+impl ChunkMatcher for PatternStreamMatcher {
+    type Match = usize;
+
+    fn feed(&mut self, chunk: &[u8]) -> Vec<usize> {
+        PatternStreamMatcher::feed(self, chunk)
+    }
+
+    fn skip(&mut self, len: usize) {
+        self.state = 0;
+        self.global_offset += len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KmpMatcher;
+
+    fn find(pattern: &str, text: &[u8]) -> Vec<usize> {
+        PatternMatcher::new(pattern).unwrap().find_all(text)
+    }
+
+    fn syntax_error(pattern: &str) -> (usize, String) {
+        match PatternMatcher::new(pattern) {
+            Err(PatternError::Syntax { offset, message }) => (offset, message),
+            other => panic!("{pattern}: expected a syntax error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn hex_and_strings() {
+        assert_eq!(find("47 45 54", b"xGETGET"), vec![1, 4]);
+        assert_eq!(find("0x47 0x45", b"GE"), vec![0]);
+        assert_eq!(find("474554", b"GET"), vec![0]);
+        assert_eq!(find(r#""A\x00\r\n\"""#, b"A\0\r\n\"A"), vec![0]);
+        assert_eq!(find(r#""GET" 20 "/""#, b"GET /"), vec![0]);
+    }
+
+    #[test]
+    fn wildcards_and_classes() {
+        let m = PatternMatcher::new(r#""GET " ?? ?? "/admin" [0x0d 0x0a]{2}"#).unwrap();
+        assert_eq!(m.find_all(b"GET ab/admin\r\n"), vec![0]);
+        assert_eq!(m.find_all(b"GET ab/admin\n\n GET xyz/admin\r\n"), vec![0]);
+        assert_eq!(find("[30-39]{3}", b"a1234"), vec![1, 2]);
+        assert_eq!(find(r#"[^ "a" 0x62]"#, b"abc"), vec![2]);
+        assert_eq!(find(r#"["xy"] 00"#, b"y\0z\0x\0"), vec![0, 4]);
+    }
+
+    #[test]
+    fn overlapping_matches_like_kmp() {
+        assert_eq!(
+            find(r#""AA""#, b"AAAA"),
+            KmpMatcher::new(b"AA").find_all(b"AAAA")
+        );
+    }
+
+    #[test]
+    fn bounded_gaps_report_shortest_match_per_start() {
+        // Start 0 ends at the first "B"; the later "B" doesn't add a match.
+        assert_eq!(find(r#""A" ??{0,3} "B""#, b"AxBB"), vec![0]);
+        assert_eq!(find(r#""A" ??{0,3} "B""#, b"AxxxxB"), vec![]);
+        // Starts are reported when their match ends.
+        assert_eq!(find(r#""A" ??{,3} "B" | "C""#, b"AxCB"), vec![2, 0]);
+    }
+
+    #[test]
+    fn alternation_and_groups() {
+        let m = PatternMatcher::new(r#"("GET" | "POST") 20 ("/admin" | "/login"){1,2}"#).unwrap();
+        assert_eq!(
+            m.find_all(b"POST /login GET /x GET /admin/admin"),
+            vec![0, 19]
+        );
+        assert!(m.contains(b"GET /login"));
+        assert_eq!(m.find_first(b"no GET /admin"), Some(3));
+    }
+
+    #[test]
+    fn streaming_matches_one_shot() {
+        let pattern = r#""GET " ??{1,8} " HTTP/1." [30 31] 0d 0a"#;
+        let text = b"GET / HTTP/1.1\r\nGET /index.html HTTP/1.0\r\nGET /a HTTP/1.1\r\n";
+        let expected = find(pattern, text);
+        assert_eq!(expected, vec![0, 42]);
+        for size in 1..10 {
+            let mut sm = PatternStreamMatcher::new(pattern).unwrap();
+            let found: Vec<_> = text.chunks(size).flat_map(|c| sm.feed(c)).collect();
+            assert_eq!(found, expected, "chunk size {size}");
+        }
+    }
+
+    #[test]
+    fn flushing_the_dfa_cache_keeps_results() {
+        let m = PatternMatcher::new(r#""ab" ??{0,4} "c""#).unwrap();
+        let text = b"abxxcababcxabxabc abababababcc";
+        let mut dfa = Dfa::new(m.class_count, 3);
+        let mut found = Vec::new();
+        m.scan(&mut dfa, 0, 0, text, &mut found);
+        assert_eq!(found, m.find_all(text));
+        assert!(dfa.keys.len() <= 3);
+    }
+
+    #[test]
+    fn searches_reuse_the_dfa_states_built_before() {
+        let m = PatternMatcher::new(r#""ab" ??{0,4} "c""#).unwrap();
+        let text = b"abxxcababcxabxabc";
+        let expected = m.find_all(text);
+        let built = m.cache.lock().unwrap().keys.len();
+        assert!(built > 1);
+        assert_eq!(m.find_all(text), expected);
+        assert_eq!(m.cache.lock().unwrap().keys.len(), built);
+        assert_eq!(m.clone().find_all(text), expected);
+    }
+
+    #[test]
+    fn reset_clears_state() {
+        let mut sm = PatternStreamMatcher::new("41 42").unwrap();
+        assert_eq!(sm.feed(b"xA"), Vec::<usize>::new());
+        sm.reset();
+        assert_eq!(sm.feed(b"Bxx"), Vec::<usize>::new());
+        assert_eq!(sm.feed(b"AB"), vec![3]);
+    }
+
+    #[test]
+    fn syntax_errors_point_at_the_problem() {
+        assert_eq!(syntax_error("41 4"), (3, "odd number of hex digits".into()));
+        assert_eq!(
+            syntax_error(r#"41 "abc"#),
+            (3, "unterminated string".into())
+        );
+        assert_eq!(syntax_error("41 [42"), (3, "unclosed '['".into()));
+        assert_eq!(syntax_error("?? ?"), (3, "unexpected '?'".into()));
+        assert_eq!(syntax_error("41 zz"), (3, "unexpected 'z'".into()));
+        assert_eq!(syntax_error("41 )"), (3, "unmatched ')'".into()));
+        assert_eq!(syntax_error("(41"), (0, "unclosed '('".into()));
+        assert_eq!(
+            syntax_error("??{2,}"),
+            (2, "unbounded repetition is not supported".into())
+        );
+        assert_eq!(
+            syntax_error("41{3,2}"),
+            (2, "invalid repetition {3,2}".into())
+        );
+        assert_eq!(
+            syntax_error("[^ 00-ff]"),
+            (0, "class matches no bytes".into())
+        );
+        assert_eq!(syntax_error("[42-41]").1, "class range is reversed");
+        assert_eq!(syntax_error(r#""\q""#), (1, "invalid escape".into()));
+    }
+
+    #[test]
+    fn rejects_empty_and_oversized_patterns() {
+        assert_eq!(
+            PatternMatcher::new("").unwrap_err(),
+            PatternError::MatchesEmpty
+        );
+        assert_eq!(
+            PatternMatcher::new("41{0,3}").unwrap_err(),
+            PatternError::MatchesEmpty
+        );
+        assert_eq!(
+            PatternMatcher::new("(??{1000}){1000}").unwrap_err(),
+            PatternError::TooLarge
+        );
+        // The minimum length alone overflows usize.
+        let huge = "(".repeat(8) + "??" + &"){1024}".repeat(8);
+        assert_eq!(
+            PatternMatcher::new(&huge).unwrap_err(),
+            PatternError::TooLarge
+        );
+    }
+
+    #[test]
+    fn rejects_deeply_nested_groups() {
+        let nested = |depth: usize| "(".repeat(depth) + "41" + &")".repeat(depth);
+        assert_eq!(find(&nested(MAX_NESTING), b"xA"), vec![1]);
+        assert_eq!(
+            PatternMatcher::new(&nested(100_000)).unwrap_err(),
+            PatternError::TooDeep {
+                offset: MAX_NESTING
+            }
+        );
+    }
+}
@@ -0,0 +1,565 @@
+//! Pure-Rust reader for classic pcap and pcapng capture files.
+//!
+//! The format is detected from the first four bytes. Classic pcap files may
+//! be in either byte order, with microsecond or nanosecond timestamps.
+//! pcapng files may hold several sections and interfaces (each with its own
+//! link type and timestamp resolution); enhanced and simple packet blocks
+//! are read, all other blocks are skipped.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::time::Duration;
+
+const PCAP_MICROS: u32 = 0xa1b2_c3d4;
+const PCAP_NANOS: u32 = 0xa1b2_3c4d;
+const PCAPNG_SECTION: u32 = 0x0a0d_0d0a;
+const PCAPNG_BYTE_ORDER: u32 = 0x1a2b_3c4d;
+
+const BLOCK_INTERFACE: u32 = 1;
+const BLOCK_SIMPLE_PACKET: u32 = 3;
+const BLOCK_ENHANCED_PACKET: u32 = 6;
+const OPTION_TSRESOL: u16 = 9;
+
+/// Largest block or record we are willing to allocate for.
+const MAX_RECORD: usize = 64 << 20;
+
+/// Error reading a capture file.
+#[derive(Debug)]
+pub enum CaptureError {
+    Io(io::Error),
+    /// The file doesn't start with a pcap or pcapng magic number.
+    UnknownFormat(u32),
+    /// A header, record or block is cut off or inconsistent.
+    Malformed(String),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::UnknownFormat(magic) => {
+                write!(f, "not a pcap or pcapng file (magic {magic:#010x})")
+            }
+            Self::Malformed(what) => write!(f, "malformed capture: {what}"),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CaptureError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// One captured frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    /// Position in the file, starting at 1.
+    pub number: u64,
+    /// Capture time since the Unix epoch.
+    pub timestamp: Duration,
+    /// Link-layer header type, e.g. 1 for Ethernet.
+    pub link_type: u32,
+    /// Length on the wire; `data` is shorter if the capture was cut off.
+    pub original_len: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Interface {
+    link_type: u32,
+    /// Timestamp units per second.
+    ticks_per_sec: u64,
+}
+
+#[derive(Debug)]
+enum Format {
+    Pcap {
+        big_endian: bool,
+        ticks_per_sec: u64,
+        link_type: u32,
+    },
+    PcapNg {
+        big_endian: bool,
+        interfaces: Vec<Interface>,
+    },
+}
+
+/// Reads packets from a pcap or pcapng stream.
+///
+/// # Example
+/// ```no_run
+/// use packet_match_fuzz::{decode, CaptureReader};
+///
+/// for packet in CaptureReader::open("capture.pcapng").unwrap() {
+///     let packet = packet.unwrap();
+///     if let Some(p) = decode(packet.link_type, &packet.data) {
+///         println!("#{} {} -> {}: {} bytes", packet.number, p.src, p.dst, p.payload.len());
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct CaptureReader<R> {
+    reader: R,
+    format: Format,
+    packets: u64,
+}
+
+impl CaptureReader<BufReader<File>> {
+    /// Open a capture file.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, CaptureError> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Read the file header from `reader`.
+    pub fn new(mut reader: R) -> Result<Self, CaptureError> {
+        let mut magic = [0u8; 4];
+        reader
+            .read_exact(&mut magic)
+            .map_err(truncated("file header"))?;
+        let format = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+            (PCAPNG_SECTION, _) => {
+                let big_endian = read_section_header(&mut reader)?;
+                Format::PcapNg {
+                    big_endian,
+                    interfaces: Vec::new(),
+                }
+            }
+            (le, be)
+                if [PCAP_MICROS, PCAP_NANOS].contains(&le)
+                    || [PCAP_MICROS, PCAP_NANOS].contains(&be) =>
+            {
+                let big_endian = [PCAP_MICROS, PCAP_NANOS].contains(&be);
+                let nanos = (if big_endian { be } else { le }) == PCAP_NANOS;
+                let mut header = [0u8; 20];
+                reader
+                    .read_exact(&mut header)
+                    .map_err(truncated("file header"))?;
+                Format::Pcap {
+                    big_endian,
+                    ticks_per_sec: if nanos { 1_000_000_000 } else { 1_000_000 },
+                    link_type: u32_at(&header, 16, big_endian),
+                }
+            }
+            (le, _) => return Err(CaptureError::UnknownFormat(le)),
+        };
+        Ok(Self {
+            reader,
+            format,
+            packets: 0,
+        })
+    }
+
+    /// Read the next packet, or `None` at the end of the file.
+    pub fn next_packet(&mut self) -> Result<Option<Packet>, CaptureError> {
+        let packet = match self.format {
+            Format::Pcap {
+                big_endian,
+                ticks_per_sec,
+                link_type,
+            } => self.next_record(big_endian, ticks_per_sec, link_type)?,
+            Format::PcapNg { .. } => self.next_block()?,
+        };
+        Ok(packet.map(|mut packet| {
+            self.packets += 1;
+            packet.number = self.packets;
+            packet
+        }))
+    }
+
+    fn next_record(
+        &mut self,
+        big_endian: bool,
+        ticks_per_sec: u64,
+        link_type: u32,
+    ) -> Result<Option<Packet>, CaptureError> {
+        let mut header = [0u8; 16];
+        if !read_or_eof(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+        let secs = u32_at(&header, 0, big_endian) as u64;
+        let ticks = u32_at(&header, 4, big_endian) as u64;
+        let captured = u32_at(&header, 8, big_endian) as usize;
+        if captured > MAX_RECORD {
+            return Err(malformed(format!("{captured}-byte record")));
+        }
+        let mut data = vec![0; captured];
+        self.reader
+            .read_exact(&mut data)
+            .map_err(truncated("packet record"))?;
+        Ok(Some(Packet {
+            number: 0,
+            timestamp: Duration::from_secs(secs) + ticks_to_duration(ticks, ticks_per_sec),
+            link_type,
+            original_len: u32_at(&header, 12, big_endian),
+            data,
+        }))
+    }
+
+    /// Read blocks until one holds a packet.
+    fn next_block(&mut self) -> Result<Option<Packet>, CaptureError> {
+        loop {
+            let mut head = [0u8; 4];
+            if !read_or_eof(&mut self.reader, &mut head)? {
+                return Ok(None);
+            }
+            let Format::PcapNg {
+                big_endian,
+                interfaces,
+            } = &mut self.format
+            else {
+                unreachable!("next_block on a classic pcap file");
+            };
+            if u32::from_le_bytes(head) == PCAPNG_SECTION {
+                // A new section may switch byte order and restarts the
+                // interface list.
+                *big_endian = read_section_header(&mut self.reader)?;
+                interfaces.clear();
+                continue;
+            }
+            let block_type = u32_at(&head, 0, *big_endian);
+            let body = read_block_body(&mut self.reader, *big_endian)?;
+            let big_endian = *big_endian;
+            match block_type {
+                BLOCK_INTERFACE => interfaces.push(parse_interface(&body, big_endian)?),
+                BLOCK_ENHANCED_PACKET => {
+                    if body.len() < 20 {
+                        return Err(malformed("short enhanced packet block"));
+                    }
+                    let id = u32_at(&body, 0, big_endian) as usize;
+                    let interface = *interfaces
+                        .get(id)
+                        .ok_or_else(|| malformed(format!("packet on unknown interface {id}")))?;
+                    let ticks = (u32_at(&body, 4, big_endian) as u64) << 32
+                        | u32_at(&body, 8, big_endian) as u64;
+                    let captured = u32_at(&body, 12, big_endian) as usize;
+                    let data = body
+                        .get(20..20 + captured)
+                        .ok_or_else(|| malformed("packet data overruns its block"))?;
+                    return Ok(Some(Packet {
+                        number: 0,
+                        timestamp: ticks_to_duration(ticks, interface.ticks_per_sec),
+                        link_type: interface.link_type,
+                        original_len: u32_at(&body, 16, big_endian),
+                        data: data.to_vec(),
+                    }));
+                }
+                BLOCK_SIMPLE_PACKET => {
+                    if body.len() < 4 {
+                        return Err(malformed("short simple packet block"));
+                    }
+                    let interface = *interfaces
+                        .first()
+                        .ok_or_else(|| malformed("packet before any interface"))?;
+                    let original_len = u32_at(&body, 0, big_endian);
+                    let captured = (original_len as usize).min(body.len() - 4);
+                    return Ok(Some(Packet {
+                        number: 0,
+                        timestamp: Duration::ZERO,
+                        link_type: interface.link_type,
+                        original_len,
+                        data: body[4..4 + captured].to_vec(),
+                    }));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = Result<Packet, CaptureError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_packet().transpose()
+    }
+}
+
+/// Read the rest of a section header block after its type; returns whether
+/// the section is big-endian.
+fn read_section_header(reader: &mut impl Read) -> Result<bool, CaptureError> {
+    let mut head = [0u8; 8];
+    reader
+        .read_exact(&mut head)
+        .map_err(truncated("section header"))?;
+    let big_endian = match u32::from_le_bytes(head[4..8].try_into().unwrap()) {
+        PCAPNG_BYTE_ORDER => false,
+        m if m.swap_bytes() == PCAPNG_BYTE_ORDER => true,
+        m => return Err(malformed(format!("bad byte-order magic {m:#010x}"))),
+    };
+    let total = u32_at(&head, 0, big_endian) as usize;
+    if total < 28 || !total.is_multiple_of(4) || total > MAX_RECORD {
+        return Err(malformed(format!("{total}-byte section header")));
+    }
+    // Skip the version, section length, options and trailing length.
+    let mut rest = vec![0; total - 12];
+    reader
+        .read_exact(&mut rest)
+        .map_err(truncated("section header"))?;
+    Ok(big_endian)
+}
+
+/// Read a block's length, body and trailing length; returns the body.
+fn read_block_body(reader: &mut impl Read, big_endian: bool) -> Result<Vec<u8>, CaptureError> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len).map_err(truncated("block"))?;
+    let total = u32_at(&len, 0, big_endian) as usize;
+    if total < 12 || !total.is_multiple_of(4) || total > MAX_RECORD {
+        return Err(malformed(format!("{total}-byte block")));
+    }
+    let mut body = vec![0; total - 8];
+    reader.read_exact(&mut body).map_err(truncated("block"))?;
+    let trailer = u32_at(&body, total - 12, big_endian) as usize;
+    if trailer != total {
+        return Err(malformed(format!(
+            "block length {total} doesn't match trailer {trailer}"
+        )));
+    }
+    body.truncate(total - 12);
+    Ok(body)
+}
+
+fn parse_interface(body: &[u8], big_endian: bool) -> Result<Interface, CaptureError> {
+    if body.len() < 8 {
+        return Err(malformed("short interface description block"));
+    }
+    let mut interface = Interface {
+        link_type: u16_at(body, 0, big_endian) as u32,
+        ticks_per_sec: 1_000_000,
+    };
+    let mut at = 8;
+    while at + 4 <= body.len() {
+        let code = u16_at(body, at, big_endian);
+        let len = u16_at(body, at + 2, big_endian) as usize;
+        let value = body
+            .get(at + 4..at + 4 + len)
+            .ok_or_else(|| malformed("interface option overruns its block"))?;
+        match code {
+            0 => break,
+            OPTION_TSRESOL if len == 1 => {
+                let exp = (value[0] & 0x7f) as u32;
+                interface.ticks_per_sec = if value[0] & 0x80 != 0 {
+                    2u64.checked_pow(exp)
+                } else {
+                    10u64.checked_pow(exp)
+                }
+                .ok_or_else(|| malformed("timestamp resolution out of range"))?;
+            }
+            _ => {}
+        }
+        at += 4 + len.next_multiple_of(4);
+    }
+    Ok(interface)
+}
+
+/// Fill `buf`, or return `false` if the reader is already at its end.
+fn read_or_eof(reader: &mut impl Read, buf: &mut [u8]) -> Result<bool, CaptureError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(malformed("file ends inside a record header")),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(true)
+}
+
+fn ticks_to_duration(ticks: u64, ticks_per_sec: u64) -> Duration {
+    let nanos = (ticks % ticks_per_sec) as u128 * 1_000_000_000 / ticks_per_sec as u128;
+    Duration::new(ticks / ticks_per_sec, nanos as u32)
+}
+
+fn u16_at(data: &[u8], at: usize, big_endian: bool) -> u16 {
+    let bytes = data[at..at + 2].try_into().unwrap();
+    if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    }
+}
+
+fn u32_at(data: &[u8], at: usize, big_endian: bool) -> u32 {
+    let bytes = data[at..at + 4].try_into().unwrap();
+    if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    }
+}
+
+fn malformed(what: impl Into<String>) -> CaptureError {
+    CaptureError::Malformed(what.into())
+}
+
+/// Map an unexpected end of file while reading `what` to `Malformed`.
+fn truncated(what: &'static str) -> impl Fn(io::Error) -> CaptureError {
+    move |e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => malformed(format!("file ends inside a {what}")),
+        _ => e.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pcap(big_endian: bool, nanos: bool, records: &[(u32, u32, &[u8])]) -> Vec<u8> {
+        let u32b = |v: u32| {
+            if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        let mut out = Vec::new();
+        out.extend(u32b(if nanos { PCAP_NANOS } else { PCAP_MICROS }));
+        out.extend([0, 2, 0, 4].map(|b| if big_endian { b } else { 0 }));
+        out.extend([0; 8]);
+        out.extend(u32b(65535));
+        out.extend(u32b(1));
+        for &(secs, frac, data) in records {
+            out.extend(u32b(secs));
+            out.extend(u32b(frac));
+            out.extend(u32b(data.len() as u32));
+            out.extend(u32b(data.len() as u32 + 10));
+            out.extend(data);
+        }
+        out
+    }
+
+    fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let padded = body.len().next_multiple_of(4);
+        let total = (padded + 12) as u32;
+        let mut out = Vec::new();
+        out.extend(block_type.to_le_bytes());
+        out.extend(total.to_le_bytes());
+        out.extend(body);
+        out.resize(8 + padded, 0);
+        out.extend(total.to_le_bytes());
+        out
+    }
+
+    fn pcapng() -> Vec<u8> {
+        let mut shb = PCAPNG_BYTE_ORDER.to_le_bytes().to_vec();
+        shb.extend([1, 0, 0, 0]);
+        shb.extend(u64::MAX.to_le_bytes());
+        let mut out = block(PCAPNG_SECTION, &shb);
+
+        // Interface 0: Ethernet, nanosecond timestamps.
+        let mut idb = vec![1, 0, 0, 0, 0, 0, 1, 0];
+        idb.extend([OPTION_TSRESOL as u8, 0, 1, 0, 9, 0, 0, 0, 0, 0, 0, 0]);
+        out.extend(block(BLOCK_INTERFACE, &idb));
+        // Interface 1: raw IP, default microseconds.
+        out.extend(block(BLOCK_INTERFACE, &[101, 0, 0, 0, 0, 0, 1, 0]));
+        // A name resolution block to skip.
+        out.extend(block(4, &[0, 0, 0, 0]));
+
+        let mut epb = 1u32.to_le_bytes().to_vec();
+        let ticks: u64 = 1_500_000; // 1.5 s
+        epb.extend(((ticks >> 32) as u32).to_le_bytes());
+        epb.extend((ticks as u32).to_le_bytes());
+        epb.extend(3u32.to_le_bytes());
+        epb.extend(3u32.to_le_bytes());
+        epb.extend(b"abc");
+        out.extend(block(BLOCK_ENHANCED_PACKET, &epb));
+
+        let mut spb = 5u32.to_le_bytes().to_vec();
+        spb.extend(b"hello");
+        out.extend(block(BLOCK_SIMPLE_PACKET, &spb));
+        out
+    }
+
+    #[test]
+    fn reads_classic_pcap_in_both_byte_orders() {
+        for big_endian in [false, true] {
+            let file = pcap(
+                big_endian,
+                false,
+                &[(10, 250_000, b"one"), (11, 0, b"two!")],
+            );
+            let packets: Vec<_> = CaptureReader::new(&file[..])
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(packets.len(), 2);
+            assert_eq!(packets[0].number, 1);
+            assert_eq!(packets[0].timestamp, Duration::from_millis(10_250));
+            assert_eq!(packets[0].link_type, 1);
+            assert_eq!(packets[0].original_len, 13);
+            assert_eq!(packets[1].data, b"two!");
+        }
+    }
+
+    #[test]
+    fn nanosecond_pcap() {
+        let file = pcap(false, true, &[(1, 5, b"x")]);
+        let packet = CaptureReader::new(&file[..])
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(packet.timestamp, Duration::new(1, 5));
+    }
+
+    #[test]
+    fn reads_pcapng() {
+        let packets: Vec<_> = CaptureReader::new(&pcapng()[..])
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].link_type, 101);
+        assert_eq!(packets[0].timestamp, Duration::from_millis(1500));
+        assert_eq!(packets[0].data, b"abc");
+        assert_eq!(packets[1].number, 2);
+        assert_eq!(packets[1].link_type, 1);
+        assert_eq!(packets[1].data, b"hello");
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            CaptureReader::new(&b"GIF89a.."[..]),
+            Err(CaptureError::UnknownFormat(_))
+        ));
+        assert!(matches!(
+            CaptureReader::new(&pcap(false, false, &[])[..10]),
+            Err(CaptureError::Malformed(_))
+        ));
+        let mut file = pcap(false, false, &[(0, 0, b"abcdef")]);
+        file.truncate(file.len() - 2);
+        let err = CaptureReader::new(&file[..])
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "malformed capture: file ends inside a packet record"
+        );
+        let mut file = pcapng();
+        let len = file.len();
+        file[len - 4] ^= 0xff; // trailing length of the last block
+        let results: Vec<_> = CaptureReader::new(&file[..]).unwrap().collect();
+        assert!(matches!(
+            results.last(),
+            Some(Err(CaptureError::Malformed(_)))
+        ));
+    }
+}
@@ -0,0 +1,727 @@
+//! Per-flow TCP stream reassembly in front of the streaming matchers.
+//!
+//! A [`Reassembler`] tracks TCP flows by their 5-tuple (protocol TCP plus
+//! both addresses and ports) and keeps one [`ChunkMatcher`] per direction.
+//! Segments are put in sequence order before they reach the matcher:
+//!
+//! - out-of-order segments wait until the bytes before them arrive;
+//! - retransmitted bytes that were already delivered are dropped, so a
+//!   retransmission never reports a match twice;
+//! - overlapping segments that are still waiting are resolved by the
+//!   [`OverlapPolicy`] (bytes already delivered can't change, so there the
+//!   first copy always wins);
+//! - payload bytes the capture cut off ([`Segment::missing`]) are skipped
+//!   when the stream reaches them, unless other segments supplied them;
+//! - a gap that is never filled is skipped once a direction buffers more
+//!   than the configured limit, on RST and on [`Reassembler::flush`]. No
+//!   match spans a skipped gap, and offsets still count the missing bytes.
+//!
+//! A flow is dropped after an RST, or once both directions delivered all
+//! data up to their FIN. When a new flow would go over the flow limit, the
+//! least recently active one is flushed like on RST and dropped; segments
+//! it gets later start a new flow.
+//!
+//! Stream offsets start at the first byte after the SYN. When the SYN was
+//! not captured, they start at the first segment seen in that direction,
+//! and bytes before it that show up later are treated as retransmissions.
+
+use crate::stream::ChunkMatcher;
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::ops::BitOr;
+
+/// TCP header flags, as in byte 13 of the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TcpFlags(pub u8);
+
+impl TcpFlags {
+    pub const FIN: Self = Self(0x01);
+    pub const SYN: Self = Self(0x02);
+    pub const RST: Self = Self(0x04);
+    pub const PSH: Self = Self(0x08);
+    pub const ACK: Self = Self(0x10);
+
+    /// Whether every flag in `other` is set.
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for TcpFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// One TCP segment.
+#[derive(Debug, Clone, Copy)]
+pub struct Segment<'a> {
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+    pub seq: u32,
+    pub flags: TcpFlags,
+    /// Captured payload.
+    pub payload: &'a [u8],
+    /// Payload bytes after `payload` that the capture left out.
+    pub missing: usize,
+}
+
+/// A TCP flow, named after the side that opened it (or sent first, when
+/// the handshake was not seen).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FlowKey {
+    pub client: SocketAddr,
+    pub server: SocketAddr,
+}
+
+/// Direction of data within a flow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    ToServer,
+    ToClient,
+}
+
+/// Which copy of overlapping, not yet delivered bytes is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverlapPolicy {
+    /// Keep the bytes that arrived first (BSD-style).
+    #[default]
+    FirstWins,
+    /// Let later segments overwrite earlier ones (Linux-style for data
+    /// that is still queued).
+    LastWins,
+}
+
+/// A match reported by a flow's matcher.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlowMatch<T> {
+    pub flow: FlowKey,
+    pub direction: Direction,
+    /// The matcher's own report, e.g. a stream offset.
+    pub matched: T,
+}
+
+/// One direction of a flow.
+#[derive(Debug)]
+struct HalfStream<M> {
+    matcher: M,
+    /// Sequence number of stream offset 0, once known.
+    base: Option<u32>,
+    /// Next stream offset to deliver.
+    next: u64,
+    /// Non-overlapping segments waiting for the bytes before them, by
+    /// stream offset.
+    pending: BTreeMap<u64, Vec<u8>>,
+    buffered: usize,
+    /// Ranges (start to end offset) known to be lost, skipped once the
+    /// stream gets there.
+    holes: BTreeMap<u64, u64>,
+    /// Stream offset of the FIN.
+    fin: Option<u64>,
+}
+
+impl<M: ChunkMatcher> HalfStream<M> {
+    fn new(matcher: M) -> Self {
+        Self {
+            matcher,
+            base: None,
+            next: 0,
+            pending: BTreeMap::new(),
+            buffered: 0,
+            holes: BTreeMap::new(),
+            fin: None,
+        }
+    }
+
+    /// Stream offset of `seq`, taken as the one closest to `next` so that
+    /// sequence numbers may wrap.
+    fn offset_of(&self, seq: u32) -> i64 {
+        let expected = self.base.unwrap_or(seq).wrapping_add(self.next as u32);
+        self.next as i64 + seq.wrapping_sub(expected) as i32 as i64
+    }
+
+    fn closed(&self) -> bool {
+        self.fin.is_some_and(|fin| self.next >= fin)
+    }
+
+    /// Queue `data` at stream offset `start`, followed by `missing` lost
+    /// bytes, and deliver what became contiguous.
+    fn insert(
+        &mut self,
+        start: i64,
+        data: &[u8],
+        missing: usize,
+        policy: OverlapPolicy,
+        max_buffer: usize,
+    ) -> Vec<M::Match> {
+        let next = self.next as i64;
+        let data_end = start + data.len() as i64;
+        let end = data_end + missing as i64;
+        if end <= next {
+            return Vec::new();
+        }
+        if missing > 0 {
+            self.holes.insert(data_end.max(next) as u64, end as u64);
+        }
+        if data_end > next {
+            let (start, data) = if start < next {
+                (self.next, &data[(next - start) as usize..])
+            } else {
+                (start as u64, data)
+            };
+            match policy {
+                OverlapPolicy::FirstWins => self.insert_first_wins(start, data),
+                OverlapPolicy::LastWins => self.insert_last_wins(start, data),
+            }
+        }
+
+        let mut matches = self.deliver();
+        while self.buffered > max_buffer {
+            matches.extend(self.skip_gap());
+        }
+        matches
+    }
+
+    /// Offsets of the queued segments overlapping `start..end`.
+    fn overlapping(&self, start: u64, end: u64) -> Vec<u64> {
+        let before = self
+            .pending
+            .range(..start)
+            .next_back()
+            .filter(|(&s, d)| s + d.len() as u64 > start)
+            .map(|(&s, _)| s);
+        before
+            .into_iter()
+            .chain(self.pending.range(start..end).map(|(&s, _)| s))
+            .collect()
+    }
+
+    /// Queue only the parts of `data` nothing covers yet.
+    fn insert_first_wins(&mut self, start: u64, data: &[u8]) {
+        let end = start + data.len() as u64;
+        let mut cursor = start;
+        let mut pieces = Vec::new();
+        for s in self.overlapping(start, end) {
+            if s > cursor {
+                pieces.push((cursor, s));
+            }
+            cursor = cursor.max(s + self.pending[&s].len() as u64);
+        }
+        if cursor < end {
+            pieces.push((cursor, end));
+        }
+        for (from, to) in pieces {
+            let bytes = data[(from - start) as usize..(to - start) as usize].to_vec();
+            self.buffered += bytes.len();
+            self.pending.insert(from, bytes);
+        }
+    }
+
+    /// Queue `data`, cutting whatever it covers out of queued segments.
+    fn insert_last_wins(&mut self, start: u64, data: &[u8]) {
+        let end = start + data.len() as u64;
+        for s in self.overlapping(start, end) {
+            let old = self.pending.remove(&s).unwrap();
+            self.buffered -= old.len();
+            let old_end = s + old.len() as u64;
+            if s < start {
+                let head = old[..(start - s) as usize].to_vec();
+                self.buffered += head.len();
+                self.pending.insert(s, head);
+            }
+            if old_end > end {
+                let tail = old[(end - s) as usize..].to_vec();
+                self.buffered += tail.len();
+                self.pending.insert(end, tail);
+            }
+        }
+        self.buffered += data.len();
+        self.pending.insert(start, data.to_vec());
+    }
+
+    /// Feed the queued segments that continue the stream, skipping holes
+    /// no queued data covers.
+    fn deliver(&mut self) -> Vec<M::Match> {
+        let mut matches = Vec::new();
+        loop {
+            if let Some(entry) = self.pending.first_entry() {
+                let start = *entry.key();
+                if start <= self.next {
+                    let data = entry.remove();
+                    self.buffered -= data.len();
+                    // Part of it may lie in a hole that was skipped.
+                    let fresh = data.get((self.next - start) as usize..).unwrap_or_default();
+                    self.next += fresh.len() as u64;
+                    matches.extend(self.matcher.feed(fresh));
+                    continue;
+                }
+            }
+            match self.holes.first_key_value() {
+                Some((&start, &end)) if start <= self.next => {
+                    self.holes.pop_first();
+                    if end > self.next {
+                        self.matcher.skip((end - self.next) as usize);
+                        self.next = end;
+                    }
+                }
+                _ => break,
+            }
+        }
+        matches
+    }
+
+    /// Give up on the bytes before the first queued segment.
+    fn skip_gap(&mut self) -> Vec<M::Match> {
+        match self.pending.keys().next() {
+            Some(&start) => {
+                self.matcher.skip((start - self.next) as usize);
+                self.next = start;
+                self.deliver()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Deliver everything queued, skipping gaps.
+    fn flush(&mut self) -> Vec<M::Match> {
+        let mut matches = Vec::new();
+        while !self.pending.is_empty() {
+            matches.extend(self.skip_gap());
+        }
+        matches
+    }
+}
+
+#[derive(Debug)]
+struct Flow<M> {
+    key: FlowKey,
+    /// Segment count when the flow was last active.
+    active: u64,
+    to_server: HalfStream<M>,
+    to_client: HalfStream<M>,
+}
+
+impl<M: ChunkMatcher> Flow<M> {
+    fn half(&mut self, direction: Direction) -> &mut HalfStream<M> {
+        match direction {
+            Direction::ToServer => &mut self.to_server,
+            Direction::ToClient => &mut self.to_client,
+        }
+    }
+
+    /// Deliver everything queued in both directions.
+    fn flush(&mut self) -> Vec<FlowMatch<M::Match>> {
+        let mut matches = tag(self.key, Direction::ToServer, self.to_server.flush());
+        matches.extend(tag(self.key, Direction::ToClient, self.to_client.flush()));
+        matches
+    }
+}
+
+fn tag<T>(flow: FlowKey, direction: Direction, found: Vec<T>) -> Vec<FlowMatch<T>> {
+    found
+        .into_iter()
+        .map(|matched| FlowMatch {
+            flow,
+            direction,
+            matched,
+        })
+        .collect()
+}
+
+/// Reassembles TCP flows and runs a matcher over each direction.
+///
+/// # Example
+/// ```
+/// use packet_match_fuzz::{Direction, Reassembler, Segment, StreamMatcher, TcpFlags};
+///
+/// let client = "10.0.0.1:40000".parse().unwrap();
+/// let server = "10.0.0.2:80".parse().unwrap();
+/// let segment = |seq, flags, payload| Segment {
+///     src: client,
+///     dst: server,
+///     seq,
+///     flags,
+///     payload,
+///     missing: 0,
+/// };
+///
+/// let mut flows = Reassembler::new(|| StreamMatcher::new(b"/admin"));
+/// flows.process(&segment(999, TcpFlags::SYN, b""));
+/// // The second half arrives first and waits for the first.
+/// assert!(flows.process(&segment(1007, TcpFlags::ACK, b"min HTTP/1.1")).is_empty());
+/// let matches = flows.process(&segment(1000, TcpFlags::ACK, b"GET /ad"));
+/// assert_eq!(matches[0].direction, Direction::ToServer);
+/// assert_eq!(matches[0].matched, 4);
+/// ```
+pub struct Reassembler<M, F> {
+    new_matcher: F,
+    policy: OverlapPolicy,
+    max_buffer: usize,
+    max_flows: usize,
+    /// Flows by their endpoints in sorted order, so both directions find
+    /// the same flow.
+    flows: HashMap<(SocketAddr, SocketAddr), Flow<M>>,
+    /// Endpoints of each flow by its `active` count, least recent first.
+    by_activity: BTreeMap<u64, (SocketAddr, SocketAddr)>,
+    /// Segments processed so far.
+    segments: u64,
+}
+
+impl<M: ChunkMatcher, F: FnMut() -> M> Reassembler<M, F> {
+    /// Reassembler that calls `new_matcher` for each direction of each new
+    /// flow. Overlaps keep the first copy, each direction buffers up to
+    /// 1 MiB of out-of-order data and up to 65536 flows are tracked.
+    pub fn new(new_matcher: F) -> Self {
+        Self {
+            new_matcher,
+            policy: OverlapPolicy::default(),
+            max_buffer: 1 << 20,
+            max_flows: 1 << 16,
+            flows: HashMap::new(),
+            by_activity: BTreeMap::new(),
+            segments: 0,
+        }
+    }
+
+    /// How overlapping out-of-order segments are resolved.
+    pub fn with_overlap_policy(mut self, policy: OverlapPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Out-of-order bytes one direction may queue before the gap in front
+    /// of them is given up on.
+    pub fn with_max_buffer(mut self, bytes: usize) -> Self {
+        self.max_buffer = bytes;
+        self
+    }
+
+    /// Flows tracked at once (at least one); past that, the least recently
+    /// active flow is flushed and forgotten to make room for a new one.
+    pub fn with_max_flows(mut self, flows: usize) -> Self {
+        self.max_flows = flows.max(1);
+        self
+    }
+
+    /// Feed one segment and return the matches it completed.
+    /// Matches from a flow evicted to make room come first.
+    pub fn process(&mut self, segment: &Segment) -> Vec<FlowMatch<M::Match>> {
+        self.segments += 1;
+        let mut matches = Vec::new();
+        let syn = segment.flags.contains(TcpFlags::SYN);
+        let endpoints = if segment.src <= segment.dst {
+            (segment.src, segment.dst)
+        } else {
+            (segment.dst, segment.src)
+        };
+        if !self.flows.contains_key(&endpoints) {
+            // Stray ACKs and FINs of flows we never saw don't start one.
+            if !syn && segment.payload.is_empty() {
+                return Vec::new();
+            }
+            // A SYN-ACK comes from the server; anything else from the client.
+            let key = if syn && segment.flags.contains(TcpFlags::ACK) {
+                FlowKey {
+                    client: segment.dst,
+                    server: segment.src,
+                }
+            } else {
+                FlowKey {
+                    client: segment.src,
+                    server: segment.dst,
+                }
+            };
+            if self.flows.len() >= self.max_flows {
+                if let Some((_, oldest)) = self.by_activity.pop_first() {
+                    let mut flow = self.flows.remove(&oldest).unwrap();
+                    matches.extend(flow.flush());
+                }
+            }
+            let flow = Flow {
+                key,
+                active: 0,
+                to_server: HalfStream::new((self.new_matcher)()),
+                to_client: HalfStream::new((self.new_matcher)()),
+            };
+            self.flows.insert(endpoints, flow);
+        }
+
+        let flow = self.flows.get_mut(&endpoints).unwrap();
+        self.by_activity.remove(&flow.active);
+        flow.active = self.segments;
+        self.by_activity.insert(flow.active, endpoints);
+        let direction = if segment.src == flow.key.client {
+            Direction::ToServer
+        } else {
+            Direction::ToClient
+        };
+        let half = flow.half(direction);
+        // The SYN takes up one sequence number before the data.
+        let data_seq = if syn {
+            segment.seq.wrapping_add(1)
+        } else {
+            segment.seq
+        };
+        if half.base.is_none() {
+            half.base = Some(data_seq);
+        }
+        let start = half.offset_of(data_seq);
+        if segment.flags.contains(TcpFlags::FIN) {
+            let len = segment.payload.len() + segment.missing;
+            half.fin = Some((start + len as i64).max(0) as u64);
+        }
+        let found = half.insert(
+            start,
+            segment.payload,
+            segment.missing,
+            self.policy,
+            self.max_buffer,
+        );
+        matches.extend(tag(flow.key, direction, found));
+
+        if segment.flags.contains(TcpFlags::RST) {
+            let mut flow = self.remove(&endpoints);
+            matches.extend(flow.flush());
+        } else if flow.to_server.closed() && flow.to_client.closed() {
+            self.remove(&endpoints);
+        }
+        matches
+    }
+
+    fn remove(&mut self, endpoints: &(SocketAddr, SocketAddr)) -> Flow<M> {
+        let flow = self.flows.remove(endpoints).unwrap();
+        self.by_activity.remove(&flow.active);
+        flow
+    }
+
+    /// End of input: deliver everything still queued, skipping gaps, and
+    /// forget all flows.
+    pub fn flush(&mut self) -> Vec<FlowMatch<M::Match>> {
+        let mut matches = Vec::new();
+        for (_, mut flow) in self.flows.drain() {
+            matches.extend(flow.flush());
+        }
+        self.by_activity.clear();
+        matches
+    }
+
+    /// Number of flows being tracked.
+    pub fn flow_count(&self) -> usize {
+        self.flows.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RuleSet, StreamMatcher};
+
+    const CLIENT: &str = "10.0.0.1:40000";
+    const SERVER: &str = "10.0.0.2:80";
+
+    fn segment(from_client: bool, seq: u32, flags: TcpFlags, payload: &[u8]) -> Segment<'_> {
+        let (client, server) = (CLIENT.parse().unwrap(), SERVER.parse().unwrap());
+        let (src, dst) = if from_client {
+            (client, server)
+        } else {
+            (server, client)
+        };
+        Segment {
+            src,
+            dst,
+            seq,
+            flags,
+            payload,
+            missing: 0,
+        }
+    }
+
+    fn data(seq: u32, payload: &[u8]) -> Segment<'_> {
+        segment(true, seq, TcpFlags::ACK, payload)
+    }
+
+    fn offsets(matches: Vec<FlowMatch<usize>>) -> Vec<usize> {
+        matches.into_iter().map(|m| m.matched).collect()
+    }
+
+    fn matcher(pattern: &'static [u8]) -> impl FnMut() -> StreamMatcher {
+        move || StreamMatcher::new(pattern)
+    }
+
+    #[test]
+    fn in_order_segments_match_across_boundaries() {
+        let mut r = Reassembler::new(matcher(b"ABCD"));
+        assert!(r.process(&segment(true, 99, TcpFlags::SYN, b"")).is_empty());
+        assert!(r.process(&data(100, b"xxAB")).is_empty());
+        let m = r.process(&data(104, b"CDyy"));
+        assert_eq!(m[0].flow.client, CLIENT.parse().unwrap());
+        assert_eq!(m[0].direction, Direction::ToServer);
+        assert_eq!(m[0].matched, 2);
+    }
+
+    #[test]
+    fn out_of_order_segments_are_reordered() {
+        let mut r = Reassembler::new(matcher(b"ABCDEF"));
+        assert!(r.process(&data(0, b"xA")).is_empty());
+        assert!(r.process(&data(5, b"EF")).is_empty());
+        assert!(r.process(&data(3, b"CD")).is_empty());
+        assert_eq!(offsets(r.process(&data(2, b"B"))), vec![1]);
+    }
+
+    #[test]
+    fn retransmissions_do_not_repeat_matches() {
+        let mut r = Reassembler::new(matcher(b"AB"));
+        assert_eq!(offsets(r.process(&data(0, b"xAB"))), vec![1]);
+        assert!(r.process(&data(0, b"xAB")).is_empty());
+        // Partly new: only the new bytes are delivered.
+        assert_eq!(offsets(r.process(&data(1, b"ABAB"))), vec![3]);
+    }
+
+    #[test]
+    fn overlap_policy_decides_queued_bytes() {
+        for (policy, expected) in [
+            (OverlapPolicy::FirstWins, vec![]),
+            (OverlapPolicy::LastWins, vec![4]),
+        ] {
+            let mut r = Reassembler::new(matcher(b"evil")).with_overlap_policy(policy);
+            r.process(&data(0, b"x"));
+            r.process(&data(4, b"good"));
+            r.process(&data(3, b"xevil!"));
+            assert_eq!(offsets(r.process(&data(1, b"xx"))), expected, "{policy:?}");
+        }
+    }
+
+    #[test]
+    fn gaps_are_skipped_on_flush() {
+        let mut r = Reassembler::new(matcher(b"ABAB"));
+        r.process(&data(0, b"xAB"));
+        // Bytes 3..5 never arrive.
+        assert!(r.process(&data(5, b"ABAB")).is_empty());
+        assert_eq!(offsets(r.flush()), vec![5]);
+        assert_eq!(r.flow_count(), 0);
+    }
+
+    #[test]
+    fn buffer_limit_skips_the_gap() {
+        let mut r = Reassembler::new(matcher(b"AB")).with_max_buffer(4);
+        r.process(&data(0, b"xA"));
+        assert!(r.process(&data(3, b"ABx")).is_empty());
+        assert_eq!(offsets(r.process(&data(6, b"AB"))), vec![3, 6]);
+    }
+
+    #[test]
+    fn directions_have_their_own_state() {
+        let mut r = Reassembler::new(matcher(b"AB"));
+        assert!(r
+            .process(&segment(true, 0, TcpFlags::ACK, b"xA"))
+            .is_empty());
+        assert!(r
+            .process(&segment(false, 500, TcpFlags::ACK, b"B"))
+            .is_empty());
+        let m = r.process(&segment(false, 501, TcpFlags::ACK, b"AB"));
+        assert_eq!(m[0].direction, Direction::ToClient);
+        assert_eq!(m[0].matched, 1);
+        assert_eq!(r.flow_count(), 1);
+    }
+
+    #[test]
+    fn handshake_names_client_and_server() {
+        let mut r = Reassembler::new(matcher(b"AB"));
+        r.process(&segment(false, 7, TcpFlags::SYN | TcpFlags::ACK, b""));
+        let m = r.process(&segment(true, 0, TcpFlags::ACK, b"AB"));
+        assert_eq!(m[0].flow.server, SERVER.parse().unwrap());
+        assert_eq!(m[0].direction, Direction::ToServer);
+    }
+
+    #[test]
+    fn fin_and_rst_end_flows() {
+        let mut r = Reassembler::new(matcher(b"AB"));
+        r.process(&segment(true, 0, TcpFlags::ACK, b"A"));
+        r.process(&segment(true, 1, TcpFlags::FIN | TcpFlags::ACK, b"B"));
+        assert_eq!(r.flow_count(), 1);
+        r.process(&segment(false, 9, TcpFlags::FIN | TcpFlags::ACK, b"x"));
+        assert_eq!(r.flow_count(), 0);
+        // The last ACK doesn't bring the flow back.
+        r.process(&segment(true, 2, TcpFlags::ACK, b""));
+        assert_eq!(r.flow_count(), 0);
+
+        r.process(&data(0, b"xA"));
+        r.process(&data(4, b"AB"));
+        assert_eq!(
+            offsets(r.process(&segment(true, 6, TcpFlags::RST, b""))),
+            vec![4]
+        );
+        assert_eq!(r.flow_count(), 0);
+    }
+
+    #[test]
+    fn flow_limit_flushes_the_least_recently_active_flow() {
+        let mut r = Reassembler::new(matcher(b"AB")).with_max_flows(2);
+        let from = |port: u16, seq, payload| Segment {
+            src: SocketAddr::from(([10, 0, 0, 1], port)),
+            ..data(seq, payload)
+        };
+        r.process(&from(1, 0, b"A"));
+        // Waits behind a gap that is never filled.
+        assert!(r.process(&from(1, 3, b"AB")).is_empty());
+        r.process(&from(2, 0, b"x"));
+        // Flow 1 was active before flow 2, so it makes room for flow 3.
+        let m = r.process(&from(3, 0, b"AB"));
+        assert_eq!(m[0].flow.client.port(), 1);
+        assert_eq!(offsets(m), vec![3, 0]);
+        assert_eq!(r.flow_count(), 2);
+
+        // Activity keeps a flow: 2 was just used, so 3 goes.
+        r.process(&from(2, 1, b"A"));
+        let m = r.process(&from(4, 0, b"x"));
+        assert!(m.is_empty());
+        assert_eq!(offsets(r.process(&from(2, 2, b"B"))), vec![1]);
+        assert_eq!(r.flow_count(), 2);
+    }
+
+    #[test]
+    fn bytes_cut_from_the_capture_are_skipped() {
+        let mut r = Reassembler::new(matcher(b"AB"));
+        // Only "xA" of a 6-byte payload was captured.
+        let cut = Segment {
+            missing: 4,
+            ..data(0, b"xA")
+        };
+        assert!(r.process(&cut).is_empty());
+        // The next segment continues right away; nothing spans the hole.
+        assert_eq!(offsets(r.process(&data(6, b"BAB"))), vec![7]);
+
+        // Data that fills the hole before the stream reaches it is used.
+        let mut r = Reassembler::new(matcher(b"ABCD"));
+        r.process(&data(0, b"x"));
+        r.process(&Segment {
+            missing: 2,
+            ..data(2, b"A")
+        });
+        r.process(&data(3, b"BCD"));
+        assert_eq!(offsets(r.process(&data(1, b"y"))), vec![2]);
+    }
+
+    #[test]
+    fn sequence_numbers_wrap() {
+        let mut r = Reassembler::new(matcher(b"ABCD"));
+        r.process(&segment(true, u32::MAX - 2, TcpFlags::SYN, b""));
+        r.process(&data(2, b"CD"));
+        assert_eq!(offsets(r.process(&data(u32::MAX - 1, b"xxAB"))), vec![2]);
+    }
+
+    #[test]
+    fn drives_rule_matchers() {
+        let rules: RuleSet = r#"alert tcp any any -> any 80 (content:"GET "; depth:4; content:"/admin"; distance:0; sid:9;)"#
+            .parse()
+            .unwrap();
+        let mut r = Reassembler::new(|| rules.stream());
+        assert!(r.process(&data(0, b"GET /ad")).is_empty());
+        let m = r.process(&segment(false, 0, TcpFlags::ACK, b"GET /admin"));
+        assert_eq!((m[0].direction, m[0].matched), (Direction::ToClient, 9));
+        let m = r.process(&data(7, b"min"));
+        assert_eq!((m[0].direction, m[0].matched), (Direction::ToServer, 9));
+    }
+}
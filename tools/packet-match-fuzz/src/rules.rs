@@ -0,0 +1,999 @@
+//! Snort/Suricata rule subset.
+//!
+//! Parses rules like
+//!
+//! ```text
+//! alert tcp any any -> any 80 (msg:"admin probe"; content:"GET "; depth:4; \
+//!     content:"/admin"; nocase; distance:0; within:32; sid:1000001; rev:1;)
+//! ```
+//!
+//! and reports which SIDs fire on a payload or stream. Only the payload is
+//! inspected: the header is parsed but its addresses and ports are ignored.
+//!
+//! Supported keywords are `content` (with `|hex|` bytes), its modifiers
+//! `nocase`, `offset`, `depth`, `distance`, `within` and `fast_pattern`, and
+//! the informational `msg`, `sid`, `rev`, `gid`, `classtype`, `reference`,
+//! `metadata` and `priority`. Anything else is rejected with
+//! [`RuleError::Unsupported`].
+//!
+//! All contents of a [`RuleSet`] go into two [`MultiMatcher`] automata (one
+//! case-sensitive, one for `nocase` contents over lowercased input), and the
+//! positional modifiers are checked against the occurrences they report.
+
+use crate::multi::{MultiMatcher, ROOT};
+use crate::stream::ChunkMatcher;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+/// Keywords that don't affect matching.
+const INFO_KEYWORDS: [&str; 6] = [
+    "gid",
+    "classtype",
+    "reference",
+    "metadata",
+    "priority",
+    "fast_pattern",
+];
+
+/// Error parsing a rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleError {
+    /// The rule text is malformed.
+    Syntax(String),
+    /// A keyword or feature outside the supported subset.
+    Unsupported(String),
+    /// A keyword's value or placement is invalid.
+    Invalid { keyword: String, message: String },
+    /// Error on line `line` of a rules file.
+    Line { line: usize, error: Box<RuleError> },
+}
+
+impl RuleError {
+    fn invalid(keyword: &str, message: impl Into<String>) -> Self {
+        Self::Invalid {
+            keyword: keyword.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Syntax(message) => write!(f, "{message}"),
+            Self::Unsupported(what) => write!(f, "'{what}' is not supported"),
+            Self::Invalid { keyword, message } => write!(f, "{keyword}: {message}"),
+            Self::Line { line, error } => write!(f, "line {line}: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+/// One `content` match and its modifiers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Content {
+    pub bytes: Vec<u8>,
+    pub nocase: bool,
+    /// Earliest start in the payload.
+    pub offset: Option<usize>,
+    /// The match must end within this many bytes of `offset`.
+    pub depth: Option<usize>,
+    /// Gap after the previous content's match (may be negative).
+    pub distance: Option<isize>,
+    /// The match must end within this many bytes after the previous
+    /// content's match plus `distance`.
+    pub within: Option<usize>,
+}
+
+impl Content {
+    fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            nocase: false,
+            offset: None,
+            depth: None,
+            distance: None,
+            within: None,
+        }
+    }
+
+    /// Positioned relative to the previous content.
+    pub fn is_relative(&self) -> bool {
+        self.distance.is_some() || self.within.is_some()
+    }
+
+    /// Where a match may start and end (exclusive), given where the previous
+    /// content's match ended.
+    fn window(&self, prev_end: usize) -> (usize, Option<usize>) {
+        if self.is_relative() {
+            let base = prev_end as isize + self.distance.unwrap_or(0);
+            let end = self.within.map(|w| (base + w as isize).max(0) as usize);
+            (base.max(0) as usize, end)
+        } else {
+            let start = self.offset.unwrap_or(0);
+            (start, self.depth.map(|d| start + d))
+        }
+    }
+
+    /// Latest end of a match, if `offset`/`depth` fix one.
+    fn fixed_end(&self) -> Option<Bound> {
+        match (self.is_relative(), self.depth) {
+            (false, Some(depth)) => Some(Bound::At((self.offset.unwrap_or(0) + depth) as isize)),
+            _ => None,
+        }
+    }
+}
+
+/// Latest end of some content's match within a rule match.
+#[derive(Debug, Clone, Copy)]
+enum Bound {
+    /// This many bytes after the start of another content's match.
+    After(isize),
+    /// This stream offset.
+    At(isize),
+}
+
+impl Bound {
+    fn shift(self, by: isize) -> Self {
+        match self {
+            Self::After(n) => Self::After(n + by),
+            Self::At(n) => Self::At(n + by),
+        }
+    }
+}
+
+/// How long an occurrence of one content of a rule stays useful. A match
+/// completed later must use an occurrence that ends after the data seen so
+/// far, so once no other content of the rule could end that late, the
+/// occurrence can be dropped.
+#[derive(Debug, Clone, Copy, Default)]
+struct Reach {
+    /// Latest end of another content, relative to this occurrence's start.
+    after: Option<isize>,
+    /// Latest end of another content, as a stream offset.
+    at: Option<isize>,
+}
+
+impl Reach {
+    /// The reach of content `i` of `contents`, or `None` if some other
+    /// content may end arbitrarily far away from it.
+    fn of(contents: &[Content], i: usize) -> Option<Self> {
+        let mut bounds = Vec::new();
+        // Later contents: each relative one ends within `distance + within`
+        // of the previous one's end.
+        let mut prev = Bound::After(contents[i].bytes.len() as isize);
+        for c in &contents[i + 1..] {
+            prev = if c.is_relative() {
+                let within = c.within? as isize;
+                prev.shift(c.distance.unwrap_or(0) + within)
+            } else {
+                c.fixed_end()?
+            };
+            bounds.push(prev);
+        }
+        // Earlier contents: one followed by a relative content ends no later
+        // than that content's start minus its distance.
+        let mut next = Bound::After(contents[i].bytes.len() as isize);
+        for k in (0..i).rev() {
+            let later = &contents[k + 1];
+            let linked = later
+                .is_relative()
+                .then(|| next.shift(-(later.bytes.len() as isize) - later.distance.unwrap_or(0)));
+            next = contents[k].fixed_end().or(linked)?;
+            bounds.push(next);
+        }
+        Some(
+            bounds
+                .into_iter()
+                .fold(Self::default(), |reach, bound| match bound {
+                    Bound::After(n) => Self {
+                        after: reach.after.max(Some(n)),
+                        ..reach
+                    },
+                    Bound::At(n) => Self {
+                        at: reach.at.max(Some(n)),
+                        ..reach
+                    },
+                }),
+        )
+    }
+
+    /// Whether an occurrence starting at `start` may still take part in a
+    /// match once `seen` bytes of the stream have been scanned.
+    fn keeps(&self, start: usize, seen: usize) -> bool {
+        let seen = seen as isize;
+        self.after.is_some_and(|n| start as isize + n > seen) || self.at.is_some_and(|n| n > seen)
+    }
+}
+
+/// A parsed rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub action: String,
+    pub protocol: String,
+    pub msg: Option<String>,
+    pub sid: u32,
+    pub rev: Option<u32>,
+    /// Contents in rule order; relative ones follow the one before.
+    pub contents: Vec<Content>,
+}
+
+impl FromStr for Rule {
+    type Err = RuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (header, body) = s
+            .split_once('(')
+            .ok_or_else(|| RuleError::Syntax("missing '(' before rule options".into()))?;
+        let body = body
+            .trim_end()
+            .strip_suffix(')')
+            .ok_or_else(|| RuleError::Syntax("rule must end with ')'".into()))?;
+
+        let header = header_fields(header)?;
+        let [action, protocol, _, _, direction, _, _] = header[..] else {
+            return Err(RuleError::Syntax(format!(
+                "expected 'action proto src sport -> dst dport', found '{}'",
+                header.join(" ")
+            )));
+        };
+        if !["->", "<>"].contains(&direction) {
+            return Err(RuleError::Syntax(format!(
+                "invalid direction '{direction}'"
+            )));
+        }
+
+        let mut msg = None;
+        let mut sid = None;
+        let mut rev = None;
+        let mut contents: Vec<Content> = Vec::new();
+        for option in split_options(body)? {
+            let (keyword, value) = match option.split_once(':') {
+                Some((k, v)) => (k.trim(), Some(v.trim())),
+                None => (option.trim(), None),
+            };
+            let value_of =
+                |keyword: &str| value.ok_or_else(|| RuleError::invalid(keyword, "missing value"));
+            match keyword {
+                "content" => {
+                    let value = value_of(keyword)?;
+                    if value.starts_with('!') {
+                        return Err(RuleError::Unsupported("negated content".into()));
+                    }
+                    let bytes = content_bytes(value)?;
+                    if bytes.is_empty() {
+                        return Err(RuleError::invalid(keyword, "empty content"));
+                    }
+                    contents.push(Content::new(bytes));
+                }
+                "nocase" | "offset" | "depth" | "distance" | "within" => {
+                    let content = contents
+                        .last_mut()
+                        .ok_or_else(|| RuleError::invalid(keyword, "must follow a content"))?;
+                    modify(content, keyword, value)?;
+                }
+                "msg" => msg = Some(unquote(value_of(keyword)?, keyword)?),
+                "sid" => sid = Some(number(keyword, value_of(keyword)?)?),
+                "rev" => rev = Some(number(keyword, value_of(keyword)?)?),
+                _ if INFO_KEYWORDS.contains(&keyword) => {}
+                _ => return Err(RuleError::Unsupported(keyword.to_string())),
+            }
+        }
+
+        let sid = sid.ok_or_else(|| RuleError::invalid("sid", "rule has no sid"))?;
+        if contents.is_empty() {
+            return Err(RuleError::invalid("content", "rule has no content"));
+        }
+        Ok(Self {
+            action: action.to_string(),
+            protocol: protocol.to_string(),
+            msg,
+            sid,
+            rev,
+            contents,
+        })
+    }
+}
+
+/// Split the rule header at whitespace outside `[...]` lists, which may
+/// contain spaces (`[1.2.3.4, 5.6.7.8]`).
+fn header_fields(header: &str) -> Result<Vec<&str>, RuleError> {
+    let mut fields = Vec::new();
+    let (mut start, mut depth) = (None, 0usize);
+    for (i, c) in header.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or_else(|| RuleError::Syntax("unmatched ']' in rule header".into()))?;
+            }
+            _ if c.is_whitespace() && depth == 0 => {
+                if let Some(s) = start.take() {
+                    fields.push(&header[s..i]);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        start.get_or_insert(i);
+    }
+    if depth > 0 {
+        return Err(RuleError::Syntax("unclosed '[' in rule header".into()));
+    }
+    if let Some(s) = start {
+        fields.push(&header[s..]);
+    }
+    Ok(fields)
+}
+
+/// Split rule options at `;`, skipping quoted and escaped ones.
+fn split_options(body: &str) -> Result<Vec<&str>, RuleError> {
+    let mut options = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (i, c) in body.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                options.push(&body[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if quoted {
+        return Err(RuleError::Syntax("unterminated string".into()));
+    }
+    if !body[start..].trim().is_empty() {
+        return Err(RuleError::Syntax(format!(
+            "option '{}' is missing its ';'",
+            body[start..].trim()
+        )));
+    }
+    options.retain(|o| !o.trim().is_empty());
+    Ok(options)
+}
+
+/// Apply a content modifier.
+fn modify(content: &mut Content, keyword: &str, value: Option<&str>) -> Result<(), RuleError> {
+    let value = || value.ok_or_else(|| RuleError::invalid(keyword, "missing value"));
+    let len = content.bytes.len();
+    let set_twice = || Err(RuleError::invalid(keyword, "set twice for one content"));
+    match keyword {
+        "nocase" => {
+            if content.nocase {
+                return set_twice();
+            }
+            content.nocase = true;
+            return Ok(());
+        }
+        "offset" if content.offset.is_some() => return set_twice(),
+        "depth" if content.depth.is_some() => return set_twice(),
+        "distance" if content.distance.is_some() => return set_twice(),
+        "within" if content.within.is_some() => return set_twice(),
+        "offset" => content.offset = Some(number(keyword, value()?)?),
+        "depth" => {
+            let depth = number(keyword, value()?)?;
+            if depth < len {
+                return Err(RuleError::invalid(
+                    keyword,
+                    format!("{depth} is shorter than the {len}-byte content"),
+                ));
+            }
+            content.depth = Some(depth);
+        }
+        "distance" => content.distance = Some(number(keyword, value()?)?),
+        _ => {
+            let within = number(keyword, value()?)?;
+            if within < len {
+                return Err(RuleError::invalid(
+                    keyword,
+                    format!("{within} is shorter than the {len}-byte content"),
+                ));
+            }
+            content.within = Some(within);
+        }
+    }
+    if content.is_relative() && (content.offset.is_some() || content.depth.is_some()) {
+        return Err(RuleError::invalid(
+            keyword,
+            "offset/depth can't be combined with distance/within",
+        ));
+    }
+    Ok(())
+}
+
+fn number<T: FromStr>(keyword: &str, value: &str) -> Result<T, RuleError> {
+    value
+        .parse()
+        .map_err(|_| RuleError::invalid(keyword, format!("invalid number '{value}'")))
+}
+
+/// Text of a quoted value, with `\` escapes removed.
+fn unquote(value: &str, keyword: &str) -> Result<String, RuleError> {
+    quoted(value, keyword).map(unescape)
+}
+
+/// Text between the quotes of a quoted value.
+fn quoted<'a>(value: &'a str, keyword: &str) -> Result<&'a str, RuleError> {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .ok_or_else(|| RuleError::invalid(keyword, "value must be quoted"))
+}
+
+/// `inner` with `\` escapes removed.
+fn unescape(inner: &str) -> String {
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        out.push(if c == '\\' {
+            chars.next().unwrap_or('\\')
+        } else {
+            c
+        });
+    }
+    out
+}
+
+/// Bytes of a `content` value: quoted text with `|0d 0a|` hex runs.
+fn content_bytes(value: &str) -> Result<Vec<u8>, RuleError> {
+    let text = quoted(value, "content")?;
+    let mut bytes = Vec::with_capacity(text.len());
+    // Split at unescaped `|` before unescaping, so `\|` stays a literal.
+    let mut parts = Vec::new();
+    let (mut start, mut escaped) = (0, false);
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '|' => {
+                parts.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    // Text and hex alternate, starting with text.
+    let mut hex = false;
+    for part in parts {
+        if hex {
+            let digits: Vec<char> = part.chars().filter(|c| !c.is_whitespace()).collect();
+            if !digits.len().is_multiple_of(2) || !digits.iter().all(char::is_ascii_hexdigit) {
+                return Err(RuleError::invalid(
+                    "content",
+                    format!("invalid hex bytes '|{part}|'"),
+                ));
+            }
+            for pair in digits.chunks(2) {
+                let s: String = pair.iter().collect();
+                bytes.push(u8::from_str_radix(&s, 16).unwrap());
+            }
+        } else {
+            bytes.extend_from_slice(unescape(part).as_bytes());
+        }
+        hex = !hex;
+    }
+    if hex {
+        Ok(bytes)
+    } else {
+        Err(RuleError::invalid("content", "unclosed '|'"))
+    }
+}
+
+/// Parse a rules file: one rule per line, `#` comments and blank lines
+/// skipped.
+pub fn parse_rules(text: &str) -> Result<Vec<Rule>, RuleError> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line, rule)| {
+            rule.parse().map_err(|error| RuleError::Line {
+                line,
+                error: Box::new(error),
+            })
+        })
+        .collect()
+}
+
+/// Rules compiled for matching.
+///
+/// # Example
+/// ```
+/// use packet_match_fuzz::RuleSet;
+///
+/// let rules: RuleSet = r#"
+/// alert tcp any any -> any 80 (msg:"admin"; content:"GET "; depth:4; content:"/admin"; nocase; distance:0; sid:1;)
+/// alert tcp any any -> any any (content:"|de ad be ef|"; sid:2;)
+/// "#
+/// .parse()
+/// .unwrap();
+/// assert_eq!(rules.matches(b"GET /ADMIN HTTP/1.1"), vec![1]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+    /// Pattern id of each rule's contents: ids below `exact.pattern_count()`
+    /// belong to `exact`, the rest to `nocase`.
+    ids: Vec<Vec<usize>>,
+    /// For each pattern id, the rules using it and how long its
+    /// occurrences matter to each (`None`: for the whole stream).
+    users: Vec<Vec<(usize, Option<Reach>)>>,
+    exact: MultiMatcher,
+    /// Lowercased `nocase` contents, matched against lowercased input.
+    nocase: MultiMatcher,
+}
+
+impl RuleSet {
+    /// Compile `rules`.
+    ///
+    /// # Panics
+    /// Panics if a rule has an empty content, which parsed rules never do.
+    pub fn new(rules: Vec<Rule>) -> Self {
+        let mut exact: Vec<Vec<u8>> = Vec::new();
+        let mut nocase: Vec<Vec<u8>> = Vec::new();
+        let mut seen: HashMap<(Vec<u8>, bool), usize> = HashMap::new();
+        let local_ids: Vec<Vec<(bool, usize)>> = rules
+            .iter()
+            .map(|rule| {
+                rule.contents
+                    .iter()
+                    .map(|c| {
+                        let (list, bytes) = if c.nocase {
+                            (&mut nocase, c.bytes.to_ascii_lowercase())
+                        } else {
+                            (&mut exact, c.bytes.clone())
+                        };
+                        let id = *seen.entry((bytes.clone(), c.nocase)).or_insert_with(|| {
+                            list.push(bytes);
+                            list.len() - 1
+                        });
+                        (c.nocase, id)
+                    })
+                    .collect()
+            })
+            .collect();
+        let exact_count = exact.len();
+        let ids: Vec<Vec<usize>> = local_ids
+            .into_iter()
+            .map(|ids| {
+                ids.into_iter()
+                    .map(|(nocase, id)| if nocase { exact_count + id } else { id })
+                    .collect()
+            })
+            .collect();
+        let mut users = vec![Vec::new(); exact_count + nocase.len()];
+        for (index, rule) in rules.iter().enumerate() {
+            for (i, &id) in ids[index].iter().enumerate() {
+                users[id].push((index, Reach::of(&rule.contents, i)));
+            }
+        }
+        Self {
+            rules,
+            ids,
+            users,
+            exact: MultiMatcher::new(exact),
+            nocase: MultiMatcher::new(nocase),
+        }
+    }
+
+    /// Return the SIDs of the rules that fire on `payload`, in rule order.
+    pub fn matches(&self, payload: &[u8]) -> Vec<u32> {
+        let mut stream = self.stream();
+        stream.feed(payload)
+    }
+
+    /// Start matching a stream.
+    pub fn stream(&self) -> RuleStreamMatcher<'_> {
+        RuleStreamMatcher {
+            rules: self,
+            exact_state: ROOT,
+            nocase_state: ROOT,
+            occurrences: vec![Vec::new(); self.pattern_count()],
+            held: Vec::new(),
+            fired: vec![false; self.rules.len()],
+            global_offset: 0,
+        }
+    }
+
+    /// The compiled rules, in file order.
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    fn pattern_count(&self) -> usize {
+        self.exact.pattern_count() + self.nocase.pattern_count()
+    }
+
+    /// Whether rule `index` is satisfied by the content `occurrences`.
+    fn fires(&self, index: usize, occurrences: &[Vec<usize>]) -> bool {
+        let search = Search {
+            contents: &self.rules[index].contents,
+            ids: &self.ids[index],
+            occurrences,
+        };
+        search.from(0, 0, &mut HashSet::new())
+    }
+}
+
+impl FromStr for RuleSet {
+    type Err = RuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_rules(s).map(Self::new)
+    }
+}
+
+/// Backtracking search for one rule: try each occurrence of a content that
+/// fits its window, then the next content after it.
+struct Search<'a> {
+    contents: &'a [Content],
+    ids: &'a [usize],
+    occurrences: &'a [Vec<usize>],
+}
+
+impl Search<'_> {
+    /// Can contents `i..` match after a match ending at `prev_end`?
+    /// `failed` remembers dead ends so repeated occurrences stay cheap.
+    fn from(&self, i: usize, prev_end: usize, failed: &mut HashSet<(usize, usize)>) -> bool {
+        let Some(content) = self.contents.get(i) else {
+            return true;
+        };
+        if failed.contains(&(i, prev_end)) {
+            return false;
+        }
+        let (first, end) = content.window(prev_end);
+        let len = content.bytes.len();
+        let starts = &self.occurrences[self.ids[i]];
+        for &start in &starts[starts.partition_point(|&s| s < first)..] {
+            if end.is_some_and(|end| start + len > end) {
+                break;
+            }
+            if self.from(i + 1, start + len, failed) {
+                return true;
+            }
+        }
+        failed.insert((i, prev_end));
+        false
+    }
+}
+
+/// Matches a [`RuleSet`] against a stream fed in chunks; offsets, depths and
+/// relative modifiers span chunk boundaries as they would on the whole
+/// stream. Each rule fires at most once per stream.
+///
+/// Content occurrences are kept only while a rule that has not fired could
+/// still use them, so memory stays bounded for rules whose contents all
+/// carry `depth` or `within`.
+#[derive(Debug, Clone)]
+pub struct RuleStreamMatcher<'a> {
+    rules: &'a RuleSet,
+    exact_state: usize,
+    nocase_state: usize,
+    /// Global start offsets of each content pattern that may still be part
+    /// of a match.
+    occurrences: Vec<Vec<usize>>,
+    /// Pattern ids with a non-empty entry in `occurrences`.
+    held: Vec<usize>,
+    fired: Vec<bool>,
+    /// Total bytes fed so far (for global offset reporting).
+    global_offset: usize,
+}
+
+impl RuleStreamMatcher<'_> {
+    /// Feed a chunk of data and return the SIDs of the rules that fired
+    /// because of it, in rule order.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<u32> {
+        let set = self.rules;
+        let mut found = Vec::new();
+        self.exact_state = set
+            .exact
+            .scan(self.exact_state, self.global_offset, chunk, &mut found);
+        let exact = found.len();
+        self.nocase_state = set.nocase.scan(
+            self.nocase_state,
+            self.global_offset,
+            &chunk.to_ascii_lowercase(),
+            &mut found,
+        );
+        self.global_offset += chunk.len();
+
+        let nocase_base = set.exact.pattern_count();
+        let mut touched = Vec::new();
+        for (i, (id, start)) in found.into_iter().enumerate() {
+            let id = if i < exact { id } else { nocase_base + id };
+            if self.occurrences[id].is_empty() {
+                self.held.push(id);
+            }
+            self.occurrences[id].push(start);
+            touched.extend(set.users[id].iter().map(|&(index, _)| index));
+        }
+
+        // Only rules with a new occurrence can have become satisfied.
+        touched.sort_unstable();
+        touched.dedup();
+        let mut sids = Vec::new();
+        for index in touched {
+            if !self.fired[index] && set.fires(index, &self.occurrences) {
+                self.fired[index] = true;
+                sids.push(set.rules[index].sid);
+            }
+        }
+        self.prune();
+        sids
+    }
+
+    /// Drop occurrences no unfired rule can use anymore.
+    fn prune(&mut self) {
+        let (set, seen) = (self.rules, self.global_offset);
+        let (occurrences, fired) = (&mut self.occurrences, &self.fired);
+        self.held.retain(|&id| {
+            let reaches: Option<Vec<Reach>> = set.users[id]
+                .iter()
+                .filter(|&&(index, _)| !fired[index])
+                .map(|&(_, reach)| reach)
+                .collect();
+            if let Some(reaches) = reaches {
+                occurrences[id].retain(|&start| reaches.iter().any(|r| r.keeps(start, seen)));
+            }
+            !occurrences[id].is_empty()
+        });
+    }
+
+    /// Forget the stream so far (but keep the rules).
+    pub fn reset(&mut self) {
+        self.exact_state = ROOT;
+        self.nocase_state = ROOT;
+        self.occurrences.iter_mut().for_each(Vec::clear);
+        self.held.clear();
+        self.fired.iter_mut().for_each(|f| *f = false);
+        self.global_offset = 0;
+    }
+
+    /// Total bytes processed so far.
+    pub fn bytes_processed(&self) -> usize {
+        self.global_offset
+    }
+}
+
+impl ChunkMatcher for RuleStreamMatcher<'_> {
+    type Match = u32;
+
+    fn feed(&mut self, chunk: &[u8]) -> Vec<u32> {
+        RuleStreamMatcher::feed(self, chunk)
+    }
+
+    fn skip(&mut self, len: usize) {
+        self.exact_state = ROOT;
+        self.nocase_state = ROOT;
+        self.global_offset += len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(options: &str) -> Result<Rule, RuleError> {
+        format!("alert tcp any any -> any any ({options})").parse()
+    }
+
+    fn set(rules: &[&str]) -> RuleSet {
+        RuleSet::new(rules.iter().map(|r| rule(r).unwrap()).collect())
+    }
+
+    #[test]
+    fn parses_a_rule() {
+        let r: Rule = r#"alert tcp $HOME_NET any -> $EXTERNAL_NET 80 (msg:"GET \"admin\"; probe"; content:"GET|20|"; offset:0; depth:8; content:"/admin"; nocase; distance:0; within:20; classtype:web-application-attack; sid:1000001; rev:2;)"#
+            .parse()
+            .unwrap();
+        assert_eq!(r.action, "alert");
+        assert_eq!(r.protocol, "tcp");
+        assert_eq!(r.msg.as_deref(), Some(r#"GET "admin"; probe"#));
+        assert_eq!((r.sid, r.rev), (1000001, Some(2)));
+        assert_eq!(r.contents.len(), 2);
+        assert_eq!(r.contents[0].bytes, b"GET ");
+        assert_eq!(
+            (r.contents[0].offset, r.contents[0].depth),
+            (Some(0), Some(8))
+        );
+        assert!(r.contents[1].nocase && r.contents[1].is_relative());
+        assert_eq!(
+            (r.contents[1].distance, r.contents[1].within),
+            (Some(0), Some(20))
+        );
+    }
+
+    #[test]
+    fn hex_content() {
+        let r = rule(r#"content:"A|0d 0A|B|00|"; sid:1;"#).unwrap();
+        assert_eq!(r.contents[0].bytes, b"A\r\nB\0");
+        let r = rule(r#"content:"a\|b|7c|\;"; sid:1;"#).unwrap();
+        assert_eq!(r.contents[0].bytes, b"a|b|;");
+    }
+
+    #[test]
+    fn header_lists_may_contain_spaces() {
+        let r: Rule = "alert tcp [10.0.0.1, 10.0.0.2] any -> $HOME_NET [80, 443,[8000:8080]] (content:\"a\"; sid:1;)"
+            .parse()
+            .unwrap();
+        assert_eq!((r.action.as_str(), r.sid), ("alert", 1));
+        assert!(matches!(
+            "alert tcp [10.0.0.1, any -> any any (content:\"a\"; sid:1;)".parse::<Rule>(),
+            Err(RuleError::Syntax(_))
+        ));
+    }
+
+    #[test]
+    fn offset_and_depth() {
+        let rules = set(&[r#"content:"GET"; offset:2; depth:5; sid:1;"#]);
+        assert_eq!(rules.matches(b"xxGETyy"), vec![1]);
+        assert!(rules.matches(b"xGETyyy").is_empty());
+        assert!(rules.matches(b"xxxxxGET").is_empty());
+        assert_eq!(rules.matches(b"xxxxGET"), vec![1]);
+    }
+
+    #[test]
+    fn nocase() {
+        let rules = set(&[
+            r#"content:"admin"; nocase; sid:1;"#,
+            r#"content:"admin"; sid:2;"#,
+        ]);
+        assert_eq!(rules.matches(b"/ADMIN"), vec![1]);
+        assert_eq!(rules.matches(b"/admin"), vec![1, 2]);
+    }
+
+    #[test]
+    fn distance_and_within_backtrack() {
+        let rules = set(&[r#"content:"user"; content:"root"; distance:1; within:6; sid:7;"#]);
+        // The first "user" is too far from "root"; the second one fits.
+        assert_eq!(rules.matches(b"user xxxxxxxx user=root"), vec![7]);
+        assert!(rules.matches(b"user xxxxxxxx root").is_empty());
+        // distance:1 needs a byte in between.
+        assert!(rules.matches(b"userroot").is_empty());
+        // Without relative modifiers the order doesn't matter.
+        let unordered = set(&[r#"content:"user"; content:"root"; sid:8;"#]);
+        assert_eq!(unordered.matches(b"root user"), vec![8]);
+    }
+
+    #[test]
+    fn stream_fires_across_chunks_once() {
+        let rules = set(&[
+            r#"content:"GET "; depth:4; content:"/admin"; distance:0; sid:1;"#,
+            r#"content:"|0d 0a 0d 0a|"; sid:2;"#,
+        ]);
+        let mut stream = rules.stream();
+        assert!(stream.feed(b"GE").is_empty());
+        assert!(stream.feed(b"T /ad").is_empty());
+        assert_eq!(stream.feed(b"min HTTP/1.1\r\n\r"), vec![1]);
+        assert_eq!(stream.feed(b"\nGET /admin\r\n\r\n"), vec![2]);
+        assert_eq!(stream.bytes_processed(), 37);
+        stream.reset();
+        assert!(stream.feed(b"x").is_empty());
+        // depth is measured from the start of the stream, not the chunk.
+        assert!(stream.feed(b"GET /admin").is_empty());
+    }
+
+    #[test]
+    fn stream_drops_occurrences_no_rule_can_use() {
+        let rules = set(&[
+            r#"content:"user"; content:"root"; distance:1; within:6; sid:1;"#,
+            r#"content:"GET"; depth:3; sid:2;"#,
+        ]);
+        let mut stream = rules.stream();
+        for _ in 0..100 {
+            assert!(stream.feed(b"xxxxxxxx GET user").is_empty());
+        }
+        // Only the last "user" could still be followed by "root".
+        assert_eq!(stream.occurrences.iter().map(Vec::len).sum::<usize>(), 1);
+        assert_eq!(stream.feed(b"user=root"), vec![1]);
+        assert_eq!(stream.held, Vec::<usize>::new());
+
+        // Without `within` a later "root" may pair with any "user".
+        let unbounded = set(&[r#"content:"user"; content:"root"; distance:1; sid:3;"#]);
+        let mut stream = unbounded.stream();
+        stream.feed(b"user ");
+        stream.feed(&[b'x'; 1000]);
+        assert_eq!(stream.feed(b"root"), vec![3]);
+    }
+
+    #[test]
+    fn stream_keeps_occurrences_a_negative_distance_reaches_back_to() {
+        // "Z" may come up to five bytes before the end of "XY".
+        let rules = set(&[r#"content:"XY"; content:"Z"; distance:-5; within:6; sid:1;"#]);
+        assert_eq!(rules.matches(b"ZabXY"), vec![1]);
+        let mut stream = rules.stream();
+        assert!(stream.feed(b"Z").is_empty());
+        assert!(stream.feed(b"ab").is_empty());
+        assert_eq!(stream.feed(b"XY"), vec![1]);
+
+        let mut stream = rules.stream();
+        stream.feed(b"Z");
+        stream.feed(b"abcd");
+        assert!(stream.occurrences.iter().all(Vec::is_empty));
+        assert!(stream.feed(b"XY").is_empty());
+    }
+
+    #[test]
+    fn rules_file() {
+        let rules: RuleSet = "# lab rules\n\n\
+            alert tcp any any -> any any (content:\"a\"; sid:1;)\n\
+            alert udp any any <> any 53 (content:\"b\"; sid:2;)\n"
+            .parse()
+            .unwrap();
+        assert_eq!(rules.rules().len(), 2);
+        assert_eq!(rules.matches(b"ba"), vec![1, 2]);
+
+        let err =
+            parse_rules("alert tcp any any -> any any (content:\"a\"; sid:1;)\n\nbad").unwrap_err();
+        assert!(matches!(err, RuleError::Line { line: 3, .. }), "{err:?}");
+    }
+
+    #[test]
+    fn errors() {
+        let unsupported = |r: Result<Rule, RuleError>| match r {
+            Err(RuleError::Unsupported(what)) => what,
+            other => panic!("expected unsupported, got {other:?}"),
+        };
+        assert_eq!(unsupported(rule(r#"pcre:"/a+/"; sid:1;"#)), "pcre");
+        assert_eq!(
+            unsupported(rule(r#"content:"a"; http_uri; sid:1;"#)),
+            "http_uri"
+        );
+        assert_eq!(
+            unsupported(rule(r#"content:!"a"; sid:1;"#)),
+            "negated content"
+        );
+        assert_eq!(
+            rule(r#"pcre:"/a+/"; sid:1;"#).unwrap_err().to_string(),
+            "'pcre' is not supported"
+        );
+
+        let invalid = |r: Result<Rule, RuleError>| match r {
+            Err(RuleError::Invalid { keyword, message }) => format!("{keyword}: {message}"),
+            other => panic!("expected invalid, got {other:?}"),
+        };
+        assert_eq!(
+            invalid(rule("nocase; sid:1;")),
+            "nocase: must follow a content"
+        );
+        assert_eq!(invalid(rule(r#"content:"a";"#)), "sid: rule has no sid");
+        assert_eq!(invalid(rule("sid:1;")), "content: rule has no content");
+        assert_eq!(
+            invalid(rule(r#"content:"a"; sid:x;"#)),
+            "sid: invalid number 'x'"
+        );
+        assert_eq!(
+            invalid(rule(r#"content:"abc"; depth:2; sid:1;"#)),
+            "depth: 2 is shorter than the 3-byte content"
+        );
+        assert_eq!(
+            invalid(rule(r#"content:"a"; offset:1; distance:2; sid:1;"#)),
+            "distance: offset/depth can't be combined with distance/within"
+        );
+        assert_eq!(
+            invalid(rule(r#"content:"|0d 0|"; sid:1;"#)),
+            "content: invalid hex bytes '|0d 0|'"
+        );
+        assert_eq!(
+            invalid(rule(r#"content:"a"; nocase; nocase; sid:1;"#)),
+            "nocase: set twice for one content"
+        );
+
+        assert!(matches!(
+            "alert tcp any -> any any (sid:1;)".parse::<Rule>(),
+            Err(RuleError::Syntax(_))
+        ));
+        assert!(matches!(
+            rule(r#"content:"a"; sid:1"#),
+            Err(RuleError::Syntax(_))
+        ));
+    }
+}
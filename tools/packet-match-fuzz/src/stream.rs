@@ -60,6 +60,33 @@ impl StreamMatcher {
     }
 }
 
+/// A matcher fed a stream chunk by chunk, so that reassembly can drive any
+/// of the crate's streaming matchers.
+pub trait ChunkMatcher {
+    /// What a match reports.
+    type Match;
+
+    /// Feed the next chunk and return the matches it completed.
+    fn feed(&mut self, chunk: &[u8]) -> Vec<Self::Match>;
+
+    /// Account for `len` missing bytes: no match spans them, but later
+    /// offsets still count them.
+    fn skip(&mut self, len: usize);
+}
+
+impl ChunkMatcher for StreamMatcher {
+    type Match = usize;
+
+    fn feed(&mut self, chunk: &[u8]) -> Vec<usize> {
+        StreamMatcher::feed(self, chunk)
+    }
+
+    fn skip(&mut self, len: usize) {
+        self.state = 0;
+        self.global_offset += len;
+    }
+}
+
 /// Re-build failure table (we keep the function private here to avoid
 /// exposing internals; the cost is negligible compared to I/O).
 fn build_failure_table(pattern: &[u8]) -> Vec<usize> {
@@ -103,6 +130,14 @@ mod tests {
         assert_eq!(sm.bytes_processed(), 7);
     }
 
+    #[test]
+    fn skip_breaks_partial_matches() {
+        let mut sm = StreamMatcher::new(b"AB");
+        sm.feed(b"xA");
+        ChunkMatcher::skip(&mut sm, 3);
+        assert_eq!(sm.feed(b"BAB"), vec![6]);
+    }
+
     #[test]
     fn reset_clears_state() {
         let mut sm = StreamMatcher::new(b"AB");
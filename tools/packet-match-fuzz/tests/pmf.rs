@@ -0,0 +1,133 @@
+use std::process::Command;
+
+fn tcp_frame(src: [u8; 4], dst: [u8; 4], ports: (u16, u16), seq: u32, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0; 12];
+    frame.extend([0x08, 0x00, 0x45, 0]);
+    frame.extend((40 + payload.len() as u16).to_be_bytes());
+    frame.extend([0, 0, 0x40, 0, 64, 6, 0, 0]);
+    frame.extend(src);
+    frame.extend(dst);
+    frame.extend(ports.0.to_be_bytes());
+    frame.extend(ports.1.to_be_bytes());
+    frame.extend(seq.to_be_bytes());
+    frame.extend([0, 0, 0, 0, 0x50, 0x18, 0xff, 0xff, 0, 0, 0, 0]);
+    frame.extend(payload);
+    frame
+}
+
+/// Capture of `frames`, each cut to `snaplen` bytes.
+fn write_pcap(name: &str, frames: &[Vec<u8>], snaplen: usize) -> std::path::PathBuf {
+    let mut file = Vec::new();
+    for field in [0xa1b2_c3d4u32, 0x0004_0002, 0, 0, snaplen as u32, 1] {
+        file.extend(field.to_le_bytes());
+    }
+    for frame in frames {
+        let captured = &frame[..frame.len().min(snaplen)];
+        for field in [0, 0, captured.len() as u32, frame.len() as u32] {
+            file.extend(field.to_le_bytes());
+        }
+        file.extend(captured);
+    }
+    let path = std::env::temp_dir().join(format!("pmf-{}-{name}.pcap", std::process::id()));
+    std::fs::write(&path, file).unwrap();
+    path
+}
+
+#[test]
+fn scan_reports_matches_across_segments() {
+    let (client, server) = ([10, 0, 0, 1], [10, 0, 0, 2]);
+    let path = write_pcap(
+        "split",
+        &[
+            tcp_frame(client, server, (40000, 80), 1000, b"GET / HT"),
+            tcp_frame(client, server, (40000, 80), 1008, b"TP/1.1\r\n"),
+            tcp_frame(server, client, (80, 40000), 7000, b"HTTP/1.1 200 OK"),
+        ],
+        65535,
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_pmf"))
+        .args([
+            "scan",
+            path.to_str().unwrap(),
+            "-p",
+            "HTTP/",
+            "-p",
+            "\\r\\n",
+        ])
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "#2 tcp 10.0.0.1:40000 -> 10.0.0.2:80 stream offset 6: \"HTTP/\"\n\
+         #2 tcp 10.0.0.1:40000 -> 10.0.0.2:80 stream offset 14: \"\\r\\n\"\n\
+         #3 tcp 10.0.0.2:80 -> 10.0.0.1:40000 stream offset 0: \"HTTP/\"\n"
+    );
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "3 packets (0 not TCP/UDP, 0 IP fragments, 0 truncated), 3 matches\n"
+    );
+}
+
+#[test]
+fn scan_skips_payload_cut_off_by_the_snap_length() {
+    let (client, server) = ([10, 0, 0, 1], [10, 0, 0, 2]);
+    let path = write_pcap(
+        "snaplen",
+        &[
+            // 6 of 20 payload bytes fit in the 60-byte snap length.
+            tcp_frame(client, server, (40000, 80), 1000, &[b'x'; 20]),
+            tcp_frame(client, server, (40000, 80), 1020, b"HTTP/"),
+        ],
+        60,
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_pmf"))
+        .args(["scan", path.to_str().unwrap(), "-p", "HTTP/"])
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "#2 tcp 10.0.0.1:40000 -> 10.0.0.2:80 stream offset 20: \"HTTP/\"\n"
+    );
+}
+
+#[test]
+fn scan_counts_packets_it_skips() {
+    let (client, server) = ([10, 0, 0, 1], [10, 0, 0, 2]);
+    let mut fragment = tcp_frame(client, server, (40000, 80), 0, b"x");
+    fragment[20] = 0x20; // more fragments
+    let mut arp = tcp_frame(client, server, (40000, 80), 0, b"");
+    arp[12..14].copy_from_slice(&[0x08, 0x06]);
+    arp.truncate(40);
+    // Cut inside the TCP header.
+    let truncated = tcp_frame(client, server, (40000, 80), 0, &[b'x'; 100]);
+    let path = write_pcap("skipped", &[fragment, arp, truncated], 40);
+    let output = Command::new(env!("CARGO_BIN_EXE_pmf"))
+        .args(["scan", path.to_str().unwrap(), "-p", "x"])
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "3 packets (1 not TCP/UDP, 1 IP fragments, 1 truncated), 0 matches\n"
+    );
+}
+
+#[test]
+fn scan_rejects_missing_file() {
+    let output = Command::new(env!("CARGO_BIN_EXE_pmf"))
+        .args(["scan", "/nonexistent/capture.pcap", "-p", "x"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("pmf: /nonexistent/capture.pcap: "));
+}
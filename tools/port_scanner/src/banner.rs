@@ -0,0 +1,404 @@
+//! Service banner grabbing and lightweight protocol probes.
+//!
+//! Protocols that greet first (SSH, SMTP, FTP, POP3, IMAP) are identified
+//! from whatever the server sends after connect. If the server stays silent,
+//! one probe is sent depending on the port: a TLS ClientHello on well-known
+//! TLS ports, a Redis `PING` on 6379 and an HTTP `HEAD /` everywhere else.
+//! The HTTP probe doubles as a fallback detector — Redis answers it with
+//! `-ERR` and TLS servers with an alert record.
+
+use std::time::Duration;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+/// Longest banner line kept in results.
+const MAX_BANNER_LEN: usize = 128;
+
+/// What was detected listening on an open port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceInfo {
+    /// Short protocol name (`ssh`, `http`, `tls`, ...), `unknown` if the
+    /// reply matched nothing.
+    pub name: String,
+    /// Product / version string when the protocol exposes one.
+    pub version: Option<String>,
+    /// First line of the raw reply, sanitised to printable ASCII.
+    pub banner: Option<String>,
+}
+
+impl ServiceInfo {
+    fn new(name: &str, version: Option<String>, banner: Option<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            version,
+            banner,
+        }
+    }
+}
+
+/// Probe sent to a silent server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Probe {
+    Http,
+    Tls,
+    Redis,
+}
+
+impl Probe {
+    fn for_port(port: u16) -> Self {
+        match port {
+            443 | 465 | 636 | 853 | 993 | 995 | 8443 | 9443 => Self::Tls,
+            6379 => Self::Redis,
+            _ => Self::Http,
+        }
+    }
+
+    fn payload(self) -> Vec<u8> {
+        match self {
+            Self::Http => b"HEAD / HTTP/1.0\r\n\r\n".to_vec(),
+            Self::Tls => client_hello(),
+            Self::Redis => b"PING\r\n".to_vec(),
+        }
+    }
+}
+
+/// Ports whose services never talk first; skip the passive wait on them.
+fn is_silent_port(port: u16) -> bool {
+    matches!(
+        port,
+        80 | 443 | 465 | 636 | 853 | 993 | 995 | 6379 | 8000 | 8008 | 8080 | 8443 | 8888 | 9443
+    )
+}
+
+/// Read a greeting from `stream` (or probe it) and identify the service.
+///
+/// `wait` bounds each read; a silent server with no recognised reply to
+/// the probe yields `None`.
+pub async fn grab_banner(mut stream: TcpStream, port: u16, wait: Duration) -> Option<ServiceInfo> {
+    let mut buf = vec![0u8; 4096];
+
+    if !is_silent_port(port) {
+        if let Some(n) = read_some(&mut stream, &mut buf, wait).await {
+            return Some(identify(&buf[..n], port));
+        }
+    }
+
+    let probe = Probe::for_port(port);
+    stream.write_all(&probe.payload()).await.ok()?;
+    let n = read_some(&mut stream, &mut buf, wait).await?;
+    Some(identify(&buf[..n], port))
+}
+
+async fn read_some(stream: &mut TcpStream, buf: &mut [u8], wait: Duration) -> Option<usize> {
+    match timeout(wait, stream.read(buf)).await {
+        Ok(Ok(n)) if n > 0 => Some(n),
+        _ => None,
+    }
+}
+
+/// Classify a server reply. `port` only breaks ties between protocols that
+/// share a greeting format (`220` for FTP vs SMTP).
+pub fn identify(data: &[u8], port: u16) -> ServiceInfo {
+    if let Some(info) = identify_tls(data) {
+        return info;
+    }
+
+    let line = first_line(data);
+    let banner = Some(line.clone()).filter(|l| !l.is_empty());
+
+    if let Some(rest) = line.strip_prefix("SSH-") {
+        // SSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13
+        let software = rest.split_once('-').map(|(_, s)| s.to_string());
+        return ServiceInfo::new("ssh", software, banner);
+    }
+    if line.starts_with("HTTP/") {
+        let server = header_value(data, "server");
+        return ServiceInfo::new("http", server, banner);
+    }
+    if line.starts_with("+PONG") || line.starts_with("-ERR") || line.starts_with("-NOAUTH") {
+        return ServiceInfo::new("redis", None, banner);
+    }
+    if let Some(rest) = line.strip_prefix("+OK") {
+        return ServiceInfo::new("pop3", non_empty(rest), banner);
+    }
+    if let Some(rest) = line.strip_prefix("* OK") {
+        return ServiceInfo::new("imap", non_empty(rest), banner);
+    }
+    if let Some(rest) = line
+        .strip_prefix("220-")
+        .or_else(|| line.strip_prefix("220 "))
+    {
+        let upper = rest.to_ascii_uppercase();
+        let name = if upper.contains("SMTP") || upper.contains("MAIL") {
+            "smtp"
+        } else if upper.contains("FTP") {
+            "ftp"
+        } else {
+            match port {
+                25 | 465 | 587 | 2525 => "smtp",
+                _ => "ftp",
+            }
+        };
+        return ServiceInfo::new(name, non_empty(rest), banner);
+    }
+    ServiceInfo::new("unknown", None, banner)
+}
+
+/// Recognise a TLS handshake or alert record and extract the version.
+pub(crate) fn identify_tls(data: &[u8]) -> Option<ServiceInfo> {
+    if data.len() < 5 || data[1] != 0x03 {
+        return None;
+    }
+    match data[0] {
+        // Handshake record: expect a ServerHello.
+        0x16 if data.len() >= 11 && data[5] == 0x02 => {
+            let version = server_hello_version(data).unwrap_or([data[9], data[10]]);
+            Some(ServiceInfo::new(
+                "tls",
+                Some(tls_version_name(version)),
+                None,
+            ))
+        }
+        // Alert record, e.g. in reply to a plaintext probe.
+        0x15 => Some(ServiceInfo::new("tls", None, None)),
+        _ => None,
+    }
+}
+
+/// Negotiated version from a ServerHello, honouring the TLS 1.3
+/// `supported_versions` extension.
+fn server_hello_version(data: &[u8]) -> Option<[u8; 2]> {
+    let body = data.get(9..)?;
+    let legacy = [*body.first()?, *body.get(1)?];
+    // version(2) + random(32)
+    let mut i = 34;
+    let sid_len = *body.get(i)? as usize;
+    i += 1 + sid_len;
+    // cipher suite(2) + compression(1)
+    i += 3;
+    let ext_len = u16::from_be_bytes([*body.get(i)?, *body.get(i + 1)?]) as usize;
+    i += 2;
+    let end = (i + ext_len).min(body.len());
+    while i + 4 <= end {
+        let ty = u16::from_be_bytes([body[i], body[i + 1]]);
+        let len = u16::from_be_bytes([body[i + 2], body[i + 3]]) as usize;
+        i += 4;
+        if ty == 0x002b && len == 2 && i + 2 <= end {
+            return Some([body[i], body[i + 1]]);
+        }
+        i += len;
+    }
+    Some(legacy)
+}
+
+fn tls_version_name(v: [u8; 2]) -> String {
+    match v {
+        [3, 0] => "SSL 3.0".into(),
+        [3, 1] => "TLS 1.0".into(),
+        [3, 2] => "TLS 1.1".into(),
+        [3, 3] => "TLS 1.2".into(),
+        [3, 4] => "TLS 1.3".into(),
+        [a, b] => format!("0x{a:02x}{b:02x}"),
+    }
+}
+
+/// Minimal TLS 1.2/1.3 ClientHello with common suites and no SNI.
+fn client_hello() -> Vec<u8> {
+    build_client_hello(
+        &[
+            0x1301, 0x1302, 0x1303, 0xc02b, 0xc02f, 0xc02c, 0xc030, 0xcca9, 0xcca8, 0x009c, 0x002f,
+            0x0035,
+        ],
+        // supported_versions: TLS 1.3, TLS 1.2
+        Some(&[0x04, 0x03, 0x04, 0x03, 0x03]),
+    )
+}
+
+/// ClientHello offering TLS 1.0 to 1.2 with the CBC, 3DES and RC4 suites
+/// old servers are limited to. Without `supported_versions` the server
+/// answers with the highest version up to TLS 1.2 it speaks.
+pub(crate) fn legacy_client_hello() -> Vec<u8> {
+    build_client_hello(
+        &[
+            0xc02f, 0xc02b, 0xc013, 0xc009, 0xc014, 0xc00a, 0x009c, 0x002f, 0x0035, 0x0033, 0x0039,
+            0x000a, 0x0005, 0x0004,
+        ],
+        None,
+    )
+}
+
+fn build_client_hello(ciphers: &[u16], supported_versions: Option<&[u8]>) -> Vec<u8> {
+    let mut ext = Vec::new();
+    // supported_groups: x25519, secp256r1, secp384r1
+    push_ext(
+        &mut ext,
+        0x000a,
+        &[0x00, 0x06, 0x00, 0x1d, 0x00, 0x17, 0x00, 0x18],
+    );
+    // ec_point_formats: uncompressed
+    push_ext(&mut ext, 0x000b, &[0x01, 0x00]);
+    // signature_algorithms
+    push_ext(
+        &mut ext,
+        0x000d,
+        &[
+            0x00, 0x0c, 0x04, 0x03, 0x08, 0x04, 0x04, 0x01, 0x05, 0x03, 0x08, 0x05, 0x05, 0x01,
+        ],
+    );
+    if let Some(versions) = supported_versions {
+        push_ext(&mut ext, 0x002b, versions);
+    }
+
+    let mut hello = vec![0x03, 0x03];
+    hello.extend((0u8..32).map(|i| i.wrapping_mul(37).wrapping_add(11)));
+    hello.push(0); // session id
+    hello.extend(((ciphers.len() * 2) as u16).to_be_bytes());
+    for c in ciphers {
+        hello.extend(c.to_be_bytes());
+    }
+    hello.extend([0x01, 0x00]); // null compression
+    hello.extend((ext.len() as u16).to_be_bytes());
+    hello.extend(ext);
+
+    let mut handshake = vec![0x01];
+    handshake.extend(&(hello.len() as u32).to_be_bytes()[1..]);
+    handshake.extend(hello);
+
+    let mut record = vec![0x16, 0x03, 0x01];
+    record.extend((handshake.len() as u16).to_be_bytes());
+    record.extend(handshake);
+    record
+}
+
+fn push_ext(out: &mut Vec<u8>, ty: u16, body: &[u8]) {
+    out.extend(ty.to_be_bytes());
+    out.extend((body.len() as u16).to_be_bytes());
+    out.extend(body);
+}
+
+/// First line of `data` as printable ASCII, truncated to [`MAX_BANNER_LEN`].
+fn first_line(data: &[u8]) -> String {
+    data.split(|&b| b == b'\n')
+        .next()
+        .unwrap_or_default()
+        .iter()
+        .filter(|b| b.is_ascii_graphic() || **b == b' ')
+        .take(MAX_BANNER_LEN)
+        .map(|&b| b as char)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Value of an HTTP header (case-insensitive name) in a raw response.
+fn header_value(data: &[u8], name: &str) -> Option<String> {
+    let text = String::from_utf8_lossy(data);
+    text.lines().skip(1).find_map(|l| {
+        let (k, v) = l.split_once(':')?;
+        k.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| v.trim().to_string())
+    })
+}
+
+fn non_empty(s: &str) -> Option<String> {
+    let s = s.trim();
+    (!s.is_empty()).then(|| s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ssh_greeting() {
+        let info = identify(b"SSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13\r\n", 22);
+        assert_eq!(info.name, "ssh");
+        assert_eq!(
+            info.version.as_deref(),
+            Some("OpenSSH_9.6p1 Ubuntu-3ubuntu13")
+        );
+    }
+
+    #[test]
+    fn smtp_vs_ftp_220() {
+        assert_eq!(
+            identify(b"220 mx.example.org ESMTP Postfix\r\n", 25).name,
+            "smtp"
+        );
+        assert_eq!(identify(b"220 (vsFTPd 3.0.5)\r\n", 21).name, "ftp");
+        // No keyword: fall back on the port.
+        assert_eq!(identify(b"220 ready\r\n", 587).name, "smtp");
+        assert_eq!(identify(b"220 ready\r\n", 2121).name, "ftp");
+    }
+
+    #[test]
+    fn pop3_and_imap() {
+        let pop = identify(b"+OK Dovecot ready.\r\n", 110);
+        assert_eq!(pop.name, "pop3");
+        assert_eq!(pop.version.as_deref(), Some("Dovecot ready."));
+        assert_eq!(
+            identify(b"* OK [CAPABILITY IMAP4rev1] ready\r\n", 143).name,
+            "imap"
+        );
+    }
+
+    #[test]
+    fn http_server_header() {
+        let info = identify(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nServer: nginx/1.24.0\r\n\r\n",
+            80,
+        );
+        assert_eq!(info.name, "http");
+        assert_eq!(info.version.as_deref(), Some("nginx/1.24.0"));
+        assert_eq!(info.banner.as_deref(), Some("HTTP/1.1 200 OK"));
+    }
+
+    #[test]
+    fn redis_replies() {
+        assert_eq!(identify(b"+PONG\r\n", 6379).name, "redis");
+        assert_eq!(
+            identify(b"-ERR unknown command 'HEAD'\r\n", 7000).name,
+            "redis"
+        );
+    }
+
+    #[test]
+    fn tls_alert_and_server_hello() {
+        assert_eq!(
+            identify(&[0x15, 0x03, 0x03, 0x00, 0x02, 0x02, 0x46], 443).name,
+            "tls"
+        );
+
+        // ServerHello, legacy version 1.2, supported_versions = 1.3
+        let mut hello = vec![0x03, 0x03];
+        hello.extend([0u8; 32]);
+        hello.extend([0x00, 0x13, 0x01, 0x00]); // no session id, suite, compression
+        hello.extend([0x00, 0x06, 0x00, 0x2b, 0x00, 0x02, 0x03, 0x04]);
+        let mut rec = vec![0x16, 0x03, 0x03, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00];
+        rec.extend(hello);
+        let info = identify(&rec, 443);
+        assert_eq!(info.name, "tls");
+        assert_eq!(info.version.as_deref(), Some("TLS 1.3"));
+    }
+
+    #[test]
+    fn unknown_binary_banner_is_sanitised() {
+        let info = identify(b"\x00\x01hello\x7fworld\r\n", 9999);
+        assert_eq!(info.name, "unknown");
+        assert_eq!(info.banner.as_deref(), Some("helloworld"));
+    }
+
+    #[test]
+    fn client_hello_lengths_are_consistent() {
+        let ch = client_hello();
+        assert_eq!(ch[0], 0x16);
+        let rec_len = u16::from_be_bytes([ch[3], ch[4]]) as usize;
+        assert_eq!(rec_len, ch.len() - 5);
+        let hs_len = u32::from_be_bytes([0, ch[6], ch[7], ch[8]]) as usize;
+        assert_eq!(hs_len, ch.len() - 9);
+    }
+}
@@ -0,0 +1,215 @@
+//! Local end of outgoing probes: `--source-ip`, `--source-port` and
+//! `--interface`.
+//!
+//! By default the OS picks the address, port and route. [`LocalBind`] pins
+//! any of them for every socket a scan opens: TCP connects (through
+//! `TcpSocket`), UDP probes, host discovery and the raw sockets of a SYN
+//! scan. A fixed source port is shared by all concurrent connects, which
+//! works because each socket sets `SO_REUSEADDR` and every connection still
+//! has its own remote end. Re-probing the same port right away can fail
+//! while the first connection sits in `TIME_WAIT`.
+
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+use tokio::net::{TcpSocket, TcpStream};
+
+/// Which local address, port and interface probes are sent from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LocalBind {
+    pub ip: Option<IpAddr>,
+    pub port: Option<u16>,
+    /// Network interface name, e.g. `eth0` (Linux `SO_BINDTODEVICE`).
+    pub interface: Option<String>,
+}
+
+impl LocalBind {
+    /// Nothing pinned: the OS chooses.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Local socket address for reaching `target`: the configured address
+    /// (or the wildcard of the target's family) and port (or 0).
+    pub fn local_addr(&self, target: IpAddr) -> io::Result<SocketAddr> {
+        let ip = match self.ip {
+            Some(ip) if ip.is_ipv4() != target.is_ipv4() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("source address {ip} cannot reach {target}"),
+                ))
+            }
+            Some(ip) => ip,
+            None if target.is_ipv4() => Ipv4Addr::UNSPECIFIED.into(),
+            None => Ipv6Addr::UNSPECIFIED.into(),
+        };
+        Ok(SocketAddr::new(ip, self.port.unwrap_or(0)))
+    }
+
+    /// Connect to `target` from the configured local end.
+    pub async fn connect(&self, target: SocketAddr) -> io::Result<TcpStream> {
+        if self.is_default() {
+            return TcpStream::connect(target).await;
+        }
+        let sock = if target.is_ipv4() {
+            TcpSocket::new_v4()?
+        } else {
+            TcpSocket::new_v6()?
+        };
+        if let Some(iface) = &self.interface {
+            bind_device_tcp(&sock, iface)?;
+        }
+        if self.port.is_some() {
+            sock.set_reuseaddr(true)?;
+        }
+        sock.bind(self.local_addr(target.ip())?)?;
+        sock.connect(target).await
+    }
+
+    /// Fail early if the address, port or interface cannot be used at all
+    /// (not local, port taken or privileged, no such interface), instead of
+    /// every probe failing and looking filtered.
+    pub fn check(&self) -> io::Result<()> {
+        if self.is_default() {
+            return Ok(());
+        }
+        let ip = self.ip.unwrap_or(Ipv4Addr::UNSPECIFIED.into());
+        let sock = Socket::new(domain_for(ip), Type::STREAM, Some(Protocol::TCP))?;
+        self.bind_interface(&sock)?;
+        sock.set_reuse_address(true)?;
+        sock.bind(&SocketAddr::new(ip, self.port.unwrap_or(0)).into())
+    }
+
+    /// Restrict `sock` to the configured interface, if any.
+    pub(crate) fn bind_interface(&self, sock: &Socket) -> io::Result<()> {
+        match &self.interface {
+            Some(iface) => bind_device(sock, iface),
+            None => Ok(()),
+        }
+    }
+
+    /// Drop the source port, for connections that cannot share it.
+    pub(crate) fn without_port(&self) -> Self {
+        Self {
+            port: None,
+            ..self.clone()
+        }
+    }
+
+    /// Apply the interface and address (and, if `with_port`, the port) to a
+    /// socket about to talk to `target`.
+    pub fn apply(&self, sock: &Socket, target: IpAddr, with_port: bool) -> io::Result<()> {
+        self.bind_interface(sock)?;
+        if self.ip.is_some() || (with_port && self.port.is_some()) {
+            let mut local = self.local_addr(target)?;
+            if with_port {
+                sock.set_reuse_address(true)?;
+            } else {
+                local.set_port(0);
+            }
+            sock.bind(&local.into())?;
+        }
+        Ok(())
+    }
+}
+
+/// Address family of `target`, for creating a matching socket.
+pub(crate) fn domain_for(target: IpAddr) -> Domain {
+    if target.is_ipv4() {
+        Domain::IPV4
+    } else {
+        Domain::IPV6
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn bind_device(sock: &Socket, iface: &str) -> io::Result<()> {
+    sock.bind_device(Some(iface.as_bytes()))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn bind_device_tcp(sock: &TcpSocket, iface: &str) -> io::Result<()> {
+    sock.bind_device(Some(iface.as_bytes()))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn bind_device(_sock: &Socket, _iface: &str) -> io::Result<()> {
+    Err(unsupported_interface())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn bind_device_tcp(_sock: &TcpSocket, _iface: &str) -> io::Result<()> {
+    Err(unsupported_interface())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn unsupported_interface() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "binding to an interface is only supported on Linux",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn local_addr_follows_target_family() {
+        let any = LocalBind {
+            port: Some(40000),
+            ..LocalBind::default()
+        };
+        assert_eq!(
+            any.local_addr("10.0.0.1".parse().unwrap()).unwrap(),
+            "0.0.0.0:40000".parse().unwrap()
+        );
+        assert_eq!(
+            any.local_addr("::1".parse().unwrap()).unwrap(),
+            "[::]:40000".parse().unwrap()
+        );
+
+        let v4 = LocalBind {
+            ip: Some("127.0.0.1".parse().unwrap()),
+            ..LocalBind::default()
+        };
+        assert!(v4.local_addr("::1".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn check_rejects_foreign_address() {
+        assert!(LocalBind::default().check().is_ok());
+        let foreign = LocalBind {
+            ip: Some("192.0.2.1".parse().unwrap()),
+            ..LocalBind::default()
+        };
+        assert!(foreign.check().is_err());
+        let missing = LocalBind {
+            interface: Some("no-such-if0".into()),
+            ..LocalBind::default()
+        };
+        assert!(missing.check().is_err());
+    }
+
+    #[tokio::test]
+    async fn concurrent_connects_share_a_source_port() {
+        let a = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let b = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = {
+            let l = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            l.local_addr().unwrap().port()
+        };
+        let local = LocalBind {
+            ip: Some("127.0.0.1".parse().unwrap()),
+            port: Some(port),
+            interface: None,
+        };
+        let s1 = local.connect(a.local_addr().unwrap()).await.unwrap();
+        let s2 = local.connect(b.local_addr().unwrap()).await.unwrap();
+        assert_eq!(s1.local_addr().unwrap().port(), port);
+        assert_eq!(s2.local_addr().unwrap().port(), port);
+    }
+}
@@ -0,0 +1,239 @@
+//! Scan checkpoints for `--checkpoint` / `--resume`.
+//!
+//! A checkpoint records what a scan was asked to do (hosts after discovery,
+//! ports, protocol), which `(host, port)` pairs already have a final result,
+//! the per-state totals so far and the results that were being reported.
+//! With `--baseline` it also keeps the hosts that failed discovery and the
+//! unreported results the comparison needs, so a resumed run reports the
+//! same drift as an uninterrupted one.
+//! Completed ports are stored per host in the same compressed `22,80-90`
+//! form as report metadata, which keeps the file small even for full-range
+//! scans of large networks.
+//!
+//! [`Checkpoint::save`] writes to a temporary file and renames it over the
+//! target, so an interrupt during a save never leaves a truncated file.
+
+use crate::{
+    diff::Drift,
+    output::{compress_ports, PortRecord},
+    ports::parse_ports_list,
+    scanner::{PortResult, PortState, Protocol, Scanner, StateCounts},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    net::IpAddr,
+    path::Path,
+};
+
+/// Progress of a scan, as saved to and loaded from a checkpoint file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Version of the scanner that wrote the file.
+    pub version: String,
+    /// Command line of the original run.
+    pub args: String,
+    pub protocol: Protocol,
+    pub hosts: Vec<IpAddr>,
+    /// Compressed port list, e.g. `22,80-90,443`.
+    pub ports: String,
+    /// Ports with a final result, per host.
+    #[serde(with = "compressed")]
+    done: BTreeMap<IpAddr, BTreeSet<u16>>,
+    pub counts: StateCounts,
+    /// Results in the states the run was reporting.
+    pub results: Vec<PortRecord>,
+    /// Hosts that failed discovery.
+    #[serde(default)]
+    pub down: Vec<IpAddr>,
+    /// Results in other states that the baseline comparison still needs.
+    #[serde(default)]
+    pub drift_results: Vec<PortRecord>,
+}
+
+impl Checkpoint {
+    /// Start an empty checkpoint for `scanner`'s hosts, ports and protocol.
+    pub fn new(scanner: &Scanner, args: impl Into<String>) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            args: args.into(),
+            protocol: scanner.protocol(),
+            hosts: scanner.targets().to_vec(),
+            ports: compress_ports(scanner.ports()),
+            done: BTreeMap::new(),
+            counts: StateCounts::default(),
+            results: Vec::new(),
+            down: Vec::new(),
+            drift_results: Vec::new(),
+        }
+    }
+
+    /// Read a checkpoint written by [`Checkpoint::save`].
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    /// Atomically replace `path` with the current state.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(&tmp, path)
+    }
+
+    /// The port list to scan, expanded.
+    pub fn port_list(&self) -> Vec<u16> {
+        parse_ports_list(&self.ports)
+    }
+
+    /// Record a final result; it is kept in full only if its state is in
+    /// `keep` or `drift` tracks it. Proxy errors are not final: a resumed
+    /// run probes them again.
+    pub fn record(&mut self, r: &PortResult, keep: &[PortState], drift: Option<&Drift>) {
+        if r.state == PortState::ProxyError {
+            return;
+        }
+        if !self.done.entry(r.addr).or_default().insert(r.port) {
+            return;
+        }
+        self.counts.record(r.state);
+        if keep.contains(&r.state) {
+            self.results.push(PortRecord::from(r));
+        } else if drift.is_some_and(|d| d.tracks(r)) {
+            self.drift_results.push(PortRecord::from(r));
+        }
+    }
+
+    /// `(host, port)` pairs that already have a result.
+    pub fn completed(&self) -> impl Iterator<Item = (IpAddr, u16)> + '_ {
+        self.done
+            .iter()
+            .flat_map(|(&addr, ports)| ports.iter().map(move |&p| (addr, p)))
+    }
+
+    /// Number of pairs that already have a result.
+    pub fn completed_count(&self) -> usize {
+        self.done.values().map(BTreeSet::len).sum()
+    }
+}
+
+/// (De)serialize per-host port sets as compressed port lists.
+mod compressed {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        done: &BTreeMap<IpAddr, BTreeSet<u16>>,
+        s: S,
+    ) -> Result<S::Ok, S::Error> {
+        let map: BTreeMap<&IpAddr, String> = done
+            .iter()
+            .map(|(addr, ports)| {
+                (
+                    addr,
+                    compress_ports(&ports.iter().copied().collect::<Vec<_>>()),
+                )
+            })
+            .collect();
+        map.serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        d: D,
+    ) -> Result<BTreeMap<IpAddr, BTreeSet<u16>>, D::Error> {
+        let map = BTreeMap::<IpAddr, String>::deserialize(d)?;
+        Ok(map
+            .into_iter()
+            .map(|(addr, ports)| (addr, parse_ports_list(&ports).into_iter().collect()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn result(addr: &str, port: u16, state: PortState) -> PortResult {
+        PortResult {
+            addr: addr.parse().unwrap(),
+            port,
+            protocol: Protocol::Tcp,
+            state,
+            latency: Duration::from_millis(2),
+            service: None,
+            tls: None,
+            http: None,
+        }
+    }
+
+    fn checkpoint() -> Checkpoint {
+        let scanner = Scanner::new()
+            .with_targets(["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()])
+            .with_ports(&[22, 23, 24, 80]);
+        Checkpoint::new(&scanner, "port_scanner 10.0.0.1-2")
+    }
+
+    #[test]
+    fn records_each_pair_once() {
+        let mut cp = checkpoint();
+        let keep = [PortState::Open];
+        cp.record(&result("10.0.0.1", 22, PortState::Open), &keep, None);
+        cp.record(&result("10.0.0.1", 23, PortState::Closed), &keep, None);
+        cp.record(&result("10.0.0.1", 22, PortState::Open), &keep, None);
+        cp.record(&result("10.0.0.1", 24, PortState::ProxyError), &keep, None);
+        assert_eq!(cp.completed_count(), 2);
+        assert_eq!((cp.counts.open, cp.counts.closed), (1, 1));
+        assert_eq!(cp.results.len(), 1);
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        let mut cp = checkpoint();
+        for port in [24, 22, 23] {
+            cp.record(&result("10.0.0.1", port, PortState::Closed), &[], None);
+        }
+        cp.record(
+            &result("10.0.0.2", 80, PortState::Open),
+            &[PortState::Open],
+            None,
+        );
+        cp.down.push("10.0.0.3".parse().unwrap());
+
+        let path = std::env::temp_dir().join(format!("ps-checkpoint-{}.json", std::process::id()));
+        cp.save(&path).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains(r#""10.0.0.1":"22-24""#));
+        let loaded = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, cp);
+        assert_eq!(loaded.port_list(), vec![22, 23, 24, 80]);
+        let done: Vec<_> = loaded.completed().collect();
+        assert_eq!(done.len(), 4);
+        assert!(done.contains(&("10.0.0.2".parse().unwrap(), 80)));
+    }
+
+    #[test]
+    fn keeps_unreported_results_the_baseline_needs() {
+        let was_open = PortRecord::from(&result("10.0.0.1", 22, PortState::Open));
+        let drift = Drift::new([was_open]);
+        let mut cp = checkpoint();
+        let keep = [PortState::Open];
+        cp.record(
+            &result("10.0.0.1", 22, PortState::Closed),
+            &keep,
+            Some(&drift),
+        );
+        cp.record(
+            &result("10.0.0.1", 23, PortState::Closed),
+            &keep,
+            Some(&drift),
+        );
+        assert!(cp.results.is_empty());
+        assert_eq!(cp.drift_results.len(), 1);
+        assert_eq!(cp.drift_results[0].port, 22);
+    }
+}
@@ -0,0 +1,392 @@
+//! Drift detection against a previous scan (`--baseline`).
+//!
+//! The baseline is a JSON or JSON Lines report written by an earlier run.
+//! Only its open ports matter: a port open now but not then is *opened*, a
+//! port open then that answered differently now is *closed*, and a port
+//! open in both whose probed service, version or banner differs is
+//! *changed*. Baseline ports the current run did not probe are ignored, so
+//! comparing a `--top-ports 100` run against a full-range baseline only
+//! reports on those 100 ports — except on hosts that failed discovery: their
+//! baseline-open ports are reported closed, host down.
+
+use crate::{
+    output::{JsonlEvent, PortRecord, ScanReport},
+    scanner::{PortResult, PortState, Protocol},
+};
+use chrono::{DateTime, Utc};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt, fs, io,
+    net::IpAddr,
+    path::Path,
+};
+
+type PortKey = (IpAddr, Protocol, u16);
+
+/// One difference between the baseline and the current scan.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// Open now, not open in the baseline.
+    Opened { now: PortRecord },
+    /// Open in the baseline, in `state` now. If `host_down`, the host
+    /// failed discovery and the port was not probed at all.
+    Closed {
+        was: PortRecord,
+        state: PortState,
+        host_down: bool,
+    },
+    /// Open in both, but the detected service differs.
+    ServiceChanged { was: PortRecord, now: PortRecord },
+}
+
+impl Change {
+    fn record(&self) -> &PortRecord {
+        match self {
+            Self::Opened { now } | Self::ServiceChanged { now, .. } => now,
+            Self::Closed { was, .. } => was,
+        }
+    }
+
+    pub fn host(&self) -> IpAddr {
+        self.record().host
+    }
+
+    pub fn port(&self) -> u16 {
+        self.record().port
+    }
+}
+
+impl fmt::Display for Change {
+    /// `+ 22/tcp opened ssh OpenSSH_9.6` and friends; the host is left to
+    /// the caller so changes can be grouped per host.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let r = self.record();
+        match self {
+            Self::Opened { now } => {
+                write!(f, "+ {}/{} opened", r.port, r.protocol)?;
+                if let Some(d) = describe(now) {
+                    write!(f, " {d}")?;
+                }
+                Ok(())
+            }
+            Self::Closed {
+                was,
+                state,
+                host_down,
+            } => {
+                write!(f, "- {}/{} now {state}", r.port, r.protocol)?;
+                if *host_down {
+                    f.write_str(" (host down)")?;
+                }
+                f.write_str(", was open")?;
+                if let Some(d) = describe(was) {
+                    write!(f, " {d}")?;
+                }
+                Ok(())
+            }
+            Self::ServiceChanged { was, now } => write!(
+                f,
+                "~ {}/{} changed {} -> {}",
+                r.port,
+                r.protocol,
+                describe(was).unwrap_or_default(),
+                describe(now).unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// `ssh OpenSSH_9.6`, `http`, or nothing when the port is unknown.
+fn describe(rec: &PortRecord) -> Option<String> {
+    match (&rec.service, &rec.version) {
+        (Some(s), Some(v)) => Some(format!("{s} {v}")),
+        (Some(s), None) => Some(s.clone()),
+        (None, v) => v.clone(),
+    }
+}
+
+/// Compares streamed results against the open ports of a baseline report.
+#[derive(Debug, Clone, Default)]
+pub struct Drift {
+    /// Start time of the baseline scan, if the report carried one.
+    pub baseline_start: Option<DateTime<Utc>>,
+    baseline: BTreeMap<PortKey, PortRecord>,
+    /// Current open ports, plus current results for baseline-open ports.
+    current: BTreeMap<PortKey, PortRecord>,
+    /// Baseline-open ports on hosts that failed discovery.
+    down: BTreeSet<PortKey>,
+}
+
+impl Drift {
+    /// Compare against these baseline records; only open ones are kept.
+    pub fn new<I: IntoIterator<Item = PortRecord>>(baseline: I) -> Self {
+        Self {
+            baseline_start: None,
+            baseline: baseline
+                .into_iter()
+                .filter(|r| r.state == PortState::Open)
+                .map(|r| (key(&r), r))
+                .collect(),
+            current: BTreeMap::new(),
+            down: BTreeSet::new(),
+        }
+    }
+
+    /// Load a baseline from a `json` or `jsonl` report file.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        if let Ok(report) = serde_json::from_str::<ScanReport>(&text) {
+            let mut drift = Self::new(report.results);
+            drift.baseline_start = Some(report.meta.start);
+            return Ok(drift);
+        }
+        let mut start = None;
+        let mut records = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event: JsonlEvent = serde_json::from_str(line).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("not a json or jsonl scan report (line {}: {e})", n + 1),
+                )
+            })?;
+            match event {
+                JsonlEvent::Start(meta) => start = Some(meta.start),
+                JsonlEvent::Port(rec) => records.push(rec),
+                JsonlEvent::Summary(_) => {}
+            }
+        }
+        let mut drift = Self::new(records);
+        drift.baseline_start = start;
+        Ok(drift)
+    }
+
+    /// Number of open ports in the baseline.
+    pub fn baseline_open(&self) -> usize {
+        self.baseline.len()
+    }
+
+    /// Whether [`Drift::record`] would keep this result: it is open, or
+    /// the port was open in the baseline.
+    pub fn tracks(&self, r: &PortResult) -> bool {
+        r.state != PortState::ProxyError
+            && (r.state == PortState::Open
+                || self.baseline.contains_key(&(r.addr, r.protocol, r.port)))
+    }
+
+    /// Feed one result of the current scan. A proxy error says nothing
+    /// about the port, so it neither opens nor closes anything.
+    pub fn record(&mut self, r: &PortResult) {
+        if self.tracks(r) {
+            let rec = PortRecord::from(r);
+            self.current.insert(key(&rec), rec);
+        }
+    }
+
+    /// Note that `addr` failed host discovery, so none of `ports` were
+    /// probed on it. Its baseline-open ports among them count as closed.
+    pub fn record_host_down(&mut self, addr: IpAddr, protocol: Protocol, ports: &[u16]) {
+        let ports: HashSet<u16> = ports.iter().copied().collect();
+        let down = self
+            .baseline
+            .keys()
+            .filter(|&&(h, p, port)| h == addr && p == protocol && ports.contains(&port));
+        self.down.extend(down);
+    }
+
+    /// All differences, ordered by host, protocol and port.
+    pub fn changes(&self) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for (k, now) in &self.current {
+            match (self.baseline.get(k), now.state) {
+                (None, _) => changes.push((*k, Change::Opened { now: now.clone() })),
+                (Some(was), PortState::Open) => {
+                    if service_differs(was, now) {
+                        let change = Change::ServiceChanged {
+                            was: was.clone(),
+                            now: now.clone(),
+                        };
+                        changes.push((*k, change));
+                    }
+                }
+                (Some(was), state) => {
+                    let change = Change::Closed {
+                        was: was.clone(),
+                        state,
+                        host_down: false,
+                    };
+                    changes.push((*k, change));
+                }
+            }
+        }
+        for k in self.down.iter().filter(|k| !self.current.contains_key(k)) {
+            let change = Change::Closed {
+                was: self.baseline[k].clone(),
+                state: PortState::Filtered,
+                host_down: true,
+            };
+            changes.push((*k, change));
+        }
+        changes.sort_by_key(|&(k, _)| k);
+        changes.into_iter().map(|(_, c)| c).collect()
+    }
+}
+
+fn key(r: &PortRecord) -> PortKey {
+    (r.host, r.protocol, r.port)
+}
+
+/// Only probe output is compared: a run without `--banners` has nothing but
+/// table names, which say nothing about what is actually listening.
+fn service_differs(was: &PortRecord, now: &PortRecord) -> bool {
+    let probed = |r: &PortRecord| r.version.is_some() || r.banner.is_some();
+    probed(was)
+        && probed(now)
+        && (was.service != now.service || was.version != now.version || was.banner != now.banner)
+}
+
+/// Print changes grouped per host, e.g. for the CLI summary.
+pub fn write_changes<W: io::Write>(out: &mut W, changes: &[Change]) -> io::Result<()> {
+    let mut host = None;
+    for c in changes {
+        if host != Some(c.host()) {
+            host = Some(c.host());
+            writeln!(out, "{}", c.host())?;
+        }
+        writeln!(out, "  {c}")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        banner::ServiceInfo,
+        output::{OutputFormat, ReportWriter, ScanMeta},
+        scanner::{Scanner, StateCounts},
+    };
+    use std::time::Duration;
+
+    fn result(port: u16, state: PortState, version: Option<&str>) -> PortResult {
+        PortResult {
+            addr: "10.0.0.1".parse().unwrap(),
+            port,
+            protocol: Protocol::Tcp,
+            state,
+            latency: Duration::from_millis(1),
+            service: version.map(|v| ServiceInfo {
+                name: "ssh".into(),
+                version: Some(v.into()),
+                banner: Some(format!("SSH-2.0-{v}")),
+            }),
+            tls: None,
+            http: None,
+        }
+    }
+
+    fn report(format: OutputFormat, results: &[PortResult]) -> String {
+        let scanner = Scanner::new().with_targets(["10.0.0.1".parse().unwrap()]);
+        let mut w = ReportWriter::new(format, Vec::new(), ScanMeta::new(&scanner, "x")).unwrap();
+        let mut counts = StateCounts::default();
+        for r in results {
+            counts.record(r.state);
+            w.write(r).unwrap();
+        }
+        String::from_utf8(w.finish(counts).unwrap()).unwrap()
+    }
+
+    fn baseline() -> Vec<PortResult> {
+        vec![
+            result(22, PortState::Open, Some("OpenSSH_9.5")),
+            result(23, PortState::Open, None),
+            result(80, PortState::Open, None),
+            result(81, PortState::Closed, None),
+        ]
+    }
+
+    #[test]
+    fn detects_opened_closed_and_changed() {
+        let mut drift = Drift::new(baseline().iter().map(PortRecord::from));
+        assert_eq!(drift.baseline_open(), 3);
+        for r in [
+            result(22, PortState::Open, Some("OpenSSH_9.6")),
+            result(23, PortState::Filtered, None),
+            result(80, PortState::Open, None),
+            result(81, PortState::Open, None),
+            result(82, PortState::Closed, None),
+        ] {
+            drift.record(&r);
+        }
+        let changes = drift.changes();
+        let lines: Vec<_> = changes.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "~ 22/tcp changed ssh OpenSSH_9.5 -> ssh OpenSSH_9.6",
+                "- 23/tcp now filtered, was open telnet",
+                "+ 81/tcp opened hosts2-ns",
+            ]
+        );
+    }
+
+    #[test]
+    fn unprobed_baseline_ports_and_bare_names_are_not_drift() {
+        let mut drift = Drift::new(baseline().iter().map(PortRecord::from));
+        // Same ports, but scanned without --banners this time.
+        drift.record(&result(22, PortState::Open, None));
+        drift.record(&result(80, PortState::Open, None));
+        assert!(drift.changes().is_empty());
+    }
+
+    #[test]
+    fn ports_on_down_hosts_are_closed() {
+        let mut drift = Drift::new(baseline().iter().map(PortRecord::from));
+        let host = "10.0.0.1".parse().unwrap();
+        // 80 was not in this run's port list.
+        drift.record_host_down(host, Protocol::Tcp, &[22, 23, 81]);
+        drift.record_host_down(host, Protocol::Udp, &[80]);
+        let lines: Vec<_> = drift.changes().iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "- 22/tcp now filtered (host down), was open ssh OpenSSH_9.5",
+                "- 23/tcp now filtered (host down), was open telnet",
+            ]
+        );
+    }
+
+    #[test]
+    fn loads_json_and_jsonl_reports() {
+        for format in [OutputFormat::Json, OutputFormat::Jsonl] {
+            let path = std::env::temp_dir()
+                .join(format!("ps-baseline-{}-{format}.json", std::process::id()));
+            fs::write(&path, report(format, &baseline())).unwrap();
+            let drift = Drift::load(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(drift.baseline_open(), 3, "{format}");
+            assert!(drift.baseline_start.is_some());
+        }
+
+        let path = std::env::temp_dir().join(format!("ps-baseline-{}.csv", std::process::id()));
+        fs::write(&path, report(OutputFormat::Csv, &baseline())).unwrap();
+        let err = Drift::load(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn changes_are_grouped_per_host() {
+        let mut drift = Drift::new([]);
+        drift.record(&result(22, PortState::Open, None));
+        drift.record(&result(80, PortState::Open, None));
+        let mut out = Vec::new();
+        write_changes(&mut out, &drift.changes()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "10.0.0.1\n  + 22/tcp opened ssh\n  + 80/tcp opened http\n"
+        );
+    }
+}
@@ -0,0 +1,260 @@
+//! Host discovery ("ping") before the port scan.
+//!
+//! A host counts as up if any of a few common TCP ports answers — accepted
+//! *or* refused, since a RST proves something is there — or, when the
+//! process may open an ICMP socket, if it answers an echo request. Hosts
+//! that stay silent are skipped by the CLI unless `--no-ping` is given.
+//!
+//! Probes go out from the scan's source address and interface. A fixed
+//! source port is left to the scan itself: a ping to port 80 would
+//! otherwise occupy the very connection the port probe needs next. When a
+//! [`RateLimiter`] is given, every TCP connect and ICMP echo waits on it.
+
+use crate::{
+    bind::LocalBind,
+    scanner::{connect_tcp, PortState},
+    timing::RateLimiter,
+};
+use socket2::{Domain, Protocol as SockProtocol, Socket, Type};
+use std::{
+    io::{self, Read},
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{sync::Semaphore, task::JoinSet};
+
+/// Ports probed when no `--ping-ports` are given: web, SSH, SMB and RDP
+/// between them answer on nearly every live server or workstation.
+pub const DEFAULT_PING_PORTS: &[u16] = &[80, 443, 22, 445, 3389];
+
+/// Whether `addr` answers on any of `ports` or to an ICMP echo within `wait`.
+pub async fn is_host_up(
+    addr: IpAddr,
+    ports: &[u16],
+    wait: Duration,
+    local: &LocalBind,
+    limiter: Option<&RateLimiter>,
+) -> bool {
+    let local = local.without_port();
+    let mut probes = JoinSet::new();
+    for &port in ports {
+        if let Some(limiter) = limiter {
+            limiter.wait().await;
+        }
+        let local = local.clone();
+        probes.spawn(async move {
+            let (r, _) = connect_tcp(SocketAddr::new(addr, port), wait, &local).await;
+            matches!(r.state, PortState::Open | PortState::Closed)
+        });
+    }
+    if let Some(limiter) = limiter {
+        limiter.wait().await;
+    }
+    // Blocking tasks can't be aborted, so the echo polls a flag instead.
+    let cancel = CancelOnDrop(Arc::new(AtomicBool::new(false)));
+    let flag = Arc::clone(&cancel.0);
+    probes.spawn_blocking(move || icmp_echo_until(addr, wait, &local, &flag).unwrap_or(false));
+    while let Some(res) = probes.join_next().await {
+        if let Ok(true) = res {
+            // Dropping the set aborts the TCP probes; `cancel` stops the echo.
+            return true;
+        }
+    }
+    false
+}
+
+/// Sets the flag when dropped.
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Return the hosts from `hosts` that appear up, in their original order.
+/// At most `concurrency` hosts are checked at a time, and every probe waits
+/// on `limiter` if one is given.
+pub async fn discover(
+    hosts: &[IpAddr],
+    ports: &[u16],
+    wait: Duration,
+    concurrency: usize,
+    local: &LocalBind,
+    limiter: Option<Arc<RateLimiter>>,
+) -> Vec<IpAddr> {
+    let sem = Arc::new(Semaphore::new(concurrency.max(1)));
+    let ports: Arc<[u16]> = ports.into();
+    let mut checks = JoinSet::new();
+    for (idx, &addr) in hosts.iter().enumerate() {
+        let Ok(permit) = Arc::clone(&sem).acquire_owned().await else {
+            break;
+        };
+        let ports = Arc::clone(&ports);
+        let local = local.clone();
+        let limiter = limiter.clone();
+        checks.spawn(async move {
+            let _permit = permit;
+            let up = is_host_up(addr, &ports, wait, &local, limiter.as_deref()).await;
+            (idx, up)
+        });
+    }
+    let mut up = Vec::new();
+    while let Some(res) = checks.join_next().await {
+        if let Ok((idx, true)) = res {
+            up.push(idx);
+        }
+    }
+    up.sort_unstable();
+    up.into_iter().map(|i| hosts[i]).collect()
+}
+
+/// Send one ICMP echo request and wait for the reply.
+///
+/// Tries an unprivileged ping socket (Linux `ping_group_range`) first and a
+/// raw socket second; the error from the raw attempt is returned when
+/// neither is permitted. Blocking — run it on the blocking pool.
+pub fn icmp_echo(addr: IpAddr, wait: Duration, local: &LocalBind) -> io::Result<bool> {
+    icmp_echo_until(addr, wait, local, &AtomicBool::new(false))
+}
+
+/// How often a pending echo checks whether it was cancelled.
+const CANCEL_POLL: Duration = Duration::from_millis(50);
+
+/// [`icmp_echo`] that gives up early once `cancel` is set.
+fn icmp_echo_until(
+    addr: IpAddr,
+    wait: Duration,
+    local: &LocalBind,
+    cancel: &AtomicBool,
+) -> io::Result<bool> {
+    let (domain, proto, request, reply) = match addr {
+        IpAddr::V4(_) => (Domain::IPV4, SockProtocol::ICMPV4, 8, 0),
+        IpAddr::V6(_) => (Domain::IPV6, SockProtocol::ICMPV6, 128, 129),
+    };
+    let mut sock = Socket::new(domain, Type::DGRAM, Some(proto))
+        .or_else(|_| Socket::new(domain, Type::RAW, Some(proto)))?;
+    local.apply(&sock, addr, false)?;
+    // Connecting makes the kernel drop replies from every other host.
+    sock.connect(&SocketAddr::new(addr, 0).into())?;
+    sock.send(&echo_request(request, std::process::id() as u16, 1))?;
+
+    let deadline = Instant::now() + wait;
+    let mut buf = [0u8; 1500];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() || cancel.load(Ordering::Relaxed) {
+            return Ok(false);
+        }
+        sock.set_read_timeout(Some(left.min(CANCEL_POLL)))?;
+        let n = match sock.read(&mut buf) {
+            Ok(n) => n,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue
+            }
+            Err(e) => return Err(e),
+        };
+        let mut msg = &buf[..n];
+        // Raw IPv4 sockets deliver the IP header too.
+        if addr.is_ipv4() && msg.first().is_some_and(|b| b >> 4 == 4) {
+            msg = msg
+                .get(usize::from(msg[0] & 0x0f) * 4..)
+                .unwrap_or_default();
+        }
+        if msg.first() == Some(&reply) {
+            return Ok(true);
+        }
+        // Anything else (e.g. our own request seen on loopback): keep waiting.
+    }
+}
+
+/// ICMP / ICMPv6 echo request with an empty payload. The checksum is always
+/// filled in; for ICMPv6 the kernel overwrites it with the pseudo-header one.
+fn echo_request(kind: u8, id: u16, seq: u16) -> Vec<u8> {
+    let mut pkt = vec![kind, 0, 0, 0];
+    pkt.extend_from_slice(&id.to_be_bytes());
+    pkt.extend_from_slice(&seq.to_be_bytes());
+    let sum = internet_checksum(&pkt);
+    pkt[2..4].copy_from_slice(&sum.to_be_bytes());
+    pkt
+}
+
+/// RFC 1071 ones' complement sum.
+pub(crate) fn internet_checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|c| u32::from(u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)])))
+        .sum();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn echo_request_checksums_to_zero() {
+        let pkt = echo_request(8, 0x1234, 1);
+        assert_eq!(pkt.len(), 8);
+        assert_eq!(pkt[0], 8);
+        assert_eq!(internet_checksum(&pkt), 0);
+    }
+
+    #[tokio::test]
+    async fn refused_port_proves_host_is_up() {
+        let port = {
+            let l = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            l.local_addr().unwrap().port()
+        };
+        let wait = Duration::from_millis(500);
+        let local = LocalBind::default();
+        let up = is_host_up([127, 0, 0, 1].into(), &[port], wait, &local, None).await;
+        assert!(up);
+    }
+
+    #[tokio::test]
+    async fn discover_keeps_input_order() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let hosts: Vec<IpAddr> = vec![[127, 0, 0, 2].into(), [127, 0, 0, 1].into()];
+        let wait = Duration::from_millis(500);
+        let up = discover(&hosts, &[port], wait, 4, &LocalBind::default(), None).await;
+        // Both loopback addresses answer (RST on .2, accept on .1).
+        assert_eq!(up, hosts);
+    }
+
+    #[tokio::test]
+    async fn discover_respects_rate_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let hosts: Vec<IpAddr> = vec![[127, 0, 0, 1].into(); 3];
+        let limiter = Arc::new(RateLimiter::new(40.0));
+        let start = Instant::now();
+        let wait = Duration::from_millis(500);
+        let up = discover(
+            &hosts,
+            &[port],
+            wait,
+            8,
+            &LocalBind::default(),
+            Some(limiter),
+        )
+        .await;
+        assert_eq!(up.len(), 3);
+        // Three hosts, one TCP connect and one echo each: six 25ms slots.
+        assert!(start.elapsed() >= Duration::from_millis(120));
+    }
+}
@@ -0,0 +1,360 @@
+//! First-pass web fingerprinting for open HTTP(S) ports (`--http-info`).
+//!
+//! A fresh connection fetches `/` once (over TLS on HTTPS ports, without
+//! verifying the certificate) and the response is summarised: status,
+//! `Server` and `X-Powered-By` headers, the page `<title>`, the redirect
+//! target of a 3xx, and which of the common security headers are missing:
+//! `Strict-Transport-Security` (only checked over HTTPS, since browsers
+//! ignore it on plain HTTP), `Content-Security-Policy` and
+//! `X-Frame-Options`. Redirects are reported, not followed.
+
+use crate::{banner::ServiceInfo, tls::tls_connect};
+use serde::{Deserialize, Serialize};
+use std::{fmt, io, net::SocketAddr, time::Duration};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+/// Most of a response read; the title is nearly always in the first few KB.
+const MAX_RESPONSE: usize = 64 * 1024;
+
+/// Longest header value or title kept in results.
+const MAX_FIELD_LEN: usize = 128;
+
+/// Ports where HTTPS is the norm.
+const HTTPS_PORTS: &[u16] = &[443, 4443, 8443, 9443];
+
+/// Ports where plain HTTP is the norm.
+const HTTP_PORTS: &[u16] = &[80, 81, 591, 3000, 5000, 8000, 8008, 8080, 8081, 8888];
+
+/// Whether the request went over TLS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scheme {
+    Http,
+    Https,
+}
+
+impl Scheme {
+    /// Scheme to use on `port`, or `None` if it does not look like a web
+    /// port. A banner-detected service overrides the port table: `http` on
+    /// any port is plain HTTP, `tls` on a web port is HTTPS.
+    pub fn for_port(port: u16, service: Option<&ServiceInfo>) -> Option<Self> {
+        let web = HTTPS_PORTS.contains(&port) || HTTP_PORTS.contains(&port);
+        match service.map(|s| s.name.as_str()) {
+            Some("http") => Some(Self::Http),
+            Some("tls") if web => Some(Self::Https),
+            _ if HTTPS_PORTS.contains(&port) => Some(Self::Https),
+            _ if HTTP_PORTS.contains(&port) => Some(Self::Http),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Http => "http",
+            Self::Https => "https",
+        }
+    }
+}
+
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Summary of the response to `GET /`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HttpInfo {
+    pub scheme: Scheme,
+    pub status: u16,
+    /// Reason phrase, e.g. `Moved Permanently`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub reason: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub powered_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// `Location` of a 3xx response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redirect: Option<String>,
+    /// Security headers the response lacks, by their usual names.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing_headers: Vec<String>,
+}
+
+impl HttpInfo {
+    /// One-line summary for console output.
+    pub fn summary(&self) -> String {
+        let mut s = format!("{} {}", self.scheme, self.status);
+        if !self.reason.is_empty() {
+            s.push_str(&format!(" {}", self.reason));
+        }
+        if let Some(server) = &self.server {
+            s.push_str(&format!(" | {server}"));
+        }
+        if let Some(powered_by) = &self.powered_by {
+            s.push_str(&format!(" | {powered_by}"));
+        }
+        if let Some(title) = &self.title {
+            s.push_str(&format!(" | \"{title}\""));
+        }
+        if let Some(target) = &self.redirect {
+            s.push_str(&format!(" | -> {target}"));
+        }
+        if !self.missing_headers.is_empty() {
+            s.push_str(&format!(" | missing {}", self.missing_headers.join(", ")));
+        }
+        s
+    }
+}
+
+/// Connect to `addr` and fetch `/`, each step bounded by `wait`.
+pub async fn inspect_http(
+    addr: SocketAddr,
+    scheme: Scheme,
+    wait: Duration,
+) -> io::Result<HttpInfo> {
+    let tcp = timeout(wait, TcpStream::connect(addr))
+        .await
+        .map_err(|_| io::ErrorKind::TimedOut)??;
+    http_exchange(tcp, addr, scheme, wait).await
+}
+
+/// Fetch `/` from `addr` over an already connected stream (e.g. a proxy
+/// tunnel), bounded by `wait` per step.
+pub async fn http_exchange(
+    tcp: TcpStream,
+    addr: SocketAddr,
+    scheme: Scheme,
+    wait: Duration,
+) -> io::Result<HttpInfo> {
+    let data = match scheme {
+        Scheme::Http => fetch(tcp, addr, scheme, wait).await?,
+        Scheme::Https => {
+            let tls = tls_connect(tcp, addr.ip(), wait).await?;
+            fetch(tls, addr, scheme, wait).await?
+        }
+    };
+    parse_response(&data, scheme)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not an HTTP response"))
+}
+
+/// Send the request and read until the server closes, the body is complete
+/// or [`MAX_RESPONSE`] bytes arrived. A read timing out after some data
+/// arrived ends the response rather than failing it.
+async fn fetch<S>(
+    mut stream: S,
+    addr: SocketAddr,
+    scheme: Scheme,
+    wait: Duration,
+) -> io::Result<Vec<u8>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let request = format!(
+        "GET / HTTP/1.1\r\nHost: {}\r\nUser-Agent: port_scanner/{}\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+        host_header(addr, scheme),
+        env!("CARGO_PKG_VERSION")
+    );
+    timeout(wait, stream.write_all(request.as_bytes()))
+        .await
+        .map_err(|_| io::ErrorKind::TimedOut)??;
+
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    while data.len() < MAX_RESPONSE {
+        let n = match timeout(wait, stream.read(&mut buf)).await {
+            Ok(Ok(0)) => break,
+            Ok(Ok(n)) => n,
+            // TLS servers often skip close_notify; keep what arrived.
+            Ok(Err(_)) | Err(_) if !data.is_empty() => break,
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Err(io::ErrorKind::TimedOut.into()),
+        };
+        data.extend_from_slice(&buf[..n]);
+        if body_complete(&data) {
+            break;
+        }
+    }
+    Ok(data)
+}
+
+/// `Host` header value: the address, with the port unless it is the
+/// scheme's default.
+fn host_header(addr: SocketAddr, scheme: Scheme) -> String {
+    match (scheme, addr.port()) {
+        (Scheme::Http, 80) | (Scheme::Https, 443) => match addr {
+            SocketAddr::V4(a) => a.ip().to_string(),
+            SocketAddr::V6(a) => format!("[{}]", a.ip()),
+        },
+        _ => addr.to_string(),
+    }
+}
+
+/// The head is in and `Content-Length` bytes of body followed it.
+fn body_complete(data: &[u8]) -> bool {
+    let Some((head, body)) = split_head(data) else {
+        return false;
+    };
+    headers(head)
+        .find(|(k, _)| k.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, v)| v.parse::<usize>().ok())
+        .is_some_and(|len| body.len() >= len)
+}
+
+fn split_head(data: &[u8]) -> Option<(&str, &[u8])> {
+    let end = data.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let head = std::str::from_utf8(&data[..end]).ok()?;
+    Some((head, &data[end + 4..]))
+}
+
+/// Header lines after the status line, as trimmed `(name, value)` pairs.
+fn headers(head: &str) -> impl Iterator<Item = (&str, &str)> {
+    head.lines().skip(1).filter_map(|l| {
+        let (k, v) = l.split_once(':')?;
+        Some((k.trim(), v.trim()))
+    })
+}
+
+/// Summarise a raw response; `None` if it does not start with a status line.
+pub fn parse_response(data: &[u8], scheme: Scheme) -> Option<HttpInfo> {
+    // A response cut off inside the head still has a usable status line.
+    let (head, body) = split_head(data).unwrap_or_else(|| {
+        let text = std::str::from_utf8(data).unwrap_or_default();
+        (text, &[])
+    });
+    let status_line = head.lines().next()?;
+    let mut parts = status_line.splitn(3, ' ');
+    if !parts.next()?.starts_with("HTTP/") {
+        return None;
+    }
+    let status: u16 = parts.next()?.parse().ok()?;
+    let reason = clean(parts.next().unwrap_or_default());
+
+    let header = |name: &str| {
+        headers(head)
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| clean(v))
+    };
+    let mut missing = Vec::new();
+    if scheme == Scheme::Https && header("strict-transport-security").is_none() {
+        missing.push("HSTS".to_string());
+    }
+    if header("content-security-policy").is_none() {
+        missing.push("CSP".to_string());
+    }
+    if header("x-frame-options").is_none() {
+        missing.push("X-Frame-Options".to_string());
+    }
+
+    Some(HttpInfo {
+        scheme,
+        status,
+        reason,
+        server: header("server").filter(|v| !v.is_empty()),
+        powered_by: header("x-powered-by").filter(|v| !v.is_empty()),
+        title: title(body),
+        redirect: (300..400)
+            .contains(&status)
+            .then(|| header("location"))
+            .flatten(),
+        missing_headers: missing,
+    })
+}
+
+/// Text of the first `<title>` element, whitespace collapsed and the common
+/// entities decoded.
+fn title(body: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(body);
+    let lower = text.to_ascii_lowercase();
+    let open = lower.find("<title")?;
+    let start = open + lower[open..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    let raw = text[start..end]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let decoded = raw
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    Some(clean(&decoded)).filter(|t| !t.is_empty())
+}
+
+/// Drop control characters and cap the length.
+fn clean(s: &str) -> String {
+    s.chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_FIELD_LEN)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarises_a_redirect() {
+        let raw = b"HTTP/1.1 301 Moved Permanently\r\n\
+            Server: nginx/1.25.3\r\n\
+            Location: https://10.0.0.5/\r\n\
+            X-Frame-Options: DENY\r\n\
+            Content-Length: 0\r\n\r\n";
+        let info = parse_response(raw, Scheme::Http).unwrap();
+        assert_eq!(
+            (info.status, info.reason.as_str()),
+            (301, "Moved Permanently")
+        );
+        assert_eq!(info.server.as_deref(), Some("nginx/1.25.3"));
+        assert_eq!(info.redirect.as_deref(), Some("https://10.0.0.5/"));
+        // HSTS is not expected over plain HTTP.
+        assert_eq!(info.missing_headers, ["CSP"]);
+        assert_eq!(
+            info.summary(),
+            "http 301 Moved Permanently | nginx/1.25.3 | -> https://10.0.0.5/ | missing CSP"
+        );
+    }
+
+    #[test]
+    fn extracts_title_and_powered_by() {
+        let raw = b"HTTP/1.1 200 OK\r\nX-Powered-By: PHP/8.2.7\r\n\r\n\
+            <html><head><TITLE id=t>\n  Admin &amp; Login\n</TITLE></head></html>";
+        let info = parse_response(raw, Scheme::Https).unwrap();
+        assert_eq!(info.title.as_deref(), Some("Admin & Login"));
+        assert_eq!(info.powered_by.as_deref(), Some("PHP/8.2.7"));
+        assert_eq!(info.redirect, None);
+        assert_eq!(info.missing_headers, ["HSTS", "CSP", "X-Frame-Options"]);
+        assert!(parse_response(b"SSH-2.0-OpenSSH_9.6\r\n", Scheme::Http).is_none());
+    }
+
+    #[test]
+    fn picks_scheme_by_port_and_service() {
+        let svc = |name: &str| ServiceInfo {
+            name: name.into(),
+            version: None,
+            banner: None,
+        };
+        assert_eq!(Scheme::for_port(443, None), Some(Scheme::Https));
+        assert_eq!(Scheme::for_port(8080, None), Some(Scheme::Http));
+        assert_eq!(
+            Scheme::for_port(8080, Some(&svc("tls"))),
+            Some(Scheme::Https)
+        );
+        assert_eq!(
+            Scheme::for_port(9999, Some(&svc("http"))),
+            Some(Scheme::Http)
+        );
+        assert_eq!(Scheme::for_port(22, Some(&svc("ssh"))), None);
+        assert_eq!(Scheme::for_port(993, Some(&svc("tls"))), None);
+    }
+}
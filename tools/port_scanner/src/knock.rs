@@ -0,0 +1,237 @@
+//! Port knocking (`port_scanner knock`).
+//!
+//! A [`Knocker`] sends an ordered sequence of TCP connects and UDP datagrams
+//! to one host, pausing after each, then probes the protected port again to
+//! see whether the sequence opened it. The protected port is also probed
+//! once before the first knock, so a port that was open all along is not
+//! mistaken for a working knock.
+//!
+//! Knocks reuse the scanner's probes: a TCP knock is a connect with a short
+//! timeout (a knock daemon usually drops the SYN, so the timeout is the
+//! normal outcome), a UDP knock is the port's usual UDP probe.
+
+use crate::{
+    bind::LocalBind,
+    scanner::{connect_tcp, PortResult, PortState, Protocol},
+    services::port_for_service,
+    udp::probe_udp_from,
+};
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    time::Duration,
+};
+use tokio::time::sleep;
+
+/// One step of a knock sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Knock {
+    pub port: u16,
+    pub protocol: Protocol,
+    /// Pause after this knock; `None` uses the sequence's delay.
+    pub delay: Option<Duration>,
+}
+
+impl Knock {
+    pub fn tcp(port: u16) -> Self {
+        Self {
+            port,
+            protocol: Protocol::Tcp,
+            delay: None,
+        }
+    }
+
+    pub fn udp(port: u16) -> Self {
+        Self {
+            port,
+            protocol: Protocol::Udp,
+            delay: None,
+        }
+    }
+}
+
+impl FromStr for Knock {
+    type Err = String;
+
+    /// `PORT[/tcp|/udp][@DELAY_MS]`, e.g. `7000`, `8000/udp`, `9000@500`.
+    /// The port may also be a service name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (rest, delay) = match s.split_once('@') {
+            Some((rest, ms)) => {
+                let ms: u64 = ms
+                    .parse()
+                    .map_err(|_| format!("invalid knock delay '{ms}' in '{s}'"))?;
+                (rest, Some(Duration::from_millis(ms)))
+            }
+            None => (s, None),
+        };
+        let (port, protocol) = match rest.split_once('/') {
+            Some((port, proto)) => match proto.to_ascii_lowercase().as_str() {
+                "tcp" => (port, Protocol::Tcp),
+                "udp" => (port, Protocol::Udp),
+                _ => return Err(format!("unknown knock protocol '{proto}' in '{s}'")),
+            },
+            None => (rest, Protocol::Tcp),
+        };
+        let port = match port.parse::<u16>() {
+            Ok(p) if p > 0 => p,
+            Ok(_) => return Err(format!("invalid knock port in '{s}'")),
+            Err(_) => {
+                port_for_service(port).ok_or_else(|| format!("invalid knock port in '{s}'"))?
+            }
+        };
+        Ok(Self {
+            port,
+            protocol,
+            delay,
+        })
+    }
+}
+
+impl fmt::Display for Knock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.port, self.protocol)?;
+        if let Some(d) = self.delay {
+            write!(f, "@{}", d.as_millis())?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of knocking on one host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnockReport {
+    /// The protected port before the first knock.
+    pub before: PortResult,
+    /// One result per knock, in sequence order.
+    pub knocks: Vec<PortResult>,
+    /// The protected port after the last knock's delay.
+    pub after: PortResult,
+}
+
+impl KnockReport {
+    /// The port was not open before the sequence and is open after it.
+    pub fn opened(&self) -> bool {
+        self.before.state != PortState::Open && self.after.state == PortState::Open
+    }
+}
+
+/// Sends a knock sequence and checks the protected TCP port.
+///
+/// # Example
+/// ```no_run
+/// use port_scanner::{Knock, Knocker};
+/// use std::time::Duration;
+///
+/// # async fn run() {
+/// let report = Knocker::new([Knock::tcp(7000), Knock::udp(8000), Knock::tcp(9000)])
+///     .with_delay(Duration::from_millis(300))
+///     .run("10.0.0.5".parse().unwrap(), 22)
+///     .await;
+/// println!("opened: {}", report.opened());
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Knocker {
+    sequence: Vec<Knock>,
+    delay: Duration,
+    knock_timeout: Duration,
+    timeout: Duration,
+    local: LocalBind,
+}
+
+impl Knocker {
+    /// A knocker with a 200ms pause after each knock, 200ms per knock and a
+    /// 1s timeout for probing the protected port.
+    pub fn new<I: IntoIterator<Item = Knock>>(sequence: I) -> Self {
+        Self {
+            sequence: sequence.into_iter().collect(),
+            delay: Duration::from_millis(200),
+            knock_timeout: Duration::from_millis(200),
+            timeout: Duration::from_secs(1),
+            local: LocalBind::default(),
+        }
+    }
+
+    /// Pause after each knock that does not set its own delay. The pause
+    /// after the last knock gives the daemon time to open the port.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// How long a single knock waits for an answer before moving on.
+    pub fn with_knock_timeout(mut self, timeout: Duration) -> Self {
+        self.knock_timeout = timeout;
+        self
+    }
+
+    /// Timeout for the connects to the protected port.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Knock from this local address, port and interface; knock daemons
+    /// usually only open the port to the knocking source address.
+    pub fn with_local_bind(mut self, local: LocalBind) -> Self {
+        self.local = local;
+        self
+    }
+
+    pub fn sequence(&self) -> &[Knock] {
+        &self.sequence
+    }
+
+    /// Send the sequence to `target` without checking anything.
+    pub async fn knock(&self, target: IpAddr) -> Vec<PortResult> {
+        let mut results = Vec::with_capacity(self.sequence.len());
+        for k in &self.sequence {
+            let addr = SocketAddr::new(target, k.port);
+            let result = match k.protocol {
+                Protocol::Tcp => connect_tcp(addr, self.knock_timeout, &self.local).await.0,
+                Protocol::Udp => probe_udp_from(addr, self.knock_timeout, &self.local).await,
+            };
+            results.push(result);
+            sleep(k.delay.unwrap_or(self.delay)).await;
+        }
+        results
+    }
+
+    /// Probe `port`, knock, and probe it again. The first probe leaves from
+    /// an ephemeral port, so a fixed source port is still free for the one
+    /// that counts.
+    pub async fn run(&self, target: IpAddr, port: u16) -> KnockReport {
+        let addr = SocketAddr::new(target, port);
+        let before = connect_tcp(addr, self.timeout, &self.local.without_port())
+            .await
+            .0;
+        let knocks = self.knock(target).await;
+        let after = connect_tcp(addr, self.timeout, &self.local).await.0;
+        KnockReport {
+            before,
+            knocks,
+            after,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_knocks() {
+        assert_eq!("7000".parse(), Ok(Knock::tcp(7000)));
+        assert_eq!("8000/UDP".parse(), Ok(Knock::udp(8000)));
+        let k: Knock = "9000/tcp@500".parse().unwrap();
+        assert_eq!(k.delay, Some(Duration::from_millis(500)));
+        assert_eq!(k.to_string(), "9000/tcp@500");
+        assert_eq!("ssh".parse::<Knock>().unwrap().port, 22);
+        assert!("0".parse::<Knock>().is_err());
+        assert!("7000/sctp".parse::<Knock>().is_err());
+        assert!("7000@soon".parse::<Knock>().is_err());
+    }
+}
@@ -0,0 +1,48 @@
+//! Async TCP port scanning library.
+//!
+//! The [`Scanner`] builder describes what to scan; [`Scanner::scan`] returns a
+//! [`ScanStream`] that yields a [`PortResult`] for every probed port as soon
+//! as it completes. The `port_scanner` binary is a thin CLI over this API.
+
+pub mod banner;
+pub mod bind;
+pub mod checkpoint;
+pub mod diff;
+pub mod discovery;
+pub mod http;
+pub mod knock;
+pub mod output;
+pub mod ports;
+pub mod profile;
+pub mod progress;
+pub mod proxy;
+pub mod scanner;
+pub mod services;
+pub mod syn;
+pub mod targets;
+pub mod timing;
+pub mod tls;
+pub mod udp;
+
+pub use banner::{grab_banner, ServiceInfo};
+pub use bind::LocalBind;
+pub use checkpoint::Checkpoint;
+pub use diff::{write_changes, Change, Drift};
+pub use discovery::{discover, is_host_up, DEFAULT_PING_PORTS};
+pub use http::{http_exchange, inspect_http, HttpInfo, Scheme};
+pub use knock::{Knock, KnockReport, Knocker};
+pub use output::{OutputFormat, ReportWriter, ScanMeta, ScanReport};
+pub use ports::{dedup_sort, parse_ports_list, parse_range, preview_ports};
+pub use profile::{Profile, ProfileError, ProfileSet};
+pub use progress::Progress;
+pub use proxy::{Proxy, ProxyError, ProxyKind, TunnelError};
+pub use scanner::{probe_tcp, PortResult, PortState, Protocol, ScanStream, Scanner, StateCounts};
+pub use services::{port_for_service, service_name, top_ports};
+pub use syn::{syn_available, SynEngine};
+pub use targets::{
+    expand_targets, expand_targets_in, parse_target_list, read_target_file, resolve_all,
+    resolve_host, IpFamily, TargetError, TargetSpec,
+};
+pub use timing::{AdaptiveConfig, CongestionWindow, RateLimiter, RttEstimator};
+pub use tls::{inspect_tls, tls_handshake, TlsInfo};
+pub use udp::{probe_udp, probe_udp_from};
@@ -1,6 +1,24 @@
-use clap::{ArgGroup, Parser};
-use std::{net::ToSocketAddrs, sync::Arc, time::Duration};
-use tokio::{net::TcpStream, sync::Semaphore, time::timeout};
+use clap::{
+    error::ErrorKind, parser::ValueSource, ArgGroup, ArgMatches, Args, CommandFactory,
+    FromArgMatches, Parser, Subcommand,
+};
+use port_scanner::{
+    discover, expand_targets_in, parse_ports_list, parse_range, parse_target_list, preview_ports,
+    read_target_file, resolve_all, service_name, top_ports, write_changes, AdaptiveConfig,
+    Checkpoint, Drift, IpFamily, Knock, Knocker, LocalBind, OutputFormat, PortResult, PortState,
+    Profile, ProfileError, ProfileSet, Progress, Protocol, Proxy, RateLimiter, ReportWriter,
+    ScanMeta, Scanner, StateCounts, SynEngine, DEFAULT_PING_PORTS,
+};
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, BufWriter, IsTerminal, Write},
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio_stream::StreamExt;
 
 /// High-performance port scanner built with Rust
 #[derive(Parser, Debug)]
@@ -8,14 +26,25 @@ use tokio::{net::TcpStream, sync::Semaphore, time::timeout};
 #[command(about = "Simple async TCP port scanner")]
 #[command(group(
     ArgGroup::new("port_spec")
-        .args(["ports", "range"])
+        .args(["ports", "range", "top_ports"])
         .required(false)
 ))]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 struct Cli {
-    /// Target hostname or IP (e.g., 192.168.1.1 or google.com)
-    target: String,
+    /// Targets: hostnames, IPs, CIDR blocks, dash ranges or comma lists
+    /// (e.g., 192.168.1.1, 10.0.0.0/24, 10.0.0.1-50, host1,host2)
+    #[arg(required_unless_present_any = ["input_list", "resume"])]
+    targets: Vec<String>,
+
+    /// Read targets from a file, one or more per line (also accepted as -iL)
+    #[arg(long, value_name = "FILE")]
+    input_list: Option<PathBuf>,
+
+    /// Targets to skip (same syntax as TARGETS)
+    #[arg(long, value_name = "TARGETS")]
+    exclude: Vec<String>,
 
-    /// Comma-separated ports (e.g., 80,443,22)
+    /// Comma-separated ports or service names (e.g., 80,443,22 or ssh,http,3306)
     #[arg(short = 'p', long)]
     ports: Option<String>,
 
@@ -23,8 +52,22 @@ struct Cli {
     #[arg(long)]
     range: Option<String>,
 
-    /// Fast preset (overrides timeout/concurrency/range unless -p/--range provided)
-    #[arg(long)]
+    /// Scan the N most commonly open ports for the selected protocol
+    #[arg(long, value_name = "N")]
+    top_ports: Option<usize>,
+
+    /// Load scan options from a named profile; options given on the command
+    /// line take precedence
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Profile config file [default: ~/.config/port_scanner.toml]
+    #[arg(long, value_name = "FILE", requires = "profile")]
+    profile_file: Option<PathBuf>,
+
+    /// Same as --profile fast (ports 1-1024, 200ms timeout, concurrency 1024
+    /// unless redefined in the profile file)
+    #[arg(long, conflicts_with = "profile")]
     fast: bool,
 
     /// Connection timeout in milliseconds
@@ -34,250 +77,927 @@ struct Cli {
     /// Max concurrent connections
     #[arg(long, default_value_t = 512)]
     concurrency: usize,
+
+    /// Adapt the timeout to measured round-trip times and back off
+    /// concurrency when timeouts spike (--timeout-ms is the starting value)
+    #[arg(long, overrides_with = "no_adaptive")]
+    adaptive: bool,
+
+    /// Turn off --adaptive when a profile enables it
+    #[arg(long, overrides_with = "adaptive")]
+    no_adaptive: bool,
+
+    /// Lower bound for the adaptive timeout, in milliseconds
+    #[arg(long, default_value_t = 50)]
+    min_timeout_ms: u64,
+
+    /// Upper bound for the adaptive timeout, in milliseconds
+    #[arg(long, default_value_t = 3000)]
+    max_timeout_ms: u64,
+
+    /// Re-probe ports that got no answer up to N more times
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    retries: u32,
+
+    /// Skip host discovery and scan every target even if it looks down
+    #[arg(long, overrides_with = "ping")]
+    no_ping: bool,
+
+    /// Run host discovery when a profile sets no_ping
+    #[arg(long, overrides_with = "no_ping")]
+    ping: bool,
+
+    /// TCP ports used to check whether a host is up (ICMP echo is also
+    /// tried when the process is allowed to)
+    #[arg(long, value_name = "PORTS", conflicts_with = "no_ping")]
+    ping_ports: Option<String>,
+
+    /// Never send more than this many probes per second
+    #[arg(long, value_name = "PPS", value_parser = parse_rate)]
+    max_rate: Option<f64>,
+
+    /// Also print ports in these states (comma-separated:
+    /// closed,filtered,open|filtered,proxy-error)
+    #[arg(long, value_delimiter = ',')]
+    show: Vec<PortState>,
+
+    /// Scan UDP instead of TCP (DNS/NTP/SNMP payloads, empty datagram otherwise)
+    #[arg(long, overrides_with = "no_udp")]
+    udp: bool,
+
+    /// Scan TCP when a profile enables --udp
+    #[arg(long, overrides_with = "udp")]
+    no_udp: bool,
+
+    /// Half-open SYN scan over raw sockets (needs root or CAP_NET_RAW; falls
+    /// back to connect scanning without)
+    #[arg(long, conflicts_with_all = ["udp", "proxy"], overrides_with = "no_syn")]
+    syn: bool,
+
+    /// Turn off --syn when a profile enables it
+    #[arg(long, overrides_with = "syn")]
+    no_syn: bool,
+
+    /// Grab service banners / send protocol probes on open ports
+    #[arg(long, overrides_with = "no_banners")]
+    banners: bool,
+
+    /// Turn off --banners when a profile enables it
+    #[arg(long, overrides_with = "banners")]
+    no_banners: bool,
+
+    /// How long to wait for each banner read, in milliseconds (also bounds
+    /// each step of the --tls handshake)
+    #[arg(long, default_value_t = 1000)]
+    banner_timeout_ms: u64,
+
+    /// Complete a TLS handshake on open ports and report protocol, cipher
+    /// and certificate details
+    #[arg(long, conflicts_with = "udp", overrides_with = "no_tls")]
+    tls: bool,
+
+    /// Turn off --tls when a profile enables it
+    #[arg(long, overrides_with = "tls")]
+    no_tls: bool,
+
+    /// Fetch / from open web ports and report status, Server/X-Powered-By,
+    /// title, redirect and missing security headers
+    #[arg(long, conflicts_with = "udp", overrides_with = "no_http_info")]
+    http_info: bool,
+
+    /// Turn off --http-info when a profile enables it
+    #[arg(long, overrides_with = "http_info")]
+    no_http_info: bool,
+
+    /// Tunnel every connect through a proxy: socks5://[user:pass@]host:port
+    /// or http://[user:pass@]host:port (implies --no-ping)
+    #[arg(long, value_name = "URL", conflicts_with = "udp")]
+    proxy: Option<Proxy>,
+
+    /// Only scan IPv4 addresses (hostnames keep their A records)
+    #[arg(short = '4', long, conflicts_with = "ipv6")]
+    ipv4: bool,
+
+    /// Only scan IPv6 addresses (hostnames keep their AAAA records)
+    #[arg(short = '6', long)]
+    ipv6: bool,
+
+    /// Send probes from this local address (implies -4 or -6)
+    #[arg(long, value_name = "ADDR")]
+    source_ip: Option<IpAddr>,
+
+    /// Send probes from this local port (not used for discovery, TLS/HTTP or
+    /// proxy connections)
+    #[arg(short = 'g', long, value_name = "PORT")]
+    source_port: Option<u16>,
+
+    /// Send probes out of this network interface (Linux only)
+    #[arg(short = 'e', long, value_name = "NAME")]
+    interface: Option<String>,
+
+    /// Report format: json, jsonl, csv or nmap-xml (inferred from -o's extension if omitted)
+    #[arg(long, value_name = "FORMAT")]
+    output_format: Option<OutputFormat>,
+
+    /// Write the report to FILE instead of stdout
+    #[arg(short = 'o', long = "output", value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Print only result lines: no header, progress line or summary
+    #[arg(short = 'q', long)]
+    quiet: bool,
+
+    /// Periodically save progress to FILE; Ctrl+C saves it before exiting
+    #[arg(long, value_name = "FILE")]
+    checkpoint: Option<PathBuf>,
+
+    /// Seconds between checkpoint saves
+    #[arg(long, value_name = "SECS", default_value_t = 10)]
+    checkpoint_interval: u64,
+
+    /// Continue the scan saved in FILE (its targets, ports and protocol are
+    /// reused; progress keeps being saved there unless --checkpoint is given)
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["targets", "input_list", "exclude", "ports", "range", "top_ports", "fast", "udp"]
+    )]
+    resume: Option<PathBuf>,
+
+    /// Compare against a previous json/jsonl report and list opened, closed
+    /// and changed ports; exits with status 3 if anything changed
+    #[arg(long, value_name = "REPORT")]
+    baseline: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Send a port-knock sequence, then check whether the protected port opened
+    Knock(KnockArgs),
+}
+
+#[derive(Args, Debug)]
+struct KnockArgs {
+    /// Host to knock on
+    target: String,
+
+    /// Knocks in order, comma-separated: PORT[/tcp|/udp][@DELAY_MS]
+    /// (e.g., 7000,8000/udp,9000@500)
+    #[arg(short = 's', long, value_delimiter = ',', required = true)]
+    sequence: Vec<Knock>,
+
+    /// TCP port the sequence should open
+    #[arg(short = 'p', long)]
+    port: u16,
+
+    /// Pause after each knock without its own @DELAY_MS, in milliseconds
+    /// (the last one gives the port time to open)
+    #[arg(long, default_value_t = 200)]
+    delay_ms: u64,
+
+    /// How long each knock waits for an answer, in milliseconds
+    #[arg(long, default_value_t = 200)]
+    knock_timeout_ms: u64,
+
+    /// Connection timeout for the protected port, in milliseconds
+    #[arg(long, default_value_t = 1000)]
+    timeout_ms: u64,
+
+    /// Knock from this local address
+    #[arg(long, value_name = "ADDR")]
+    source_ip: Option<IpAddr>,
+
+    /// Knock from this local port
+    #[arg(short = 'g', long, value_name = "PORT")]
+    source_port: Option<u16>,
+
+    /// Knock out of this network interface (Linux only)
+    #[arg(short = 'e', long, value_name = "NAME")]
+    interface: Option<String>,
+}
+
+/// Exit status when `--baseline` finds drift.
+const EXIT_DRIFT: i32 = 3;
+
+/// Exit status when `knock` does not open the protected port.
+const EXIT_KNOCK_FAILED: i32 = 4;
+
+fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(r) if r.is_finite() && r > 0.0 => Ok(r),
+        _ => Err(format!("'{s}' is not a positive rate")),
+    }
 }
 
 #[tokio::main]
 async fn main() {
-    let mut cli = Cli::parse();
+    // Nmap-style `-iL` cannot be expressed as a clap short flag.
+    let args = std::env::args_os().map(|a| if a == "-iL" { "--input-list".into() } else { a });
+    let matches = Cli::command().get_matches_from(args);
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if let Some(Command::Knock(args)) = &cli.command {
+        std::process::exit(knock(args).await);
+    }
 
-    // --fast preset
-    if cli.fast && cli.ports.is_none() && cli.range.is_none() {
-        cli.range = Some("1-1024".to_string());
-        cli.timeout_ms = 200;
-        cli.concurrency = 1024;
+    let profile_name = if cli.fast {
+        Some("fast")
+    } else {
+        cli.profile.as_deref()
+    };
+    if let Some(name) = profile_name {
+        let set = match &cli.profile_file {
+            Some(path) => ProfileSet::load(path),
+            None => ProfileSet::load_default(),
+        };
+        let applied = set
+            .and_then(|set| set.get(name))
+            .and_then(|profile| apply_profile(&mut cli, &matches, profile));
+        if let Err(e) = applied {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
+    // Checked here rather than by clap, since a profile may turn on
+    // --adaptive.
+    let on_command_line = |id| matches.value_source(id) == Some(ValueSource::CommandLine);
+    if !cli.adaptive
+        && ["min_timeout_ms", "max_timeout_ms"]
+            .into_iter()
+            .any(on_command_line)
+    {
+        Cli::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "--min-timeout-ms and --max-timeout-ms need --adaptive (or a profile that sets it)",
+            )
+            .exit();
     }
 
+    let resumed = cli
+        .resume
+        .as_ref()
+        .map(|path| match Checkpoint::load(path) {
+            Ok(cp) => cp,
+            Err(e) => {
+                eprintln!("Cannot read checkpoint {}: {e}", path.display());
+                std::process::exit(1);
+            }
+        });
+
+    let mut drift = cli.baseline.as_ref().map(|path| match Drift::load(path) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("Cannot read baseline {}: {e}", path.display());
+            std::process::exit(1);
+        }
+    });
+
+    let protocol = match &resumed {
+        Some(cp) => cp.protocol,
+        None if cli.udp => Protocol::Udp,
+        None => Protocol::Tcp,
+    };
+
     // port set
-    let ports = if let Some(spec) = &cli.ports {
+    let ports = if let Some(cp) = &resumed {
+        cp.port_list()
+    } else if let Some(spec) = &cli.ports {
         parse_ports_list(spec)
     } else if let Some(r) = &cli.range {
         parse_range(r)
+    } else if let Some(n) = cli.top_ports {
+        top_ports(n, protocol)
     } else {
         parse_range("1-1000")
     };
 
     if ports.is_empty() {
-        eprintln!("No valid ports to scan (check -p/--range).");
+        eprintln!("No valid ports to scan (check -p/--range/--top-ports).");
         std::process::exit(1);
     }
 
-    let host = cli.target.clone();
-    if (host.as_str(), 0).to_socket_addrs().is_err() {
-        eprintln!("Invalid target hostname/IP: {}", host);
+    // A report on stdout replaces the human-readable output.
+    let human = cli.output_format.is_none() || cli.output.is_some();
+    // Header, discovery and summary lines around the results.
+    let chatty = human && !cli.quiet;
+
+    let local = LocalBind {
+        ip: cli.source_ip,
+        port: cli.source_port,
+        interface: cli.interface.clone(),
+    };
+    if let Err(e) = local.check() {
+        eprintln!("Cannot send from {}: {e}", describe_bind(&local));
         std::process::exit(1);
     }
 
-    let timeout_dur = Duration::from_millis(cli.timeout_ms);
-    let sem = Arc::new(Semaphore::new(cli.concurrency));
+    // Open the raw sockets up front so the header says what really runs.
+    let syn = match (cli.syn && protocol == Protocol::Tcp).then(|| SynEngine::bound(&local)) {
+        Some(Ok(engine)) => Some(Arc::new(engine)),
+        Some(Err(e)) => {
+            eprintln!("--syn: cannot open raw sockets ({e}); falling back to a connect scan.");
+            None
+        }
+        None => None,
+    };
 
-    println!(
-        "Target: {host}\nPorts: {} ({} total)\nTimeout: {}ms  Concurrency: {}\nStarting scan...",
-        preview_ports(&ports, 20),
-        ports.len(),
-        cli.timeout_ms,
-        cli.concurrency
-    );
+    // One limiter for discovery and the scan, so --max-rate holds for both.
+    let limiter = cli
+        .max_rate
+        .filter(|&pps| pps > 0.0)
+        .map(|pps| Arc::new(RateLimiter::new(pps)));
 
-    let mut tasks = Vec::with_capacity(ports.len());
-    for &port in &ports {
-        let sem = Arc::clone(&sem);
-        let host = host.clone();
-        let t = tokio::spawn(async move {
-            let permit = sem.acquire_owned().await.ok()?; // hold until end of scope
-            let _keep = permit;
-            let addr = format!("{}:{}", host, port);
-            let res = timeout(timeout_dur, TcpStream::connect(&addr)).await;
-            match res {
-                Ok(Ok(_)) => Some(port),
-                _ => None,
-            }
+    // A checkpoint holds the hosts that survived discovery; don't redo it.
+    let (hosts, down) = match &resumed {
+        Some(cp) => (cp.hosts.clone(), cp.down.clone()),
+        None => live_hosts(&cli, &local, limiter.clone(), drift.is_some(), chatty).await,
+    };
+    if let Some(d) = drift.as_mut() {
+        for &addr in &down {
+            d.record_host_down(addr, protocol, &ports);
+        }
+    }
+
+    let target_desc = if hosts.len() == 1 {
+        hosts[0].to_string()
+    } else {
+        format!("{} hosts", hosts.len())
+    };
+    let mut scanner = Scanner::new()
+        .with_targets(hosts)
+        .with_ports(&ports)
+        .with_timeout(Duration::from_millis(cli.timeout_ms))
+        .with_concurrency(cli.concurrency)
+        .with_protocol(protocol)
+        .with_banners(cli.banners)
+        .with_tls(cli.tls)
+        .with_http_info(cli.http_info)
+        .with_banner_timeout(Duration::from_millis(cli.banner_timeout_ms))
+        .with_retries(cli.retries)
+        .with_local_bind(local.clone())
+        .with_completed(resumed.iter().flat_map(Checkpoint::completed));
+    if cli.adaptive {
+        scanner = scanner.with_adaptive_timing(AdaptiveConfig {
+            min_timeout: Duration::from_millis(cli.min_timeout_ms),
+            max_timeout: Duration::from_millis(cli.max_timeout_ms),
+            ..AdaptiveConfig::default()
         });
-        tasks.push(t);
+    }
+    if let Some(limiter) = limiter {
+        scanner = scanner.with_rate_limiter(limiter);
+    }
+    if let Some(engine) = syn.clone() {
+        scanner = scanner.with_syn_engine(engine);
+    }
+    if let Some(proxy) = &cli.proxy {
+        let proxy = proxy.clone().with_local_bind(local.clone());
+        if let Err(e) = proxy.check(Duration::from_millis(cli.timeout_ms)).await {
+            eprintln!("Proxy {proxy}: {e}");
+            std::process::exit(1);
+        }
+        scanner = scanner.with_proxy(proxy);
+    }
+
+    let mut shown = vec![PortState::Open];
+    shown.extend(cli.show.iter().copied());
+    let mut report = match open_report(&cli, &scanner) {
+        Ok(r) => r.map(|w| w.with_states(&shown)),
+        Err(e) => {
+            eprintln!("Cannot open report output: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    if chatty {
+        println!(
+            "Target: {target_desc}\nPorts: {}/{} ({} total{})\nTimeout: {}ms{}  Concurrency: {}{}{}{}\nStarting scan...",
+            preview_ports(&ports, 20),
+            scanner.protocol(),
+            ports.len(),
+            if syn.is_some() { ", SYN scan" } else { "" },
+            cli.timeout_ms,
+            if cli.adaptive { " (adaptive)" } else { "" },
+            cli.concurrency,
+            cli.max_rate
+                .map(|r| format!("  Max rate: {r}/s"))
+                .unwrap_or_default(),
+            cli.proxy
+                .as_ref()
+                .map(|p| format!("\nProxy: {p}"))
+                .unwrap_or_default(),
+            if local.is_default() {
+                String::new()
+            } else {
+                format!("\nSource: {}", describe_bind(&local))
+            }
+        );
     }
 
     let mut open = Vec::new();
-    for t in tasks {
-        if let Ok(Some(p)) = t.await {
-            println!("OPEN  {p}");
-            open.push(p);
+    let mut counts = StateCounts::default();
+    let total_probes = scanner.total_probes();
+    if let Some(cp) = &resumed {
+        if chatty {
+            println!(
+                "Resuming: {}/{} probes already done",
+                cp.completed_count(),
+                cp.completed_count() + total_probes
+            );
+        }
+        counts = cp.counts;
+        for rec in &cp.results {
+            let r = PortResult::from(rec);
+            emit(&r, human, &shown, &mut open, &mut report);
+            if let Some(d) = drift.as_mut() {
+                d.record(&r);
+            }
+        }
+        if let Some(d) = drift.as_mut() {
+            for rec in &cp.drift_results {
+                d.record(&PortResult::from(rec));
+            }
         }
     }
 
+    let checkpoint_path = cli.checkpoint.clone().or_else(|| cli.resume.clone());
+    let mut checkpoint = match (resumed, &checkpoint_path) {
+        (Some(cp), _) => Some(cp),
+        (None, Some(_)) => {
+            let mut cp = Checkpoint::new(&scanner, command_line());
+            cp.down = down;
+            Some(cp)
+        }
+        (None, None) => None,
+    };
+    let save_every = Duration::from_secs(cli.checkpoint_interval);
+    let mut last_save = Instant::now();
+
+    // Without a checkpoint there is nothing to flush: keep the default
+    // Ctrl+C behaviour.
+    let checkpointing = checkpoint.is_some();
+    let interrupt = async move {
+        if checkpointing {
+            let _ = tokio::signal::ctrl_c().await;
+        } else {
+            std::future::pending::<()>().await;
+        }
+    };
+    tokio::pin!(interrupt);
+
+    let mut status = StatusLine::new(
+        Progress::new(total_probes),
+        !cli.quiet && io::stderr().is_terminal(),
+    );
+    let mut redraw = tokio::time::interval(Duration::from_millis(250));
+
+    let mut results = scanner.scan();
+    let mut interrupted = false;
+    loop {
+        let r = tokio::select! {
+            r = results.next() => match r {
+                Some(r) => r,
+                None => break,
+            },
+            _ = redraw.tick() => {
+                status.draw();
+                continue;
+            }
+            _ = &mut interrupt => {
+                interrupted = true;
+                break;
+            }
+        };
+        status.progress.tick();
+        counts.record(r.state);
+        if human && shown.contains(&r.state) {
+            status.clear();
+        }
+        emit(&r, human, &shown, &mut open, &mut report);
+        if let Some(d) = drift.as_mut() {
+            d.record(&r);
+        }
+        if let (Some(cp), Some(path)) = (checkpoint.as_mut(), &checkpoint_path) {
+            cp.record(&r, &shown, drift.as_ref());
+            if last_save.elapsed() >= save_every {
+                save_checkpoint(cp, path);
+                last_save = Instant::now();
+            }
+        }
+    }
+    // Stop the remaining probes; their pairs are not in the checkpoint yet.
+    drop(results);
+    status.clear();
+    open.sort_unstable();
+
+    if let (Some(cp), Some(path)) = (&checkpoint, &checkpoint_path) {
+        save_checkpoint(cp, path);
+        if interrupted {
+            eprintln!(
+                "\nInterrupted. Progress saved to {0}; continue with --resume {0}",
+                path.display()
+            );
+        }
+    }
+    if let Some(w) = report {
+        if let Err(e) = w.finish(counts) {
+            eprintln!("Failed to write report: {e}");
+            std::process::exit(1);
+        }
+    }
+    if interrupted {
+        std::process::exit(130);
+    }
+    let changes = drift.as_ref().map(Drift::changes).unwrap_or_default();
+    if !chatty {
+        if !changes.is_empty() {
+            let _ = write_changes(&mut io::stderr().lock(), &changes);
+            std::process::exit(EXIT_DRIFT);
+        }
+        return;
+    }
+
     println!(
         "\nDone. Open ports: {}",
         if open.is_empty() {
             "none".to_string()
         } else {
-            format!("{:?}", open)
+            open.iter()
+                .map(SocketAddr::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
         }
     );
-}
-
-fn parse_ports_list(spec: &str) -> Vec<u16> {
-    let mut out = Vec::new();
-    for part in spec.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
-        if let Some((a, b)) = part.split_once('-') {
-            out.extend(parse_range(&format!("{}-{}", a, b)));
-        } else if let Ok(p) = part.parse::<u16>() {
-            if p > 0 {
-                out.push(p);
-            }
+    print!(
+        "Open: {}  Closed: {}  Filtered: {}",
+        counts.open, counts.closed, counts.filtered
+    );
+    if scanner.protocol() == Protocol::Udp {
+        print!("  Open|Filtered: {}", counts.open_filtered);
+    }
+    if counts.proxy_error > 0 {
+        print!("  Proxy errors: {}", counts.proxy_error);
+    }
+    println!();
+
+    if let Some(d) = &drift {
+        let since = d
+            .baseline_start
+            .map(|t| format!(" ({})", t.format("%Y-%m-%d %H:%M:%S UTC")))
+            .unwrap_or_default();
+        if changes.is_empty() {
+            println!("\nNo changes since baseline{since}.");
+        } else {
+            println!("\nChanges since baseline{since}:");
+            let _ = write_changes(&mut io::stdout().lock(), &changes);
+            std::process::exit(EXIT_DRIFT);
         }
     }
-    dedup_sort(out)
 }
 
-fn parse_range(spec: &str) -> Vec<u16> {
-    let (a, b) = match spec.split_once('-') {
-        Some((a, b)) => (a.trim(), b.trim()),
-        None => return Vec::new(),
-    };
-    let (Ok(mut start), Ok(mut end)) = (a.parse::<u16>(), b.parse::<u16>()) else {
-        return Vec::new();
+/// Run the `knock` subcommand and return the exit status.
+async fn knock(args: &KnockArgs) -> i32 {
+    let local = LocalBind {
+        ip: args.source_ip,
+        port: args.source_port,
+        interface: args.interface.clone(),
     };
-    if start == 0 {
-        start = 1;
+    if let Err(e) = local.check() {
+        eprintln!("Cannot send from {}: {e}", describe_bind(&local));
+        return 1;
     }
-    if end == 0 {
-        end = 1;
+    let family = args.source_ip.map_or(IpFamily::Any, IpFamily::of);
+    let target = match resolve_all(&args.target).await {
+        Ok(addrs) => match addrs.into_iter().find(|&ip| family.matches(ip)) {
+            Some(ip) => ip,
+            None => {
+                eprintln!("'{}' has no {family} address", args.target);
+                return 1;
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to resolve '{}': {e}", args.target);
+            return 1;
+        }
+    };
+
+    let knocker = Knocker::new(args.sequence.iter().copied())
+        .with_delay(Duration::from_millis(args.delay_ms))
+        .with_knock_timeout(Duration::from_millis(args.knock_timeout_ms))
+        .with_timeout(Duration::from_millis(args.timeout_ms))
+        .with_local_bind(local);
+    let sequence: Vec<_> = args.sequence.iter().map(Knock::to_string).collect();
+    println!("Knocking on {target}: {}", sequence.join(", "));
+
+    let report = knocker.run(target, args.port).await;
+    for (k, r) in args.sequence.iter().zip(&report.knocks) {
+        println!("  {:<12} {}", format!("{}/{}", k.port, k.protocol), r.state);
     }
-    if start > end {
-        std::mem::swap(&mut start, &mut end);
+    let port = format!("{target}:{}/tcp", args.port);
+    if report.opened() {
+        println!(
+            "{port} opened (was {} before knocking).",
+            report.before.state
+        );
+        0
+    } else if report.before.state == PortState::Open {
+        println!("{port} was already open before knocking; the sequence is not what guards it.");
+        EXIT_KNOCK_FAILED
+    } else {
+        println!("{port} is still {} after knocking.", report.after.state);
+        EXIT_KNOCK_FAILED
     }
-    (start..=end).collect()
-}
-
-fn dedup_sort(mut v: Vec<u16>) -> Vec<u16> {
-    v.sort_unstable();
-    v.dedup();
-    v
 }
 
-fn preview_ports(ports: &[u16], max_show: usize) -> String {
-    if ports.len() <= max_show {
-        return format!("{:?}", ports);
+/// Fill in every option the command line did not set from `profile`.
+///
+/// A `--no-<flag>` (or `--ping`) on the command line counts as setting the
+/// flag, so it turns off what the profile enables.
+fn apply_profile(
+    cli: &mut Cli,
+    matches: &ArgMatches,
+    profile: Profile,
+) -> Result<(), ProfileError> {
+    let unset = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+    macro_rules! fill {
+        ($($field:ident),*) => {$(
+            if let (Some(v), true) = (profile.$field.clone(), unset(stringify!($field))) {
+                cli.$field = v;
+            }
+        )*};
     }
-    let mut first = ports[..max_show].to_vec();
-    let last = *ports.last().unwrap();
-    first.push(last);
-    format!("{:?} … (truncated)", first)
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    // --- parse_range ---
-
-    #[test]
-    fn parse_range_normal() {
-        assert_eq!(parse_range("1-5"), vec![1, 2, 3, 4, 5]);
+    macro_rules! fill_flag {
+        ($($field:ident / $negation:ident),*) => {$(
+            if let (Some(v), true, false) = (profile.$field, unset(stringify!($field)), cli.$negation) {
+                cli.$field = v;
+            }
+        )*};
     }
-
-    #[test]
-    fn parse_range_single_port() {
-        assert_eq!(parse_range("80-80"), vec![80]);
+    macro_rules! fill_opt {
+        ($($field:ident),*) => {$(
+            if let (Some(v), true) = (profile.$field.clone(), unset(stringify!($field))) {
+                cli.$field = Some(v);
+            }
+        )*};
     }
 
-    #[test]
-    fn parse_range_swapped_bounds() {
-        // start > end should swap
-        assert_eq!(
-            parse_range("100-90"),
-            vec![90, 91, 92, 93, 94, 95, 96, 97, 98, 99, 100]
-        );
+    // Ports are chosen as a unit: -p on the command line replaces a
+    // profile's range rather than combining with it.
+    if profile.has_port_spec() && ["ports", "range", "top_ports"].into_iter().all(unset) {
+        cli.ports = profile.ports.clone();
+        cli.range = profile.range.clone();
+        cli.top_ports = profile.top_ports;
     }
-
-    #[test]
-    fn parse_range_zero_clamped() {
-        // port 0 is invalid; should be clamped to 1
-        let r = parse_range("0-3");
-        assert_eq!(r, vec![1, 2, 3]);
+    fill!(
+        timeout_ms,
+        concurrency,
+        min_timeout_ms,
+        max_timeout_ms,
+        retries,
+        show,
+        banner_timeout_ms
+    );
+    fill_flag!(
+        adaptive / no_adaptive,
+        no_ping / ping,
+        udp / no_udp,
+        syn / no_syn,
+        banners / no_banners,
+        tls / no_tls,
+        http_info / no_http_info
+    );
+    fill!(ipv4, ipv6);
+    fill_opt!(ping_ports, max_rate, source_ip, source_port, interface);
+    if let (Some(url), true) = (&profile.proxy, unset("proxy")) {
+        let proxy = url
+            .parse()
+            .map_err(|reason| ProfileError::Proxy { reason })?;
+        cli.proxy = Some(proxy);
     }
-
-    #[test]
-    fn parse_range_no_dash() {
-        assert!(parse_range("80").is_empty());
+    let conflict = if cli.udp && (cli.tls || cli.http_info || cli.proxy.is_some() || cli.syn) {
+        Some("UDP scans cannot use --tls, --http-info, --proxy or --syn")
+    } else if cli.syn && cli.proxy.is_some() {
+        Some("--syn cannot go through a proxy")
+    } else if cli.ipv4 && cli.ipv6 {
+        Some("-4 and -6 cannot be combined")
+    } else if cli.no_ping && cli.ping_ports.is_some() {
+        Some("--ping-ports cannot be used with --no-ping")
+    } else {
+        None
+    };
+    match conflict {
+        Some(reason) => Err(ProfileError::Conflict { reason }),
+        None => Ok(()),
     }
+}
 
-    #[test]
-    fn parse_range_invalid_numbers() {
-        assert!(parse_range("abc-xyz").is_empty());
+/// Address family to scan: `-4`/`-6`, or the family of `--source-ip`.
+fn ip_family(cli: &Cli) -> IpFamily {
+    let flag = match (cli.ipv4, cli.ipv6) {
+        (true, _) => IpFamily::V4,
+        (_, true) => IpFamily::V6,
+        _ => IpFamily::Any,
+    };
+    match cli.source_ip.map(IpFamily::of) {
+        Some(src) if flag != IpFamily::Any && flag != src => {
+            eprintln!("--source-ip is an {src} address but {flag} was requested.");
+            std::process::exit(1);
+        }
+        Some(src) => src,
+        None => flag,
     }
+}
 
-    #[test]
-    fn parse_range_with_spaces() {
-        assert_eq!(parse_range("  20 - 22 "), vec![20, 21, 22]);
+/// `10.0.0.5:53 on eth0`, leaving out whatever is not pinned.
+fn describe_bind(local: &LocalBind) -> String {
+    let mut out = match (local.ip, local.port) {
+        (Some(ip), Some(port)) => SocketAddr::new(ip, port).to_string(),
+        (Some(ip), None) => ip.to_string(),
+        (None, Some(port)) => format!("port {port}"),
+        (None, None) => String::new(),
+    };
+    match (&local.interface, out.is_empty()) {
+        (Some(iface), true) => out = format!("interface {iface}"),
+        (Some(iface), false) => out.push_str(&format!(" on {iface}")),
+        (None, _) => {}
     }
+    out
+}
 
-    // --- parse_ports_list ---
-
-    #[test]
-    fn parse_ports_list_single() {
-        assert_eq!(parse_ports_list("80"), vec![80]);
-    }
+/// Progress line redrawn in place on stderr.
+struct StatusLine {
+    progress: Progress,
+    enabled: bool,
+    visible: bool,
+}
 
-    #[test]
-    fn parse_ports_list_multiple() {
-        assert_eq!(parse_ports_list("22,80,443"), vec![22, 80, 443]);
+impl StatusLine {
+    fn new(progress: Progress, enabled: bool) -> Self {
+        Self {
+            progress,
+            enabled,
+            visible: false,
+        }
     }
 
-    #[test]
-    fn parse_ports_list_with_range() {
-        assert_eq!(parse_ports_list("22,80-82,443"), vec![22, 80, 81, 82, 443]);
+    fn draw(&mut self) {
+        if self.enabled {
+            eprint!("\r\x1b[2K{}", self.progress.line());
+            self.visible = true;
+        }
     }
 
-    #[test]
-    fn parse_ports_list_deduplicates() {
-        assert_eq!(parse_ports_list("80,80,443,443"), vec![80, 443]);
+    /// Erase the line so regular output doesn't get mixed into it.
+    fn clear(&mut self) {
+        if self.visible {
+            eprint!("\r\x1b[2K");
+            self.visible = false;
+        }
     }
+}
 
-    #[test]
-    fn parse_ports_list_unsorted_input() {
-        assert_eq!(parse_ports_list("443,22,80"), vec![22, 80, 443]);
+/// Print a result and add it to the report and the open-port list.
+fn emit(
+    r: &PortResult,
+    human: bool,
+    shown: &[PortState],
+    open: &mut Vec<SocketAddr>,
+    report: &mut Option<ReportWriter<Box<dyn Write>>>,
+) {
+    let sa = SocketAddr::new(r.addr, r.port);
+    if r.state == PortState::Open {
+        open.push(sa);
     }
-
-    #[test]
-    fn parse_ports_list_skips_zero() {
-        assert_eq!(parse_ports_list("0,80,443"), vec![80, 443]);
+    if human && shown.contains(&r.state) {
+        match &r.service {
+            Some(svc) => println!(
+                "{:<14}{:<22} {} {}",
+                r.state.as_str().to_uppercase(),
+                sa,
+                svc.name,
+                svc.version.as_deref().unwrap_or("")
+            ),
+            None => println!(
+                "{:<14}{:<22} {}",
+                r.state.as_str().to_uppercase(),
+                sa,
+                service_name(r.port, r.protocol).unwrap_or("")
+            ),
+        }
+        if let Some(tls) = &r.tls {
+            println!("{:<14}{}", "", tls.summary());
+        }
+        if let Some(http) = &r.http {
+            println!("{:<14}{}", "", http.summary());
+        }
     }
-
-    #[test]
-    fn parse_ports_list_empty_string() {
-        assert!(parse_ports_list("").is_empty());
+    if let Some(w) = report.as_mut() {
+        if let Err(e) = w.write(r) {
+            eprintln!("Failed to write report: {e}");
+            std::process::exit(1);
+        }
     }
+}
 
-    #[test]
-    fn parse_ports_list_extra_commas() {
-        assert_eq!(parse_ports_list(",80,,443,"), vec![80, 443]);
+fn save_checkpoint(cp: &Checkpoint, path: &Path) {
+    if let Err(e) = cp.save(path) {
+        eprintln!("Failed to save checkpoint {}: {e}", path.display());
     }
+}
 
-    // --- dedup_sort ---
+fn command_line() -> String {
+    std::env::args().collect::<Vec<_>>().join(" ")
+}
 
-    #[test]
-    fn dedup_sort_removes_duplicates_and_sorts() {
-        assert_eq!(dedup_sort(vec![3, 1, 2, 1, 3]), vec![1, 2, 3]);
+/// Load the targets and split them into those that pass host discovery and
+/// those that don't. With `baseline` an empty scan is still worth running:
+/// every host going dark is drift.
+async fn live_hosts(
+    cli: &Cli,
+    local: &LocalBind,
+    limiter: Option<Arc<RateLimiter>>,
+    baseline: bool,
+    human: bool,
+) -> (Vec<IpAddr>, Vec<IpAddr>) {
+    let hosts = match load_targets(cli).await {
+        Ok(h) if h.is_empty() => {
+            eprintln!("No targets left to scan (check --exclude).");
+            std::process::exit(1);
+        }
+        Ok(h) => h,
+        Err(e) => {
+            eprintln!("Invalid targets: {e}");
+            std::process::exit(1);
+        }
+    };
+    // Discovery probes would bypass the proxy; the targets may not even be
+    // routable from here.
+    if cli.no_ping || cli.proxy.is_some() {
+        return (hosts, Vec::new());
     }
 
-    #[test]
-    fn dedup_sort_empty() {
-        assert_eq!(dedup_sort(vec![]), Vec::<u16>::new());
+    let ping_ports = match &cli.ping_ports {
+        Some(spec) => parse_ports_list(spec),
+        None => DEFAULT_PING_PORTS.to_vec(),
+    };
+    if ping_ports.is_empty() {
+        eprintln!("No valid ports in --ping-ports.");
+        std::process::exit(1);
     }
-
-    // --- preview_ports ---
-
-    #[test]
-    fn preview_ports_short_list() {
-        let ports = vec![22, 80, 443];
-        let s = preview_ports(&ports, 5);
-        assert_eq!(s, "[22, 80, 443]");
+    let wait = Duration::from_millis(cli.timeout_ms) * (cli.retries + 1);
+    let up = discover(
+        &hosts,
+        &ping_ports,
+        wait,
+        cli.concurrency / ping_ports.len(),
+        local,
+        limiter,
+    )
+    .await;
+    if human {
+        println!("Host discovery: {}/{} up", up.len(), hosts.len());
     }
+    if up.is_empty() && !baseline {
+        eprintln!("No hosts appear to be up (use --no-ping to scan them anyway).");
+        std::process::exit(1);
+    }
+    let live: HashSet<_> = up.iter().collect();
+    let down = hosts
+        .iter()
+        .filter(|h| !live.contains(h))
+        .copied()
+        .collect();
+    (up, down)
+}
 
-    #[test]
-    fn preview_ports_truncated() {
-        let ports: Vec<u16> = (1..=100).collect();
-        let s = preview_ports(&ports, 3);
-        assert!(s.contains("truncated"));
-        assert!(s.contains("100")); // last port included
+/// Combine positional targets and `--input-list`, minus `--exclude`.
+async fn load_targets(cli: &Cli) -> Result<Vec<IpAddr>, port_scanner::TargetError> {
+    let mut include = Vec::new();
+    for t in &cli.targets {
+        include.extend(parse_target_list(t)?);
+    }
+    if let Some(path) = &cli.input_list {
+        include.extend(read_target_file(path)?);
+    }
+    let mut exclude = Vec::new();
+    for t in &cli.exclude {
+        exclude.extend(parse_target_list(t)?);
     }
+    expand_targets_in(&include, &exclude, ip_family(cli)).await
+}
+
+/// Open the `--output-format` / `-o` report sink, if one was requested.
+fn open_report(cli: &Cli, scanner: &Scanner) -> io::Result<Option<ReportWriter<Box<dyn Write>>>> {
+    let format = match (cli.output_format, &cli.output) {
+        (Some(f), _) => f,
+        (None, Some(path)) => OutputFormat::from_extension(path).unwrap_or(OutputFormat::Json),
+        (None, None) => return Ok(None),
+    };
+    let out: Box<dyn Write> = match &cli.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout().lock()),
+    };
+    ReportWriter::new(format, out, ScanMeta::new(scanner, command_line())).map(Some)
 }
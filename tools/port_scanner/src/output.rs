@@ -0,0 +1,789 @@
+//! Machine-readable report writers: JSON, JSON Lines, CSV and Nmap XML.
+//!
+//! JSON Lines and CSV are written as results arrive; JSON and Nmap XML are
+//! buffered until [`ReportWriter::finish`] because both need a closing
+//! summary (and XML groups ports per host).
+
+use crate::{
+    banner::ServiceInfo,
+    http::HttpInfo,
+    scanner::{PortResult, PortState, Protocol, Scanner, StateCounts},
+    services::service_name,
+    tls::TlsInfo,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{self, Write},
+    net::IpAddr,
+    str::FromStr,
+    time::Duration,
+};
+
+/// Report serialization format selected with `--output-format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Json,
+    Jsonl,
+    Csv,
+    NmapXml,
+}
+
+impl OutputFormat {
+    /// Guess a format from an output file extension.
+    pub fn from_extension(path: &std::path::Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "jsonl" | "ndjson" => Some(Self::Jsonl),
+            "csv" => Some(Self::Csv),
+            "xml" => Some(Self::NmapXml),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "jsonl" | "ndjson" => Ok(Self::Jsonl),
+            "csv" => Ok(Self::Csv),
+            "nmap-xml" | "xml" => Ok(Self::NmapXml),
+            other => Err(format!(
+                "unknown output format '{other}' (expected json, jsonl, csv or nmap-xml)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Json => "json",
+            Self::Jsonl => "jsonl",
+            Self::Csv => "csv",
+            Self::NmapXml => "nmap-xml",
+        })
+    }
+}
+
+/// Serializable form of a [`PortResult`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PortRecord {
+    pub host: IpAddr,
+    pub port: u16,
+    pub protocol: Protocol,
+    pub state: PortState,
+    pub latency_ms: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub banner: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<Box<TlsInfo>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http: Option<Box<HttpInfo>>,
+}
+
+impl From<&PortResult> for PortRecord {
+    fn from(r: &PortResult) -> Self {
+        let svc = r.service.as_ref();
+        Self {
+            host: r.addr,
+            port: r.port,
+            protocol: r.protocol,
+            state: r.state,
+            latency_ms: r.latency.as_secs_f64() * 1000.0,
+            service: svc
+                .map(|s| s.name.clone())
+                .or_else(|| service_name(r.port, r.protocol).map(String::from)),
+            version: svc.and_then(|s| s.version.clone()),
+            banner: svc.and_then(|s| s.banner.clone()),
+            tls: r.tls.clone(),
+            http: r.http.clone(),
+        }
+    }
+}
+
+impl From<&PortRecord> for PortResult {
+    /// Rebuild a result from its serialized form. A service is only attached
+    /// when the record carries probe output; table names are re-derived.
+    fn from(rec: &PortRecord) -> Self {
+        let probed = rec.version.is_some() || rec.banner.is_some();
+        Self {
+            addr: rec.host,
+            port: rec.port,
+            protocol: rec.protocol,
+            state: rec.state,
+            latency: Duration::from_secs_f64(rec.latency_ms.max(0.0) / 1000.0),
+            service: probed.then(|| ServiceInfo {
+                name: rec.service.clone().unwrap_or_else(|| "unknown".into()),
+                version: rec.version.clone(),
+                banner: rec.banner.clone(),
+            }),
+            tls: rec.tls.clone(),
+            http: rec.http.clone(),
+        }
+    }
+}
+
+/// Scan parameters and start time, written at the head of a report.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScanMeta {
+    pub scanner: String,
+    pub version: String,
+    pub args: String,
+    pub start: DateTime<Utc>,
+    #[serde(default)]
+    pub protocol: Protocol,
+    /// TCP ports were probed with half-open SYN scans.
+    #[serde(default)]
+    pub syn: bool,
+    pub hosts: usize,
+    /// Compressed port list, e.g. `22,80-90,443`.
+    pub ports: String,
+    pub port_count: usize,
+    pub timeout_ms: u64,
+    pub concurrency: usize,
+}
+
+impl ScanMeta {
+    /// Describe a scan that is starting now.
+    pub fn new(scanner: &Scanner, args: impl Into<String>) -> Self {
+        Self {
+            scanner: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            args: args.into(),
+            start: Utc::now(),
+            protocol: scanner.protocol(),
+            syn: scanner.syn() && scanner.protocol() == Protocol::Tcp,
+            hosts: scanner.targets().len(),
+            ports: compress_ports(scanner.ports()),
+            port_count: scanner.ports().len(),
+            timeout_ms: scanner.timeout().as_millis() as u64,
+            concurrency: scanner.concurrency(),
+        }
+    }
+}
+
+/// End time and per-state totals, written at the tail of a report.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScanSummary {
+    pub end: DateTime<Utc>,
+    pub elapsed_secs: f64,
+    pub open: usize,
+    pub closed: usize,
+    pub filtered: usize,
+    #[serde(default)]
+    pub open_filtered: usize,
+    #[serde(default)]
+    pub proxy_error: usize,
+}
+
+/// Complete JSON report (`--output-format json`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScanReport {
+    #[serde(flatten)]
+    pub meta: ScanMeta,
+    pub results: Vec<PortRecord>,
+    pub summary: ScanSummary,
+}
+
+/// One line of a `jsonl` report.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum JsonlEvent {
+    Start(ScanMeta),
+    Port(PortRecord),
+    Summary(ScanSummary),
+}
+
+/// Incremental report writer.
+pub struct ReportWriter<W: Write> {
+    format: OutputFormat,
+    out: W,
+    meta: ScanMeta,
+    include: Vec<PortState>,
+    /// Buffered records (JSON / XML only).
+    records: Vec<PortRecord>,
+    /// Per-host counts of results left out by the state filter (XML extraports).
+    omitted: BTreeMap<IpAddr, StateCounts>,
+}
+
+impl<W: Write> ReportWriter<W> {
+    /// Create a writer and emit any header the format needs.
+    pub fn new(format: OutputFormat, mut out: W, meta: ScanMeta) -> io::Result<Self> {
+        match format {
+            OutputFormat::Jsonl => {
+                serde_json::to_writer(&mut out, &JsonlEvent::Start(meta.clone()))?;
+                writeln!(out)?;
+            }
+            OutputFormat::Csv => writeln!(
+                out,
+                "host,port,protocol,state,latency_ms,service,version,banner"
+            )?,
+            OutputFormat::Json | OutputFormat::NmapXml => {}
+        }
+        Ok(Self {
+            format,
+            out,
+            meta,
+            include: PortState::ALL.to_vec(),
+            records: Vec::new(),
+            omitted: BTreeMap::new(),
+        })
+    }
+
+    /// Only record results in these states (all states by default).
+    pub fn with_states(mut self, states: &[PortState]) -> Self {
+        self.include = states.to_vec();
+        self
+    }
+
+    /// Record one probe result.
+    pub fn write(&mut self, r: &PortResult) -> io::Result<()> {
+        if !self.include.contains(&r.state) {
+            self.omitted.entry(r.addr).or_default().record(r.state);
+            return Ok(());
+        }
+        let rec = PortRecord::from(r);
+        match self.format {
+            OutputFormat::Jsonl => {
+                serde_json::to_writer(&mut self.out, &JsonlEvent::Port(rec))?;
+                writeln!(self.out)?;
+            }
+            OutputFormat::Csv => writeln!(
+                self.out,
+                "{},{},{},{},{:.3},{},{},{}",
+                rec.host,
+                rec.port,
+                rec.protocol,
+                rec.state,
+                rec.latency_ms,
+                csv_field(rec.service.as_deref()),
+                csv_field(rec.version.as_deref()),
+                csv_field(rec.banner.as_deref())
+            )?,
+            OutputFormat::Json | OutputFormat::NmapXml => self.records.push(rec),
+        }
+        Ok(())
+    }
+
+    /// Write the trailer (and any buffered body) and flush.
+    pub fn finish(mut self, counts: StateCounts) -> io::Result<W> {
+        let end = Utc::now();
+        let summary = ScanSummary {
+            end,
+            elapsed_secs: (end - self.meta.start).num_milliseconds() as f64 / 1000.0,
+            open: counts.open,
+            closed: counts.closed,
+            filtered: counts.filtered,
+            open_filtered: counts.open_filtered,
+            proxy_error: counts.proxy_error,
+        };
+        match self.format {
+            OutputFormat::Json => {
+                let report = ScanReport {
+                    meta: self.meta.clone(),
+                    results: std::mem::take(&mut self.records),
+                    summary,
+                };
+                serde_json::to_writer_pretty(&mut self.out, &report)?;
+                writeln!(self.out)?;
+            }
+            OutputFormat::Jsonl => {
+                serde_json::to_writer(&mut self.out, &JsonlEvent::Summary(summary))?;
+                writeln!(self.out)?;
+            }
+            OutputFormat::Csv => {}
+            OutputFormat::NmapXml => self.write_nmap_xml(&summary)?,
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write_nmap_xml(&mut self, summary: &ScanSummary) -> io::Result<()> {
+        let meta = &self.meta;
+        let out = &mut self.out;
+        let start = meta.start.timestamp();
+        let end = summary.end.timestamp();
+
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(out, "<!DOCTYPE nmaprun>")?;
+        writeln!(
+            out,
+            r#"<nmaprun scanner="{}" args="{}" start="{}" startstr="{}" version="{}" xmloutputversion="1.05">"#,
+            xml_escape(&meta.scanner),
+            xml_escape(&meta.args),
+            start,
+            nmap_time(&meta.start),
+            xml_escape(&meta.version)
+        )?;
+        writeln!(
+            out,
+            r#"<scaninfo type="{}" protocol="{}" numservices="{}" services="{}"/>"#,
+            match meta.protocol {
+                Protocol::Tcp if meta.syn => "syn",
+                Protocol::Tcp => "connect",
+                Protocol::Udp => "udp",
+            },
+            meta.protocol,
+            meta.port_count,
+            meta.ports
+        )?;
+
+        let mut by_host: BTreeMap<IpAddr, Vec<&PortRecord>> = BTreeMap::new();
+        for rec in &self.records {
+            by_host.entry(rec.host).or_default().push(rec);
+        }
+        for host in self.omitted.keys() {
+            by_host.entry(*host).or_default();
+        }
+
+        let mut hosts_up = 0;
+        for (host, mut recs) in by_host {
+            recs.sort_by_key(|r| r.port);
+            let omitted = self.omitted.get(&host).copied().unwrap_or_default();
+            let responded = omitted.open + omitted.closed > 0
+                || recs
+                    .iter()
+                    .any(|r| !matches!(r.state, PortState::Filtered | PortState::ProxyError));
+            if responded {
+                hosts_up += 1;
+            }
+            let any_open = omitted.open > 0 || recs.iter().any(|r| r.state == PortState::Open);
+            let proto = meta.protocol;
+            let (status, reason) = match (responded, any_open) {
+                (true, true) => ("up", nmap_reason(PortState::Open, proto, meta.syn)),
+                (true, false) => ("up", nmap_reason(PortState::Closed, proto, meta.syn)),
+                (false, _) => ("down", nmap_reason(PortState::Filtered, proto, meta.syn)),
+            };
+
+            writeln!(out, r#"<host starttime="{start}" endtime="{end}">"#)?;
+            writeln!(out, r#"<status state="{status}" reason="{reason}"/>"#)?;
+            writeln!(
+                out,
+                r#"<address addr="{}" addrtype="{}"/>"#,
+                host,
+                if host.is_ipv4() { "ipv4" } else { "ipv6" }
+            )?;
+            writeln!(out, "<ports>")?;
+            for (state, count) in [
+                (PortState::Closed, omitted.closed),
+                (PortState::Filtered, omitted.filtered),
+                (PortState::OpenFiltered, omitted.open_filtered),
+                (PortState::ProxyError, omitted.proxy_error),
+            ] {
+                if count > 0 {
+                    writeln!(
+                        out,
+                        r#"<extraports state="{state}" count="{count}"><extrareasons reason="{}" count="{count}"/></extraports>"#,
+                        nmap_reason(state, proto, meta.syn)
+                    )?;
+                }
+            }
+            for rec in recs {
+                write!(
+                    out,
+                    r#"<port protocol="{}" portid="{}"><state state="{}" reason="{}" reason_ttl="0"/>"#,
+                    rec.protocol,
+                    rec.port,
+                    rec.state,
+                    nmap_reason(rec.state, rec.protocol, meta.syn)
+                )?;
+                if let Some(name) = &rec.service {
+                    write!(out, r#"<service name="{}""#, xml_escape(name))?;
+                    if let Some(version) = &rec.version {
+                        write!(out, r#" product="{}""#, xml_escape(version))?;
+                    }
+                    // Banner-derived names are probed; bare names come from the port table.
+                    if rec.banner.is_some() || rec.version.is_some() {
+                        write!(out, r#" method="probed" conf="10"/>"#)?;
+                    } else {
+                        write!(out, r#" method="table" conf="3"/>"#)?;
+                    }
+                }
+                if let Some(tls) = &rec.tls {
+                    write_ssl_cert_script(out, tls)?;
+                }
+                if let Some(http) = &rec.http {
+                    write_http_scripts(out, http)?;
+                }
+                writeln!(out, "</port>")?;
+            }
+            writeln!(out, "</ports>")?;
+            writeln!(out, "</host>")?;
+        }
+
+        let total_hosts = meta.hosts;
+        writeln!(out, "<runstats>")?;
+        writeln!(
+            out,
+            r#"<finished time="{}" timestr="{}" elapsed="{:.2}" summary="{} IP addresses ({} hosts up) scanned in {:.2} seconds" exit="success"/>"#,
+            end,
+            nmap_time(&summary.end),
+            summary.elapsed_secs,
+            total_hosts,
+            hosts_up,
+            summary.elapsed_secs
+        )?;
+        writeln!(
+            out,
+            r#"<hosts up="{}" down="{}" total="{}"/>"#,
+            hosts_up,
+            total_hosts.saturating_sub(hosts_up),
+            total_hosts
+        )?;
+        writeln!(out, "</runstats>")?;
+        writeln!(out, "</nmaprun>")?;
+        Ok(())
+    }
+}
+
+/// TLS details as Nmap's `ssl-cert` script element.
+fn write_ssl_cert_script(out: &mut impl Write, tls: &TlsInfo) -> io::Result<()> {
+    let summary = format!(
+        "Subject: {}\nIssuer: {}\nPublic Key type: {}\nPublic Key bits: {}\nNot valid before: {}\nNot valid after: {}",
+        tls.subject,
+        tls.issuer,
+        tls.key_type,
+        tls.key_bits,
+        tls.not_before.format("%Y-%m-%dT%H:%M:%S"),
+        tls.not_after.format("%Y-%m-%dT%H:%M:%S")
+    );
+    write!(
+        out,
+        r#"<script id="ssl-cert" output="{}"><elem key="protocol">{}</elem><elem key="cipher">{}</elem><elem key="subject">{}</elem><elem key="issuer">{}</elem>"#,
+        xml_escape(&summary),
+        xml_escape(&tls.version),
+        xml_escape(&tls.cipher),
+        xml_escape(&tls.subject),
+        xml_escape(&tls.issuer)
+    )?;
+    for name in &tls.san {
+        write!(out, r#"<elem key="san">{}</elem>"#, xml_escape(name))?;
+    }
+    write!(
+        out,
+        r#"<elem key="notBefore">{}</elem><elem key="notAfter">{}</elem><elem key="keyType">{}</elem><elem key="bits">{}</elem><elem key="selfSigned">{}</elem><elem key="expired">{}</elem></script>"#,
+        tls.not_before.to_rfc3339(),
+        tls.not_after.to_rfc3339(),
+        xml_escape(&tls.key_type),
+        tls.key_bits,
+        tls.self_signed,
+        tls.expired
+    )
+}
+
+/// HTTP details as Nmap's `http-title` and `http-server-header` script
+/// elements, plus `http-security-headers` listing what is missing.
+fn write_http_scripts(out: &mut impl Write, http: &HttpInfo) -> io::Result<()> {
+    let title = match (&http.title, &http.redirect) {
+        (_, Some(target)) => format!("Did not follow redirect to {target}"),
+        (Some(title), None) => title.clone(),
+        (None, None) => "Site doesn't have a title.".to_string(),
+    };
+    write!(
+        out,
+        r#"<script id="http-title" output="{}">"#,
+        xml_escape(&title)
+    )?;
+    if let Some(t) = &http.title {
+        write!(out, r#"<elem key="title">{}</elem>"#, xml_escape(t))?;
+    }
+    if let Some(target) = &http.redirect {
+        write!(
+            out,
+            r#"<elem key="redirect_url">{}</elem>"#,
+            xml_escape(target)
+        )?;
+    }
+    write!(out, "</script>")?;
+    let server: Vec<_> = http.server.iter().chain(&http.powered_by).collect();
+    if !server.is_empty() {
+        let joined: Vec<_> = server.iter().map(|s| xml_escape(s)).collect();
+        write!(
+            out,
+            r#"<script id="http-server-header" output="{}">"#,
+            joined.join("&#xa;")
+        )?;
+        for s in joined {
+            write!(out, "<elem>{s}</elem>")?;
+        }
+        write!(out, "</script>")?;
+    }
+    if !http.missing_headers.is_empty() {
+        write!(
+            out,
+            r#"<script id="http-security-headers" output="Missing: {}">"#,
+            xml_escape(&http.missing_headers.join(", "))
+        )?;
+        for h in &http.missing_headers {
+            write!(out, r#"<elem key="missing">{}</elem>"#, xml_escape(h))?;
+        }
+        write!(out, "</script>")?;
+    }
+    Ok(())
+}
+
+/// Nmap's `reason` attribute for a probe outcome.
+fn nmap_reason(state: PortState, protocol: Protocol, syn: bool) -> &'static str {
+    match (state, protocol) {
+        (PortState::Open, Protocol::Tcp) => "syn-ack",
+        (PortState::Open, Protocol::Udp) => "udp-response",
+        (PortState::Closed, Protocol::Tcp) if syn => "reset",
+        (PortState::Closed, Protocol::Tcp) => "conn-refused",
+        (PortState::Closed, Protocol::Udp) => "port-unreach",
+        (PortState::Filtered, _) | (PortState::OpenFiltered, _) => "no-response",
+        (PortState::ProxyError, _) => "proxy-error",
+    }
+}
+
+/// Nmap `startstr`/`timestr` format, e.g. `Mon Jan  1 00:00:00 2024`.
+fn nmap_time(t: &DateTime<Utc>) -> String {
+    t.format("%a %b %e %H:%M:%S %Y").to_string()
+}
+
+/// Quote a CSV field if it contains separators or quotes.
+fn csv_field(value: Option<&str>) -> String {
+    match value {
+        None => String::new(),
+        Some(v) if v.contains([',', '"', '\n', '\r']) => format!("\"{}\"", v.replace('"', "\"\"")),
+        Some(v) => v.to_string(),
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Collapse a sorted port list into `a-b` runs: `[1,2,3,80]` → `1-3,80`.
+pub fn compress_ports(ports: &[u16]) -> String {
+    let mut parts = Vec::new();
+    let mut i = 0;
+    while i < ports.len() {
+        let start = ports[i];
+        let mut end = start;
+        while i + 1 < ports.len() && ports[i + 1] == end.wrapping_add(1) {
+            i += 1;
+            end = ports[i];
+        }
+        parts.push(if start == end {
+            start.to_string()
+        } else {
+            format!("{start}-{end}")
+        });
+        i += 1;
+    }
+    parts.join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(addr: &str, port: u16, state: PortState) -> PortResult {
+        PortResult {
+            addr: addr.parse().unwrap(),
+            port,
+            protocol: Protocol::Tcp,
+            state,
+            latency: Duration::from_micros(1500),
+            service: None,
+            tls: None,
+            http: None,
+        }
+    }
+
+    fn meta() -> ScanMeta {
+        let scanner = Scanner::new()
+            .with_targets(["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()])
+            .with_ports(&[22, 23, 24, 80]);
+        ScanMeta::new(&scanner, "port_scanner 10.0.0.1-2 -p 22-24,80 & <x>")
+    }
+
+    fn sample() -> Vec<PortResult> {
+        let mut ssh = result("10.0.0.1", 22, PortState::Open);
+        ssh.service = Some(ServiceInfo {
+            name: "ssh".into(),
+            version: Some("OpenSSH_9.6".into()),
+            banner: Some("SSH-2.0-OpenSSH_9.6, \"x\"".into()),
+        });
+        vec![
+            ssh,
+            result("10.0.0.1", 23, PortState::Closed),
+            result("10.0.0.2", 80, PortState::Filtered),
+        ]
+    }
+
+    fn render(format: OutputFormat, states: &[PortState]) -> String {
+        let mut w = ReportWriter::new(format, Vec::new(), meta())
+            .unwrap()
+            .with_states(states);
+        let mut counts = StateCounts::default();
+        for r in sample() {
+            counts.record(r.state);
+            w.write(&r).unwrap();
+        }
+        String::from_utf8(w.finish(counts).unwrap()).unwrap()
+    }
+
+    const ALL: &[PortState] = &[PortState::Open, PortState::Closed, PortState::Filtered];
+
+    #[test]
+    fn udp_states_in_xml() {
+        let scanner = Scanner::new()
+            .with_targets(["10.0.0.9".parse().unwrap()])
+            .with_ports(&[53, 161])
+            .with_protocol(Protocol::Udp);
+        let mut w = ReportWriter::new(
+            OutputFormat::NmapXml,
+            Vec::new(),
+            ScanMeta::new(&scanner, "x"),
+        )
+        .unwrap()
+        .with_states(&[PortState::Open]);
+        let mut counts = StateCounts::default();
+        let mut dns = result("10.0.0.9", 53, PortState::Open);
+        dns.protocol = Protocol::Udp;
+        let mut snmp = result("10.0.0.9", 161, PortState::OpenFiltered);
+        snmp.protocol = Protocol::Udp;
+        for r in [dns, snmp] {
+            counts.record(r.state);
+            w.write(&r).unwrap();
+        }
+        let xml = String::from_utf8(w.finish(counts).unwrap()).unwrap();
+        assert!(xml.contains(r#"<scaninfo type="udp" protocol="udp""#));
+        assert!(xml.contains(
+            r#"<state state="open" reason="udp-response" reason_ttl="0"/><service name="domain" method="table" conf="3"/>"#
+        ));
+        assert!(xml.contains(r#"<extraports state="open|filtered" count="1">"#));
+    }
+
+    #[test]
+    fn format_parsing() {
+        assert_eq!("nmap-xml".parse(), Ok(OutputFormat::NmapXml));
+        assert_eq!("JSONL".parse(), Ok(OutputFormat::Jsonl));
+        assert!("yaml".parse::<OutputFormat>().is_err());
+        assert_eq!(
+            OutputFormat::from_extension(std::path::Path::new("out.csv")),
+            Some(OutputFormat::Csv)
+        );
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let text = render(OutputFormat::Json, ALL);
+        let report: ScanReport = serde_json::from_str(&text).unwrap();
+        assert_eq!(report.results.len(), 3);
+        assert_eq!(report.results[0].state, PortState::Open);
+        assert_eq!(report.results[0].latency_ms, 1.5);
+        assert_eq!(report.results[0].version.as_deref(), Some("OpenSSH_9.6"));
+        // No banner: name comes from the service table.
+        assert_eq!(report.results[1].service.as_deref(), Some("telnet"));
+        assert_eq!(report.results[1].banner, None);
+        assert_eq!(report.meta.ports, "22-24,80");
+        assert_eq!(report.summary.closed, 1);
+    }
+
+    #[test]
+    fn jsonl_has_start_ports_summary() {
+        let text = render(OutputFormat::Jsonl, ALL);
+        let events: Vec<JsonlEvent> = text
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(events.len(), 5);
+        assert!(matches!(events[0], JsonlEvent::Start(_)));
+        assert!(matches!(events[4], JsonlEvent::Summary(_)));
+    }
+
+    #[test]
+    fn csv_rows_respect_state_filter() {
+        let text = render(OutputFormat::Csv, &[PortState::Open]);
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(
+            lines[0],
+            "host,port,protocol,state,latency_ms,service,version,banner"
+        );
+        assert_eq!(
+            lines[1],
+            r#"10.0.0.1,22,tcp,open,1.500,ssh,OpenSSH_9.6,"SSH-2.0-OpenSSH_9.6, ""x""""#
+        );
+        assert_eq!(lines.len(), 2);
+
+        let text = render(OutputFormat::Csv, &[PortState::Filtered]);
+        assert_eq!(
+            text.lines().nth(1),
+            Some("10.0.0.2,80,tcp,filtered,1.500,http,,")
+        );
+    }
+
+    #[test]
+    fn nmap_xml_structure() {
+        let xml = render(OutputFormat::NmapXml, &[PortState::Open]);
+        assert!(xml.starts_with("<?xml"));
+        assert!(xml.contains(r#"args="port_scanner 10.0.0.1-2 -p 22-24,80 &amp; &lt;x&gt;""#));
+        assert!(xml.contains(r#"services="22-24,80""#));
+        assert!(xml.contains(r#"<address addr="10.0.0.1" addrtype="ipv4"/>"#));
+        assert!(xml.contains(
+            r#"<port protocol="tcp" portid="22"><state state="open" reason="syn-ack" reason_ttl="0"/><service name="ssh" product="OpenSSH_9.6" method="probed" conf="10"/></port>"#
+        ));
+        assert!(xml.contains(r#"<extraports state="closed" count="1">"#));
+        assert!(!xml.contains(r#"method="table""#));
+        assert!(xml.contains(r#"<extraports state="filtered" count="1">"#));
+        assert!(xml.contains(r#"<hosts up="1" down="1" total="2"/>"#));
+        assert!(xml.trim_end().ends_with("</nmaprun>"));
+    }
+
+    #[test]
+    fn default_writer_keeps_every_state() {
+        let mut w = ReportWriter::new(OutputFormat::Jsonl, Vec::new(), meta()).unwrap();
+        let mut counts = StateCounts::default();
+        for (port, state) in (1..).zip(PortState::ALL) {
+            counts.record(state);
+            w.write(&result("10.0.0.1", port, state)).unwrap();
+        }
+        let text = String::from_utf8(w.finish(counts).unwrap()).unwrap();
+        let states: Vec<_> = text
+            .lines()
+            .filter_map(|l| match serde_json::from_str(l).unwrap() {
+                JsonlEvent::Port(rec) => Some(rec.state),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(states, PortState::ALL);
+    }
+
+    #[test]
+    fn record_converts_back_to_result() {
+        for r in sample() {
+            let rec = PortRecord::from(&r);
+            assert_eq!(PortResult::from(&rec), r);
+        }
+    }
+
+    #[test]
+    fn compress_ports_runs() {
+        assert_eq!(compress_ports(&[1, 2, 3, 80, 443, 444]), "1-3,80,443-444");
+        assert_eq!(compress_ports(&[65535]), "65535");
+        assert_eq!(compress_ports(&[]), "");
+    }
+}
@@ -0,0 +1,193 @@
+//! Port specification parsing (`-p 22,80-90,ssh`, `--range 1-1000`).
+
+use crate::services::port_for_service;
+
+/// Parse a comma-separated port list that may also contain `a-b` ranges and
+/// service names (`ssh,http,3306`).
+///
+/// Invalid entries and port 0 are skipped; the result is sorted and deduplicated.
+pub fn parse_ports_list(spec: &str) -> Vec<u16> {
+    let mut out = Vec::new();
+    for part in spec.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        if let Some(p) = port_for_service(part) {
+            out.push(p);
+        } else if let Some((a, b)) = part.split_once('-') {
+            out.extend(parse_range(&format!("{}-{}", a, b)));
+        } else if let Ok(p) = part.parse::<u16>() {
+            if p > 0 {
+                out.push(p);
+            }
+        }
+    }
+    dedup_sort(out)
+}
+
+/// Parse an inclusive `start-end` range. Bounds are swapped if reversed and
+/// port 0 is clamped to 1.
+pub fn parse_range(spec: &str) -> Vec<u16> {
+    let (a, b) = match spec.split_once('-') {
+        Some((a, b)) => (a.trim(), b.trim()),
+        None => return Vec::new(),
+    };
+    let (Ok(mut start), Ok(mut end)) = (a.parse::<u16>(), b.parse::<u16>()) else {
+        return Vec::new();
+    };
+    if start == 0 {
+        start = 1;
+    }
+    if end == 0 {
+        end = 1;
+    }
+    if start > end {
+        std::mem::swap(&mut start, &mut end);
+    }
+    (start..=end).collect()
+}
+
+/// Sort and deduplicate a port vector.
+pub fn dedup_sort(mut v: Vec<u16>) -> Vec<u16> {
+    v.sort_unstable();
+    v.dedup();
+    v
+}
+
+/// Render at most `max_show` ports (plus the last one) for log output.
+pub fn preview_ports(ports: &[u16], max_show: usize) -> String {
+    if ports.len() <= max_show {
+        return format!("{:?}", ports);
+    }
+    let mut first = ports[..max_show].to_vec();
+    let last = *ports.last().unwrap();
+    first.push(last);
+    format!("{:?} … (truncated)", first)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // --- parse_range ---
+
+    #[test]
+    fn parse_range_normal() {
+        assert_eq!(parse_range("1-5"), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn parse_range_single_port() {
+        assert_eq!(parse_range("80-80"), vec![80]);
+    }
+
+    #[test]
+    fn parse_range_swapped_bounds() {
+        // start > end should swap
+        assert_eq!(
+            parse_range("100-90"),
+            vec![90, 91, 92, 93, 94, 95, 96, 97, 98, 99, 100]
+        );
+    }
+
+    #[test]
+    fn parse_range_zero_clamped() {
+        // port 0 is invalid; should be clamped to 1
+        let r = parse_range("0-3");
+        assert_eq!(r, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn parse_range_no_dash() {
+        assert!(parse_range("80").is_empty());
+    }
+
+    #[test]
+    fn parse_range_invalid_numbers() {
+        assert!(parse_range("abc-xyz").is_empty());
+    }
+
+    #[test]
+    fn parse_range_with_spaces() {
+        assert_eq!(parse_range("  20 - 22 "), vec![20, 21, 22]);
+    }
+
+    // --- parse_ports_list ---
+
+    #[test]
+    fn parse_ports_list_service_names() {
+        assert_eq!(
+            parse_ports_list("ssh,http,https,3306"),
+            vec![22, 80, 443, 3306]
+        );
+        // Hyphenated names are not mistaken for ranges.
+        assert_eq!(parse_ports_list("http-proxy"), vec![8080]);
+        assert!(parse_ports_list("no-such-service").is_empty());
+    }
+
+    #[test]
+    fn parse_ports_list_single() {
+        assert_eq!(parse_ports_list("80"), vec![80]);
+    }
+
+    #[test]
+    fn parse_ports_list_multiple() {
+        assert_eq!(parse_ports_list("22,80,443"), vec![22, 80, 443]);
+    }
+
+    #[test]
+    fn parse_ports_list_with_range() {
+        assert_eq!(parse_ports_list("22,80-82,443"), vec![22, 80, 81, 82, 443]);
+    }
+
+    #[test]
+    fn parse_ports_list_deduplicates() {
+        assert_eq!(parse_ports_list("80,80,443,443"), vec![80, 443]);
+    }
+
+    #[test]
+    fn parse_ports_list_unsorted_input() {
+        assert_eq!(parse_ports_list("443,22,80"), vec![22, 80, 443]);
+    }
+
+    #[test]
+    fn parse_ports_list_skips_zero() {
+        assert_eq!(parse_ports_list("0,80,443"), vec![80, 443]);
+    }
+
+    #[test]
+    fn parse_ports_list_empty_string() {
+        assert!(parse_ports_list("").is_empty());
+    }
+
+    #[test]
+    fn parse_ports_list_extra_commas() {
+        assert_eq!(parse_ports_list(",80,,443,"), vec![80, 443]);
+    }
+
+    // --- dedup_sort ---
+
+    #[test]
+    fn dedup_sort_removes_duplicates_and_sorts() {
+        assert_eq!(dedup_sort(vec![3, 1, 2, 1, 3]), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn dedup_sort_empty() {
+        assert_eq!(dedup_sort(vec![]), Vec::<u16>::new());
+    }
+
+    // --- preview_ports ---
+
+    #[test]
+    fn preview_ports_short_list() {
+        let ports = vec![22, 80, 443];
+        let s = preview_ports(&ports, 5);
+        assert_eq!(s, "[22, 80, 443]");
+    }
+
+    #[test]
+    fn preview_ports_truncated() {
+        let ports: Vec<u16> = (1..=100).collect();
+        let s = preview_ports(&ports, 3);
+        assert!(s.contains("truncated"));
+        assert!(s.contains("100")); // last port included
+    }
+}
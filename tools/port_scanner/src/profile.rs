@@ -0,0 +1,233 @@
+//! Named scan profiles (`--profile`) from a TOML config file.
+//!
+//! The file holds one table per profile, each setting any of the scan
+//! options by their command-line names (with underscores):
+//!
+//! ```toml
+//! [profiles.web]
+//! ports = "80,443,8000-8100,8443"
+//! timeout_ms = 500
+//! banners = true
+//! tls = true
+//! ```
+//!
+//! It is read from `--profile-file`, or else `$XDG_CONFIG_HOME/port_scanner.toml`
+//! (`~/.config/port_scanner.toml`). Options given on the command line win
+//! over the profile. `fast` is built in and can be redefined in the file.
+
+use crate::scanner::PortState;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// File name looked up in the user's config directory.
+pub const CONFIG_FILE: &str = "port_scanner.toml";
+
+#[derive(Debug, Error)]
+pub enum ProfileError {
+    #[error("failed to read {path}: {source}")]
+    Read { path: String, source: io::Error },
+    #[error("invalid profile file {path}: {source}")]
+    Parse {
+        path: String,
+        source: Box<toml::de::Error>,
+    },
+    #[error("profile '{name}' sets more than one of ports, range and top_ports")]
+    PortSpec { name: String },
+    #[error("unknown profile '{name}' (available: {available})")]
+    Unknown { name: String, available: String },
+    #[error("invalid proxy in profile: {reason}")]
+    Proxy { reason: String },
+    #[error("{reason} (check the profile)")]
+    Conflict { reason: &'static str },
+}
+
+/// Scan options a profile can set; unset ones keep the CLI defaults.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub ports: Option<String>,
+    pub range: Option<String>,
+    pub top_ports: Option<usize>,
+    pub timeout_ms: Option<u64>,
+    pub concurrency: Option<usize>,
+    pub adaptive: Option<bool>,
+    pub min_timeout_ms: Option<u64>,
+    pub max_timeout_ms: Option<u64>,
+    pub retries: Option<u32>,
+    pub no_ping: Option<bool>,
+    pub ping_ports: Option<String>,
+    pub max_rate: Option<f64>,
+    pub show: Option<Vec<PortState>>,
+    pub udp: Option<bool>,
+    pub syn: Option<bool>,
+    pub banners: Option<bool>,
+    pub banner_timeout_ms: Option<u64>,
+    pub tls: Option<bool>,
+    pub http_info: Option<bool>,
+    pub proxy: Option<String>,
+    pub ipv4: Option<bool>,
+    pub ipv6: Option<bool>,
+    pub source_ip: Option<IpAddr>,
+    pub source_port: Option<u16>,
+    pub interface: Option<String>,
+}
+
+impl Profile {
+    /// The built-in `fast` profile: the first 1024 ports, a short timeout and
+    /// high concurrency.
+    pub fn fast() -> Self {
+        Self {
+            range: Some("1-1024".into()),
+            timeout_ms: Some(200),
+            concurrency: Some(1024),
+            ..Self::default()
+        }
+    }
+
+    /// Whether the profile picks which ports to scan.
+    pub fn has_port_spec(&self) -> bool {
+        self.ports.is_some() || self.range.is_some() || self.top_ports.is_some()
+    }
+
+    fn port_specs(&self) -> usize {
+        [
+            self.ports.is_some(),
+            self.range.is_some(),
+            self.top_ports.is_some(),
+        ]
+        .into_iter()
+        .filter(|&set| set)
+        .count()
+    }
+}
+
+/// Profiles defined in a config file.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileSet {
+    #[serde(default)]
+    profiles: BTreeMap<String, Profile>,
+}
+
+impl ProfileSet {
+    /// Parse a config file's contents; `path` is only used in errors.
+    pub fn parse(text: &str, path: &Path) -> Result<Self, ProfileError> {
+        let set: Self = toml::from_str(text).map_err(|e| ProfileError::Parse {
+            path: path.display().to_string(),
+            source: Box::new(e),
+        })?;
+        if let Some((name, _)) = set.profiles.iter().find(|(_, p)| p.port_specs() > 1) {
+            return Err(ProfileError::PortSpec { name: name.clone() });
+        }
+        Ok(set)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ProfileError> {
+        let text = fs::read_to_string(path).map_err(|source| ProfileError::Read {
+            path: path.display().to_string(),
+            source,
+        })?;
+        Self::parse(&text, path)
+    }
+
+    /// Load the default config file, or nothing if there is none.
+    pub fn load_default() -> Result<Self, ProfileError> {
+        match default_path() {
+            Some(path) if path.exists() => Self::load(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Look up `name`, falling back to the built-in profiles.
+    pub fn get(&self, name: &str) -> Result<Profile, ProfileError> {
+        if let Some(p) = self.profiles.get(name) {
+            return Ok(p.clone());
+        }
+        match name {
+            "fast" => Ok(Profile::fast()),
+            _ => Err(ProfileError::Unknown {
+                name: name.to_string(),
+                available: self.names().join(", "),
+            }),
+        }
+    }
+
+    /// Names of all selectable profiles, built-in ones included.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.profiles.keys().cloned().collect();
+        if !self.profiles.contains_key("fast") {
+            names.push("fast".into());
+        }
+        names.sort();
+        names
+    }
+}
+
+/// `$XDG_CONFIG_HOME/port_scanner.toml`, or `~/.config/port_scanner.toml`.
+pub fn default_path() -> Option<PathBuf> {
+    let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join(CONFIG_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        [profiles.web]
+        ports = "80,443,8080"
+        timeout_ms = 500
+        banners = true
+        show = ["closed", "open|filtered"]
+
+        [profiles.fast]
+        top_ports = 50
+    "#;
+
+    #[test]
+    fn parses_profiles_and_builtins() {
+        let set = ProfileSet::parse(CONFIG, Path::new("test.toml")).unwrap();
+        let web = set.get("web").unwrap();
+        assert_eq!(web.ports.as_deref(), Some("80,443,8080"));
+        assert_eq!(
+            (web.timeout_ms, web.banners, web.tls),
+            (Some(500), Some(true), None)
+        );
+        assert_eq!(
+            web.show,
+            Some(vec![PortState::Closed, PortState::OpenFiltered])
+        );
+        // The file's `fast` replaces the built-in one.
+        assert_eq!(set.get("fast").unwrap().top_ports, Some(50));
+        assert_eq!(set.get("fast").unwrap().range, None);
+        assert_eq!(ProfileSet::default().get("fast").unwrap(), Profile::fast());
+    }
+
+    #[test]
+    fn rejects_bad_profiles() {
+        let path = Path::new("test.toml");
+        let set = ProfileSet::parse(CONFIG, path).unwrap();
+        let err = set.get("db").unwrap_err().to_string();
+        assert_eq!(err, "unknown profile 'db' (available: fast, web)");
+
+        let typo = "[profiles.web]\nport = \"80\"\n";
+        assert!(matches!(
+            ProfileSet::parse(typo, path),
+            Err(ProfileError::Parse { .. })
+        ));
+        let both = "[profiles.web]\nports = \"80\"\nrange = \"1-10\"\n";
+        assert!(matches!(
+            ProfileSet::parse(both, path),
+            Err(ProfileError::PortSpec { .. })
+        ));
+    }
+}
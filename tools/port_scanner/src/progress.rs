@@ -0,0 +1,126 @@
+//! Scan progress: completed/total, probe rate and estimated time left.
+//!
+//! [`Progress`] only counts and formats; the CLI decides where (and whether)
+//! to draw the line, which it does on stderr when that is a terminal.
+
+use std::time::{Duration, Instant};
+
+/// Running tally of finished probes against the expected total.
+#[derive(Debug, Clone)]
+pub struct Progress {
+    total: usize,
+    done: usize,
+    started: Instant,
+}
+
+impl Progress {
+    /// Start tracking a scan of `total` probes now.
+    pub fn new(total: usize) -> Self {
+        Self {
+            total,
+            done: 0,
+            started: Instant::now(),
+        }
+    }
+
+    /// Count one finished probe.
+    pub fn tick(&mut self) {
+        self.done += 1;
+    }
+
+    pub fn done(&self) -> usize {
+        self.done
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Status line as of now, e.g.
+    /// `[  1200/65535   1.8%]  812/s  elapsed 1s  ETA 1m19s`.
+    pub fn line(&self) -> String {
+        self.line_at(self.started.elapsed())
+    }
+
+    /// Status line after `elapsed` of scanning.
+    pub fn line_at(&self, elapsed: Duration) -> String {
+        let pct = if self.total == 0 {
+            100.0
+        } else {
+            self.done as f64 * 100.0 / self.total as f64
+        };
+        let width = self.total.to_string().len();
+        let mut line = format!(
+            "[{:>width$}/{} {:>5.1}%]  {:.0}/s  elapsed {}",
+            self.done,
+            self.total,
+            pct,
+            rate(self.done, elapsed),
+            format_duration(elapsed),
+        );
+        if let Some(eta) = self.eta_at(elapsed) {
+            line.push_str(&format!("  ETA {}", format_duration(eta)));
+        }
+        line
+    }
+
+    /// Time left at the average rate so far; `None` until something finished.
+    pub fn eta_at(&self, elapsed: Duration) -> Option<Duration> {
+        let r = rate(self.done, elapsed);
+        (r > 0.0).then(|| Duration::from_secs_f64(self.total.saturating_sub(self.done) as f64 / r))
+    }
+}
+
+fn rate(done: usize, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 {
+        done as f64 / secs
+    } else {
+        0.0
+    }
+}
+
+/// Compact duration: `45s`, `3m05s`, `2h07m`.
+pub fn format_duration(d: Duration) -> String {
+    let s = d.as_secs();
+    match s {
+        0..=59 => format!("{s}s"),
+        60..=3599 => format!("{}m{:02}s", s / 60, s % 60),
+        _ => format!("{}h{:02}m", s / 3600, s % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_shows_rate_and_eta() {
+        let mut p = Progress::new(1000);
+        for _ in 0..250 {
+            p.tick();
+        }
+        assert_eq!(
+            p.line_at(Duration::from_secs(5)),
+            "[ 250/1000  25.0%]  50/s  elapsed 5s  ETA 15s"
+        );
+        assert_eq!(
+            p.eta_at(Duration::from_secs(5)),
+            Some(Duration::from_secs(15))
+        );
+    }
+
+    #[test]
+    fn no_eta_before_first_result() {
+        let p = Progress::new(10);
+        assert_eq!(p.eta_at(Duration::from_secs(3)), None);
+        assert_eq!(p.line_at(Duration::ZERO), "[ 0/10   0.0%]  0/s  elapsed 0s");
+    }
+
+    #[test]
+    fn durations_are_compact() {
+        assert_eq!(format_duration(Duration::from_secs(59)), "59s");
+        assert_eq!(format_duration(Duration::from_secs(185)), "3m05s");
+        assert_eq!(format_duration(Duration::from_secs(7620)), "2h07m");
+    }
+}
@@ -0,0 +1,401 @@
+//! Tunnelling TCP probes through a SOCKS5 or HTTP CONNECT proxy (`--proxy`).
+//!
+//! Every connect goes to the proxy, which is asked to open the real
+//! connection; on success the returned stream is the tunnel, so banner grabs
+//! and TLS handshakes run through it unchanged. Where the proxy says *why* a
+//! target connect failed, the answer is mapped back to a port state:
+//! - SOCKS5 `connection refused` → closed; `network/host unreachable` and
+//!   `TTL expired` → filtered
+//! - HTTP `502`/`503` → closed (the upstream connect failed); `504` →
+//!   filtered
+//!
+//! Anything else — the proxy cannot be reached, rejects the credentials or
+//! the request, or answers with garbage — is a [`ProxyError`], which the
+//! scanner reports as [`PortState::ProxyError`] rather than guessing.
+
+use crate::{bind::LocalBind, scanner::PortState};
+use std::{
+    fmt, io,
+    net::{SocketAddr, ToSocketAddrs},
+    str::FromStr,
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+/// Longest HTTP CONNECT response head accepted.
+const MAX_HTTP_HEAD: usize = 8192;
+
+/// Proxy protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyKind {
+    Socks5,
+    Http,
+}
+
+impl ProxyKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Socks5 => "socks5",
+            Self::Http => "http",
+        }
+    }
+
+    fn default_port(self) -> u16 {
+        match self {
+            Self::Socks5 => 1080,
+            Self::Http => 8080,
+        }
+    }
+}
+
+/// Failure on the proxy's side, as opposed to an answer about the target.
+#[derive(Debug, Error)]
+pub enum ProxyError {
+    #[error("cannot connect to proxy: {0}")]
+    Unreachable(io::Error),
+    #[error("proxy connection failed: {0}")]
+    Io(#[from] io::Error),
+    #[error("proxy rejected the credentials")]
+    Auth,
+    #[error("proxy refused the request: {0}")]
+    Refused(String),
+    #[error("unexpected proxy reply: {0}")]
+    Protocol(String),
+}
+
+/// Why a tunnelled connect did not produce a stream.
+#[derive(Debug, Error)]
+pub enum TunnelError {
+    /// The proxy reached a verdict about the target port.
+    #[error("target port is {0}")]
+    Target(PortState),
+    #[error(transparent)]
+    Proxy(#[from] ProxyError),
+}
+
+/// A proxy to tunnel through, parsed from
+/// `socks5://[user:pass@]host[:port]` or `http://[user:pass@]host[:port]`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Proxy {
+    kind: ProxyKind,
+    addr: SocketAddr,
+    auth: Option<(String, String)>,
+    local: LocalBind,
+}
+
+impl Proxy {
+    pub fn new(kind: ProxyKind, addr: SocketAddr) -> Self {
+        Self {
+            kind,
+            addr,
+            auth: None,
+            local: LocalBind::default(),
+        }
+    }
+
+    /// Authenticate with a username and password (SOCKS5 RFC 1929, or HTTP
+    /// Basic).
+    pub fn with_auth(mut self, user: impl Into<String>, pass: impl Into<String>) -> Self {
+        self.auth = Some((user.into(), pass.into()));
+        self
+    }
+
+    /// Connect to the proxy from this local address and interface. Every
+    /// tunnel goes to the same proxy address, so a source port is ignored.
+    pub fn with_local_bind(mut self, local: LocalBind) -> Self {
+        self.local = local.without_port();
+        self
+    }
+
+    pub fn kind(&self) -> ProxyKind {
+        self.kind
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Open a tunnel to `target`. The caller bounds the whole exchange with
+    /// its own timeout.
+    pub async fn connect(&self, target: SocketAddr) -> Result<TcpStream, TunnelError> {
+        let mut stream = self
+            .local
+            .connect(self.addr)
+            .await
+            .map_err(ProxyError::Unreachable)?;
+        match self.kind {
+            ProxyKind::Socks5 => {
+                self.socks5_greet(&mut stream).await?;
+                socks5_connect(&mut stream, target).await?;
+            }
+            ProxyKind::Http => self.http_connect(&mut stream, target).await?,
+        }
+        Ok(stream)
+    }
+
+    /// Check that the proxy is reachable and, for SOCKS5, accepts the
+    /// credentials, before any probe goes out.
+    pub async fn check(&self, wait: Duration) -> Result<(), ProxyError> {
+        let mut stream = timeout(wait, self.local.connect(self.addr))
+            .await
+            .map_err(|_| ProxyError::Unreachable(io::ErrorKind::TimedOut.into()))?
+            .map_err(ProxyError::Unreachable)?;
+        if self.kind == ProxyKind::Socks5 {
+            timeout(wait, self.socks5_greet(&mut stream))
+                .await
+                .map_err(|_| ProxyError::Io(io::ErrorKind::TimedOut.into()))??;
+        }
+        Ok(())
+    }
+
+    /// Method negotiation plus, if offered, username/password auth.
+    async fn socks5_greet(&self, s: &mut TcpStream) -> Result<(), ProxyError> {
+        let greeting: &[u8] = match self.auth {
+            Some(_) => &[5, 2, 0x00, 0x02],
+            None => &[5, 1, 0x00],
+        };
+        s.write_all(greeting).await?;
+        let mut reply = [0u8; 2];
+        s.read_exact(&mut reply).await?;
+        if reply[0] != 5 {
+            return Err(ProxyError::Protocol(format!(
+                "not a SOCKS5 server (version {})",
+                reply[0]
+            )));
+        }
+        match (reply[1], &self.auth) {
+            (0x00, _) => Ok(()),
+            (0x02, Some((user, pass))) => {
+                let (user, pass) = (user.as_bytes(), pass.as_bytes());
+                if user.len() > 255 || pass.len() > 255 {
+                    return Err(ProxyError::Protocol("credentials too long".into()));
+                }
+                let mut req = vec![1, user.len() as u8];
+                req.extend_from_slice(user);
+                req.push(pass.len() as u8);
+                req.extend_from_slice(pass);
+                s.write_all(&req).await?;
+                let mut status = [0u8; 2];
+                s.read_exact(&mut status).await?;
+                if status[1] == 0 {
+                    Ok(())
+                } else {
+                    Err(ProxyError::Auth)
+                }
+            }
+            (0xff, _) => Err(ProxyError::Auth),
+            (m, _) => Err(ProxyError::Protocol(format!("unsupported auth method {m}"))),
+        }
+    }
+
+    async fn http_connect(&self, s: &mut TcpStream, target: SocketAddr) -> Result<(), TunnelError> {
+        let mut req = format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n");
+        if let Some((user, pass)) = &self.auth {
+            req.push_str(&format!(
+                "Proxy-Authorization: Basic {}\r\n",
+                base64(format!("{user}:{pass}").as_bytes())
+            ));
+        }
+        req.push_str("\r\n");
+        s.write_all(req.as_bytes())
+            .await
+            .map_err(ProxyError::from)?;
+
+        // Byte at a time so nothing the target sends after the head (an SSH
+        // greeting, say) is swallowed before the banner grab sees it.
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            if head.len() >= MAX_HTTP_HEAD {
+                return Err(ProxyError::Protocol("response head too long".into()).into());
+            }
+            head.push(s.read_u8().await.map_err(ProxyError::from)?);
+        }
+        let head = String::from_utf8_lossy(&head);
+        let status_line = head.lines().next().unwrap_or_default();
+        let status = status_line
+            .strip_prefix("HTTP/1.")
+            .and_then(|rest| rest.get(2..5))
+            .and_then(|code| code.parse::<u16>().ok())
+            .ok_or_else(|| ProxyError::Protocol(format!("bad status line '{status_line}'")))?;
+        match status {
+            200..=299 => Ok(()),
+            502 | 503 => Err(TunnelError::Target(PortState::Closed)),
+            504 => Err(TunnelError::Target(PortState::Filtered)),
+            407 => Err(ProxyError::Auth.into()),
+            _ => Err(ProxyError::Refused(status_line.to_string()).into()),
+        }
+    }
+}
+
+/// CONNECT request and reply; the reply code decides the port state.
+async fn socks5_connect(s: &mut TcpStream, target: SocketAddr) -> Result<(), TunnelError> {
+    let mut req = vec![5, 1, 0];
+    match target {
+        SocketAddr::V4(a) => {
+            req.push(1);
+            req.extend_from_slice(&a.ip().octets());
+        }
+        SocketAddr::V6(a) => {
+            req.push(4);
+            req.extend_from_slice(&a.ip().octets());
+        }
+    }
+    req.extend_from_slice(&target.port().to_be_bytes());
+    s.write_all(&req).await.map_err(ProxyError::from)?;
+
+    let mut head = [0u8; 4];
+    s.read_exact(&mut head).await.map_err(ProxyError::from)?;
+    match head[1] {
+        0x00 => {}
+        0x05 => return Err(TunnelError::Target(PortState::Closed)),
+        0x03 | 0x04 | 0x06 => return Err(TunnelError::Target(PortState::Filtered)),
+        0x02 => return Err(ProxyError::Refused("connection not allowed by ruleset".into()).into()),
+        code => return Err(ProxyError::Refused(socks5_reply(code)).into()),
+    }
+    // Skip the bound address the server reports.
+    let addr_len = match head[3] {
+        1 => 4,
+        4 => 16,
+        3 => s.read_u8().await.map_err(ProxyError::from)? as usize,
+        t => return Err(ProxyError::Protocol(format!("unknown address type {t}")).into()),
+    };
+    let mut rest = vec![0u8; addr_len + 2];
+    s.read_exact(&mut rest).await.map_err(ProxyError::from)?;
+    Ok(())
+}
+
+fn socks5_reply(code: u8) -> String {
+    match code {
+        0x01 => "general SOCKS server failure".into(),
+        0x07 => "command not supported".into(),
+        0x08 => "address type not supported".into(),
+        c => format!("reply code {c}"),
+    }
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, b[0], b[1], b[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+impl fmt::Display for Proxy {
+    /// `socks5://127.0.0.1:1080`; credentials are never shown.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://{}", self.kind.as_str(), self.addr)
+    }
+}
+
+impl fmt::Debug for Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Proxy({self})")
+    }
+}
+
+impl FromStr for Proxy {
+    type Err = String;
+
+    /// Parse a proxy URL; a hostname is resolved here, once.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = s
+            .split_once("://")
+            .ok_or_else(|| format!("proxy '{s}' needs a scheme (socks5:// or http://)"))?;
+        let kind = match scheme.to_ascii_lowercase().as_str() {
+            "socks5" | "socks5h" => ProxyKind::Socks5,
+            "http" => ProxyKind::Http,
+            other => return Err(format!("unsupported proxy scheme '{other}'")),
+        };
+        let rest = rest.trim_end_matches('/');
+        let (auth, hostport) = match rest.rsplit_once('@') {
+            Some((creds, hostport)) => {
+                let (user, pass) = creds.split_once(':').unwrap_or((creds, ""));
+                (Some((user.to_string(), pass.to_string())), hostport)
+            }
+            None => (None, rest),
+        };
+        let addr = resolve(hostport, kind.default_port())
+            .map_err(|e| format!("cannot resolve proxy '{hostport}': {e}"))?;
+        Ok(Self {
+            kind,
+            addr,
+            auth,
+            local: LocalBind::default(),
+        })
+    }
+}
+
+/// `host`, `host:port`, `[v6]` or `[v6]:port` to a socket address.
+fn resolve(hostport: &str, default_port: u16) -> io::Result<SocketAddr> {
+    if let Ok(addr) = hostport.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    let bare = hostport.trim_start_matches('[').trim_end_matches(']');
+    if let Ok(ip) = bare.parse() {
+        return Ok(SocketAddr::new(ip, default_port));
+    }
+    let (host, port) = match hostport.rsplit_once(':') {
+        Some((h, p)) => (
+            h,
+            p.parse()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid port"))?,
+        ),
+        None => (hostport, default_port),
+    };
+    (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no addresses"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_proxy_urls() {
+        let p: Proxy = "socks5://127.0.0.1:9050".parse().unwrap();
+        assert_eq!(
+            (p.kind(), p.addr()),
+            (ProxyKind::Socks5, "127.0.0.1:9050".parse().unwrap())
+        );
+        assert_eq!(p.auth, None);
+
+        let p: Proxy = "http://user:s3cret@[::1]".parse().unwrap();
+        assert_eq!(
+            (p.kind(), p.addr()),
+            (ProxyKind::Http, "[::1]:8080".parse().unwrap())
+        );
+        assert_eq!(p.auth, Some(("user".into(), "s3cret".into())));
+        assert_eq!(p.to_string(), "http://[::1]:8080");
+
+        assert!("127.0.0.1:1080".parse::<Proxy>().is_err());
+        assert!("socks4://127.0.0.1".parse::<Proxy>().is_err());
+    }
+
+    #[test]
+    fn base64_pads() {
+        assert_eq!(base64(b"user:pass"), "dXNlcjpwYXNz");
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"a"), "YQ==");
+    }
+}
@@ -0,0 +1,888 @@
+//! Scan engine: the [`Scanner`] builder and the [`ScanStream`] it produces.
+//!
+//! Work is expanded lazily into `(host, port)` pairs; a semaphore bounds the
+//! number of in-flight probes and every result is pushed through a channel as
+//! soon as its probe completes. With adaptive timing the semaphore is resized
+//! and the timeout re-derived as results come in (see [`crate::timing`]).
+
+use crate::{
+    banner::{grab_banner, ServiceInfo},
+    bind::LocalBind,
+    http::{http_exchange, HttpInfo, Scheme},
+    proxy::{Proxy, TunnelError},
+    syn::SynEngine,
+    timing::{AdaptiveConfig, AdaptiveController, RateLimiter},
+    tls::{legacy_handshake, tls_handshake, wants_legacy_retry, TlsInfo},
+    udp::probe_udp_from,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fmt, io,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::{
+    net::TcpStream,
+    sync::{mpsc, Semaphore},
+    task::JoinHandle,
+    time::timeout,
+};
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+
+/// Outcome of a single port probe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PortState {
+    /// The connection was accepted.
+    Open,
+    /// The host actively refused the connection (TCP RST).
+    Closed,
+    /// No answer before the timeout, or an ICMP unreachable — usually a
+    /// firewall dropping the probe.
+    Filtered,
+    /// UDP only: no reply at all. Either the service ignored the payload or
+    /// a firewall dropped it; the two cannot be told apart.
+    #[serde(rename = "open|filtered")]
+    OpenFiltered,
+    /// The proxy the probe was tunnelled through failed, so nothing is known
+    /// about the target port.
+    #[serde(rename = "proxy-error")]
+    ProxyError,
+}
+
+impl PortState {
+    /// Every state, in report order.
+    pub const ALL: [Self; 5] = [
+        Self::Open,
+        Self::Closed,
+        Self::Filtered,
+        Self::OpenFiltered,
+        Self::ProxyError,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::Closed => "closed",
+            Self::Filtered => "filtered",
+            Self::OpenFiltered => "open|filtered",
+            Self::ProxyError => "proxy-error",
+        }
+    }
+
+    /// Classify a failed connect by its error kind.
+    ///
+    /// Only an explicit refusal/reset proves the port is closed; anything
+    /// else (unreachable, permission denied by a local firewall, ...) is
+    /// treated as filtered.
+    pub fn from_connect_error(kind: io::ErrorKind) -> Self {
+        match kind {
+            io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset => Self::Closed,
+            _ => Self::Filtered,
+        }
+    }
+}
+
+impl fmt::Display for PortState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for PortState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "open" => Ok(Self::Open),
+            "closed" => Ok(Self::Closed),
+            "filtered" => Ok(Self::Filtered),
+            "open|filtered" | "open-filtered" => Ok(Self::OpenFiltered),
+            "proxy-error" => Ok(Self::ProxyError),
+            other => Err(format!(
+                "unknown port state '{other}' \
+                 (expected open, closed, filtered, open|filtered or proxy-error)"
+            )),
+        }
+    }
+}
+
+/// Per-state tally of scan results.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateCounts {
+    pub open: usize,
+    pub closed: usize,
+    pub filtered: usize,
+    pub open_filtered: usize,
+    #[serde(default)]
+    pub proxy_error: usize,
+}
+
+impl StateCounts {
+    pub fn record(&mut self, state: PortState) {
+        match state {
+            PortState::Open => self.open += 1,
+            PortState::Closed => self.closed += 1,
+            PortState::Filtered => self.filtered += 1,
+            PortState::OpenFiltered => self.open_filtered += 1,
+            PortState::ProxyError => self.proxy_error += 1,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.open + self.closed + self.filtered + self.open_filtered + self.proxy_error
+    }
+}
+
+/// Transport protocol probed.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Tcp => "tcp",
+            Self::Udp => "udp",
+        }
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Result for one `(addr, port)` probe.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortResult {
+    pub addr: IpAddr,
+    pub port: u16,
+    pub protocol: Protocol,
+    pub state: PortState,
+    /// Time from starting the connect until it resolved (or timed out).
+    pub latency: Duration,
+    /// Detected service, when banner grabbing is enabled and the port is open.
+    pub service: Option<ServiceInfo>,
+    /// Handshake details, when TLS inspection is enabled and succeeded.
+    pub tls: Option<Box<TlsInfo>>,
+    /// Response to `GET /`, when HTTP fingerprinting is enabled and the port
+    /// is a web port.
+    pub http: Option<Box<HttpInfo>>,
+}
+
+impl PortResult {
+    /// The probe got no answer at all within `wait` (as opposed to an
+    /// explicit refusal or ICMP error), so a retry could change the outcome.
+    pub fn timed_out(&self, wait: Duration) -> bool {
+        match self.state {
+            PortState::Open | PortState::Closed | PortState::ProxyError => false,
+            PortState::OpenFiltered => true,
+            PortState::Filtered => self.latency >= wait,
+        }
+    }
+}
+
+/// Configurable async TCP connect scanner.
+///
+/// # Example
+/// ```no_run
+/// use port_scanner::{PortState, Scanner};
+/// use std::time::Duration;
+/// use tokio_stream::StreamExt;
+///
+/// # async fn run() {
+/// let scanner = Scanner::new()
+///     .with_targets(["127.0.0.1".parse().unwrap()])
+///     .with_ports(&[22, 80, 443])
+///     .with_timeout(Duration::from_millis(300))
+///     .with_concurrency(64);
+///
+/// let mut results = scanner.scan();
+/// while let Some(r) = results.next().await {
+///     if r.state == PortState::Open {
+///         println!("{}:{} open", r.addr, r.port);
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    targets: Vec<IpAddr>,
+    ports: Vec<u16>,
+    timeout: Duration,
+    concurrency: usize,
+    protocol: Protocol,
+    banners: bool,
+    banner_timeout: Duration,
+    tls: bool,
+    http_info: bool,
+    syn: bool,
+    syn_engine: Option<Arc<SynEngine>>,
+    proxy: Option<Proxy>,
+    local: LocalBind,
+    adaptive: Option<AdaptiveConfig>,
+    limiter: Option<Arc<RateLimiter>>,
+    retries: u32,
+    completed: HashSet<(IpAddr, u16)>,
+}
+
+impl Default for Scanner {
+    fn default() -> Self {
+        Self {
+            targets: Vec::new(),
+            ports: Vec::new(),
+            timeout: Duration::from_millis(300),
+            concurrency: 512,
+            protocol: Protocol::Tcp,
+            banners: false,
+            banner_timeout: Duration::from_millis(1000),
+            tls: false,
+            http_info: false,
+            syn: false,
+            syn_engine: None,
+            proxy: None,
+            local: LocalBind::default(),
+            adaptive: None,
+            limiter: None,
+            retries: 0,
+            completed: HashSet::new(),
+        }
+    }
+}
+
+impl Scanner {
+    /// Create a scanner with no targets or ports, a 300ms timeout and 512
+    /// concurrent connections.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the hosts to scan.
+    pub fn with_targets<I: IntoIterator<Item = IpAddr>>(mut self, targets: I) -> Self {
+        self.targets = targets.into_iter().collect();
+        self
+    }
+
+    /// Set the ports to probe on every target.
+    pub fn with_ports(mut self, ports: &[u16]) -> Self {
+        self.ports = ports.to_vec();
+        self
+    }
+
+    /// Set the per-connection timeout.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the maximum number of in-flight probes.
+    pub fn with_concurrency(mut self, n: usize) -> Self {
+        self.concurrency = n.max(1);
+        self
+    }
+
+    /// Scan TCP (connect) or UDP ports.
+    pub fn with_protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Read a greeting or send a protocol probe on every open TCP port.
+    pub fn with_banners(mut self, enabled: bool) -> Self {
+        self.banners = enabled;
+        self
+    }
+
+    /// Attempt a TLS handshake on every open TCP port and record the
+    /// negotiated parameters and certificate. Uses the banner timeout.
+    pub fn with_tls(mut self, enabled: bool) -> Self {
+        self.tls = enabled;
+        self
+    }
+
+    /// Probe TCP ports with half-open SYN scans over raw sockets instead of
+    /// full connects. Needs root or `CAP_NET_RAW`; without it the scan falls
+    /// back to connect probes (see [`crate::syn::syn_available`]). Ignored
+    /// for UDP and with a proxy.
+    pub fn with_syn(mut self, enabled: bool) -> Self {
+        self.syn = enabled;
+        self
+    }
+
+    /// Send SYN probes through an engine the caller already opened, so a
+    /// failure to get raw sockets can be reported before the scan starts.
+    pub fn with_syn_engine(mut self, engine: Arc<SynEngine>) -> Self {
+        self.syn = true;
+        self.syn_engine = Some(engine);
+        self
+    }
+
+    /// Fetch `/` from open web ports on a fresh connection and report the
+    /// status, server headers, title, redirect and missing security headers.
+    pub fn with_http_info(mut self, enabled: bool) -> Self {
+        self.http_info = enabled;
+        self
+    }
+
+    /// Tunnel every TCP connect, including the extra one for TLS
+    /// inspection, through a SOCKS5 or HTTP CONNECT proxy.
+    pub fn with_proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Send every probe from this local address, port and interface.
+    pub fn with_local_bind(mut self, local: LocalBind) -> Self {
+        self.local = local;
+        self
+    }
+
+    /// How long each banner read may wait for the server.
+    pub fn with_banner_timeout(mut self, timeout: Duration) -> Self {
+        self.banner_timeout = timeout;
+        self
+    }
+
+    /// Derive the timeout from measured RTTs and back off concurrency when
+    /// timeouts spike. The configured timeout becomes the starting value and
+    /// the configured concurrency the upper bound.
+    pub fn with_adaptive_timing(mut self, cfg: AdaptiveConfig) -> Self {
+        self.adaptive = Some(cfg);
+        self
+    }
+
+    /// Start at most `per_sec` probes per second.
+    pub fn with_max_rate(mut self, per_sec: f64) -> Self {
+        self.limiter = (per_sec > 0.0).then(|| Arc::new(RateLimiter::new(per_sec)));
+        self
+    }
+
+    /// Pace probes with an existing limiter, e.g. the one host discovery
+    /// used, so the cap holds across both phases.
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// Re-probe ports that timed out up to `n` more times; the last attempt
+    /// decides the state. Refusals and ICMP errors are never retried.
+    pub fn with_retries(mut self, n: u32) -> Self {
+        self.retries = n;
+        self
+    }
+
+    /// Skip `(host, port)` pairs that already have a result, e.g. from a
+    /// checkpoint of an interrupted run.
+    pub fn with_completed<I: IntoIterator<Item = (IpAddr, u16)>>(mut self, done: I) -> Self {
+        self.completed = done.into_iter().collect();
+        self
+    }
+
+    pub fn targets(&self) -> &[IpAddr] {
+        &self.targets
+    }
+
+    pub fn ports(&self) -> &[u16] {
+        &self.ports
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    pub fn adaptive_timing(&self) -> Option<AdaptiveConfig> {
+        self.adaptive
+    }
+
+    pub fn max_rate(&self) -> Option<f64> {
+        self.limiter.as_ref().map(|l| l.per_sec())
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Whether SYN probes were requested.
+    pub fn syn(&self) -> bool {
+        self.syn
+    }
+
+    pub fn proxy(&self) -> Option<&Proxy> {
+        self.proxy.as_ref()
+    }
+
+    pub fn local_bind(&self) -> &LocalBind {
+        &self.local
+    }
+
+    /// Number of `(host, port)` probes a full scan will perform, not
+    /// counting pairs skipped by [`Scanner::with_completed`].
+    pub fn total_probes(&self) -> usize {
+        let targets: HashSet<_> = self.targets.iter().collect();
+        let ports: HashSet<_> = self.ports.iter().collect();
+        let skipped = self
+            .completed
+            .iter()
+            .filter(|(addr, port)| targets.contains(addr) && ports.contains(port))
+            .count();
+        self.targets.len() * self.ports.len() - skipped
+    }
+
+    /// Start scanning and return a stream of results in completion order.
+    ///
+    /// Must be called from within a Tokio runtime. Dropping the stream stops
+    /// the scan.
+    pub fn scan(&self) -> ScanStream {
+        let (tx, rx) = mpsc::channel(self.concurrency);
+        let cfg = Arc::new(self.clone());
+        let driver = tokio::spawn(drive(cfg, tx));
+        ScanStream {
+            inner: ReceiverStream::new(rx),
+            driver,
+        }
+    }
+}
+
+/// Expand the work queue, holding one semaphore permit per in-flight probe.
+async fn drive(cfg: Arc<Scanner>, tx: mpsc::Sender<PortResult>) {
+    let sem = Arc::new(Semaphore::new(cfg.concurrency));
+    let adaptive = cfg.adaptive.map(|a| {
+        Arc::new(AdaptiveController::new(
+            a,
+            cfg.timeout,
+            cfg.concurrency,
+            Arc::clone(&sem),
+        ))
+    });
+    let limiter = cfg.limiter.clone();
+    let syn = match &cfg.syn_engine {
+        _ if !cfg.syn || cfg.protocol != Protocol::Tcp || cfg.proxy.is_some() => None,
+        Some(engine) => Some(Arc::clone(engine)),
+        None => SynEngine::bound(&cfg.local).ok().map(Arc::new),
+    };
+    for &addr in &cfg.targets {
+        for &port in &cfg.ports {
+            if cfg.completed.contains(&(addr, port)) {
+                continue;
+            }
+            let Ok(permit) = Arc::clone(&sem).acquire_owned().await else {
+                return;
+            };
+            if let Some(limiter) = &limiter {
+                limiter.wait().await;
+            }
+            if tx.is_closed() {
+                return;
+            }
+            let tx = tx.clone();
+            let cfg = Arc::clone(&cfg);
+            let adaptive = adaptive.clone();
+            let limiter = limiter.clone();
+            let syn = syn.clone();
+            tokio::spawn(async move {
+                let sa = SocketAddr::new(addr, port);
+                let mut attempt = 0;
+                let result = loop {
+                    let wait = adaptive.as_ref().map_or(cfg.timeout, |a| a.timeout());
+                    let result = cfg.probe(sa, wait, syn.as_deref()).await;
+                    if let Some(a) = &adaptive {
+                        a.observe(&result, wait);
+                    }
+                    if attempt == cfg.retries || !result.timed_out(wait) {
+                        break result;
+                    }
+                    attempt += 1;
+                    if let Some(limiter) = &limiter {
+                        limiter.wait().await;
+                    }
+                };
+                match &adaptive {
+                    Some(a) => a.release(permit),
+                    None => drop(permit),
+                }
+                let _ = tx.send(result).await;
+            });
+        }
+    }
+}
+
+impl Scanner {
+    /// Probe one port with this scanner's settings.
+    async fn probe(&self, addr: SocketAddr, wait: Duration, syn: Option<&SynEngine>) -> PortResult {
+        if self.protocol == Protocol::Udp {
+            return probe_udp_from(addr, wait, &self.local).await;
+        }
+        let (mut result, stream) = match (syn, &self.proxy) {
+            (Some(engine), _) => match engine.probe(addr, wait).await {
+                // Banners still need a real connection, which cannot reuse
+                // the source port of the SYN just reset. TLS and HTTP open
+                // their own.
+                Ok(r) if r.state == PortState::Open && self.banners => (
+                    r,
+                    connect_tcp(addr, wait, &self.local.without_port()).await.1,
+                ),
+                Ok(r) => (r, None),
+                Err(_) => connect_tcp(addr, wait, &self.local).await,
+            },
+            (None, Some(proxy)) => connect_via(proxy, addr, wait).await,
+            (None, None) => connect_tcp(addr, wait, &self.local).await,
+        };
+        if let (true, Some(stream)) = (self.banners, stream) {
+            result.service = grab_banner(stream, addr.port(), self.banner_timeout).await;
+        }
+        if self.tls && result.state == PortState::Open {
+            result.tls = self.inspect_tls(addr).await.map(Box::new);
+        }
+        if self.http_info && result.state == PortState::Open {
+            if let Some(scheme) = Scheme::for_port(addr.port(), result.service.as_ref()) {
+                result.http = self.inspect_http(addr, scheme).await.map(Box::new);
+            }
+        }
+        result
+    }
+
+    /// Connection for an extra exchange after the probe: through the proxy
+    /// if there is one, else without a fixed source port, which the probe's
+    /// connection may still hold in `TIME_WAIT`.
+    async fn reconnect(&self, addr: SocketAddr) -> Option<TcpStream> {
+        let wait = self.banner_timeout;
+        match &self.proxy {
+            Some(proxy) => timeout(wait, proxy.connect(addr)).await.ok()?.ok(),
+            None => {
+                let local = self.local.without_port();
+                timeout(wait, local.connect(addr)).await.ok()?.ok()
+            }
+        }
+    }
+
+    /// Handshake on a fresh connection, and once more with a legacy
+    /// ClientHello if rustls could not agree on a version or suite.
+    async fn inspect_tls(&self, addr: SocketAddr) -> Option<TlsInfo> {
+        let tcp = self.reconnect(addr).await?;
+        match tls_handshake(tcp, addr.ip(), self.banner_timeout).await {
+            Ok(info) => Some(info),
+            Err(e) if wants_legacy_retry(&e) => {
+                let tcp = self.reconnect(addr).await?;
+                legacy_handshake(tcp, self.banner_timeout).await.ok()
+            }
+            Err(_) => None,
+        }
+    }
+
+    /// `GET /` on a fresh connection.
+    async fn inspect_http(&self, addr: SocketAddr, scheme: Scheme) -> Option<HttpInfo> {
+        let tcp = self.reconnect(addr).await?;
+        http_exchange(tcp, addr, scheme, self.banner_timeout)
+            .await
+            .ok()
+    }
+}
+
+/// Attempt a single TCP connect and classify the outcome: accepted is open,
+/// refused is closed, a timeout is filtered.
+pub async fn probe_tcp(addr: SocketAddr, timeout_dur: Duration) -> PortResult {
+    connect_tcp(addr, timeout_dur, &LocalBind::default())
+        .await
+        .0
+}
+
+/// Like [`probe_tcp`], but from `local` and handing back the connected
+/// stream on success.
+pub(crate) async fn connect_tcp(
+    addr: SocketAddr,
+    timeout_dur: Duration,
+    local: &LocalBind,
+) -> (PortResult, Option<TcpStream>) {
+    let started = Instant::now();
+    let res = timeout(timeout_dur, local.connect(addr)).await;
+    let latency = started.elapsed();
+    let (state, stream) = match res {
+        Ok(Ok(stream)) => (PortState::Open, Some(stream)),
+        Ok(Err(e)) => (PortState::from_connect_error(e.kind()), None),
+        Err(_elapsed) => (PortState::Filtered, None),
+    };
+    let result = PortResult {
+        addr: addr.ip(),
+        port: addr.port(),
+        protocol: Protocol::Tcp,
+        state,
+        latency,
+        service: None,
+        tls: None,
+        http: None,
+    };
+    (result, stream)
+}
+
+/// [`connect_tcp`] through a proxy. A timeout is filtered, as without one;
+/// proxy failures get their own state.
+async fn connect_via(
+    proxy: &Proxy,
+    addr: SocketAddr,
+    timeout_dur: Duration,
+) -> (PortResult, Option<TcpStream>) {
+    let started = Instant::now();
+    let res = timeout(timeout_dur, proxy.connect(addr)).await;
+    let latency = started.elapsed();
+    let (state, stream) = match res {
+        Ok(Ok(stream)) => (PortState::Open, Some(stream)),
+        Ok(Err(TunnelError::Target(state))) => (state, None),
+        Ok(Err(TunnelError::Proxy(_))) => (PortState::ProxyError, None),
+        Err(_elapsed) => (PortState::Filtered, None),
+    };
+    let result = PortResult {
+        addr: addr.ip(),
+        port: addr.port(),
+        protocol: Protocol::Tcp,
+        state,
+        latency,
+        service: None,
+        tls: None,
+        http: None,
+    };
+    (result, stream)
+}
+
+/// Stream of [`PortResult`]s produced by [`Scanner::scan`].
+pub struct ScanStream {
+    inner: ReceiverStream<PortResult>,
+    driver: JoinHandle<()>,
+}
+
+impl Stream for ScanStream {
+    type Item = PortResult;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.inner).poll_next(cx)
+    }
+}
+
+impl Drop for ScanStream {
+    fn drop(&mut self) {
+        self.driver.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+    use tokio_stream::StreamExt;
+
+    /// Bind and immediately release a port so nothing is listening on it.
+    async fn unused_port() -> u16 {
+        let l = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        l.local_addr().unwrap().port()
+    }
+
+    #[tokio::test]
+    async fn detects_open_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let r = probe_tcp(
+            SocketAddr::new([127, 0, 0, 1].into(), port),
+            Duration::from_secs(1),
+        )
+        .await;
+        assert_eq!(r.state, PortState::Open);
+        assert_eq!(r.port, port);
+    }
+
+    #[tokio::test]
+    async fn detects_closed_port() {
+        let port = unused_port().await;
+        let r = probe_tcp(
+            SocketAddr::new([127, 0, 0, 1].into(), port),
+            Duration::from_secs(1),
+        )
+        .await;
+        assert_eq!(r.state, PortState::Closed);
+    }
+
+    #[test]
+    fn refused_is_closed_other_errors_filtered() {
+        use io::ErrorKind::*;
+        assert_eq!(
+            PortState::from_connect_error(ConnectionRefused),
+            PortState::Closed
+        );
+        assert_eq!(
+            PortState::from_connect_error(ConnectionReset),
+            PortState::Closed
+        );
+        assert_eq!(PortState::from_connect_error(TimedOut), PortState::Filtered);
+        assert_eq!(
+            PortState::from_connect_error(PermissionDenied),
+            PortState::Filtered
+        );
+    }
+
+    #[test]
+    fn port_state_round_trips_through_str() {
+        for s in PortState::ALL {
+            assert_eq!(s.to_string().parse::<PortState>(), Ok(s));
+        }
+        assert_eq!(" Filtered ".parse::<PortState>(), Ok(PortState::Filtered));
+        assert!("half-open".parse::<PortState>().is_err());
+    }
+
+    #[test]
+    fn state_counts_tally() {
+        let mut c = StateCounts::default();
+        for s in [
+            PortState::Open,
+            PortState::Closed,
+            PortState::Closed,
+            PortState::OpenFiltered,
+        ] {
+            c.record(s);
+        }
+        assert_eq!(
+            (c.open, c.closed, c.filtered, c.open_filtered, c.total()),
+            (1, 2, 0, 1, 4)
+        );
+    }
+
+    #[tokio::test]
+    async fn stream_yields_one_result_per_probe() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open = listener.local_addr().unwrap().port();
+        let closed = unused_port().await;
+
+        let scanner = Scanner::new()
+            .with_targets(["127.0.0.1".parse().unwrap()])
+            .with_ports(&[open, closed])
+            .with_timeout(Duration::from_secs(1))
+            .with_concurrency(1);
+        assert_eq!(scanner.total_probes(), 2);
+
+        let results: Vec<_> = scanner.scan().collect().await;
+        assert_eq!(results.len(), 2);
+        let open_result = results.iter().find(|r| r.port == open).unwrap();
+        assert_eq!(open_result.state, PortState::Open);
+        let closed_result = results.iter().find(|r| r.port == closed).unwrap();
+        assert_eq!(closed_result.state, PortState::Closed);
+    }
+
+    #[tokio::test]
+    async fn adaptive_rate_limited_scan_reports_every_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open = listener.local_addr().unwrap().port();
+        let closed = unused_port().await;
+
+        let scanner = Scanner::new()
+            .with_targets(["127.0.0.1".parse().unwrap()])
+            .with_ports(&[open, closed, closed])
+            .with_timeout(Duration::from_secs(1))
+            .with_adaptive_timing(AdaptiveConfig::default())
+            .with_max_rate(50.0);
+        let started = Instant::now();
+        let results: Vec<_> = scanner.scan().collect().await;
+        assert_eq!(results.len(), 3);
+        assert_eq!(
+            results
+                .iter()
+                .filter(|r| r.state == PortState::Open)
+                .count(),
+            1
+        );
+        // Three probes at 50/s: the last starts no sooner than 40ms in.
+        assert!(started.elapsed() >= Duration::from_millis(35));
+    }
+
+    #[test]
+    fn total_probes_scales_to_large_checkpoints() {
+        let hosts: Vec<IpAddr> = (0..=255u8).map(|i| [10, 0, 0, i].into()).collect();
+        let ports: Vec<u16> = (1..=65535).collect();
+        let done: Vec<_> = hosts
+            .iter()
+            .flat_map(|&h| (1..=1000).map(move |p| (h, p)))
+            .collect();
+        let scanner = Scanner::new()
+            .with_targets(hosts)
+            .with_ports(&ports)
+            .with_completed(done);
+        assert_eq!(scanner.total_probes(), 256 * (65535 - 1000));
+    }
+
+    #[tokio::test]
+    async fn completed_pairs_are_not_probed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open = listener.local_addr().unwrap().port();
+        let closed = unused_port().await;
+        let host: IpAddr = "127.0.0.1".parse().unwrap();
+
+        let scanner = Scanner::new()
+            .with_targets([host])
+            .with_ports(&[open, closed])
+            .with_completed([(host, open), (host, 9)]);
+        assert_eq!(scanner.total_probes(), 1);
+        let results: Vec<_> = scanner.scan().collect().await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].port, closed);
+    }
+
+    #[test]
+    fn only_silence_counts_as_timed_out() {
+        let wait = Duration::from_millis(100);
+        let mut r = PortResult {
+            addr: [127, 0, 0, 1].into(),
+            port: 1,
+            protocol: Protocol::Tcp,
+            state: PortState::Filtered,
+            latency: wait,
+            service: None,
+            tls: None,
+            http: None,
+        };
+        assert!(r.timed_out(wait));
+        r.latency = Duration::from_millis(3); // fast ICMP unreachable
+        assert!(!r.timed_out(wait));
+        r.state = PortState::Closed;
+        assert!(!r.timed_out(wait));
+        r.state = PortState::OpenFiltered;
+        assert!(r.timed_out(wait));
+    }
+
+    #[tokio::test]
+    async fn retries_do_not_repeat_answered_ports() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let scanner = Scanner::new()
+            .with_targets(["127.0.0.1".parse().unwrap()])
+            .with_ports(&[port])
+            .with_retries(3);
+        let results: Vec<_> = scanner.scan().collect().await;
+        assert_eq!(results[0].state, PortState::Open);
+        // Every connect the scan made is queued on the listener by now.
+        let mut attempts = 0;
+        while timeout(Duration::from_millis(100), listener.accept())
+            .await
+            .is_ok()
+        {
+            attempts += 1;
+        }
+        assert_eq!(attempts, 1);
+    }
+}
@@ -0,0 +1,268 @@
+//! Embedded well-known service table.
+//!
+//! Names follow the IANA / `nmap-services` conventions. Each protocol's list
+//! is ordered by how often the port is found open in the wild (the
+//! `nmap-services` frequency ranking), so a prefix of it is a "top N" set.
+
+use crate::scanner::Protocol;
+use std::collections::HashSet;
+
+/// TCP services, most frequently open first.
+const TCP: &[(&str, u16)] = &[
+    ("http", 80),
+    ("telnet", 23),
+    ("https", 443),
+    ("ftp", 21),
+    ("ssh", 22),
+    ("smtp", 25),
+    ("ms-wbt-server", 3389),
+    ("pop3", 110),
+    ("microsoft-ds", 445),
+    ("netbios-ssn", 139),
+    ("imap", 143),
+    ("domain", 53),
+    ("msrpc", 135),
+    ("mysql", 3306),
+    ("http-proxy", 8080),
+    ("pptp", 1723),
+    ("rpcbind", 111),
+    ("pop3s", 995),
+    ("imaps", 993),
+    ("vnc", 5900),
+    ("nfs-or-iis", 1025),
+    ("submission", 587),
+    ("sun-answerbook", 8888),
+    ("smux", 199),
+    ("h323q931", 1720),
+    ("smtps", 465),
+    ("afp", 548),
+    ("ident", 113),
+    ("hosts2-ns", 81),
+    ("x11-1", 6001),
+    ("snet-sensor-mgmt", 10000),
+    ("shell", 514),
+    ("sip", 5060),
+    ("bgp", 179),
+    ("lsa-or-nterm", 1026),
+    ("cisco-sccp", 2000),
+    ("https-alt", 8443),
+    ("http-alt", 8000),
+    ("filenet-tms", 32768),
+    ("rtsp", 554),
+    ("rsftp", 26),
+    ("ms-sql-s", 1433),
+    ("dc", 2001),
+    ("printer", 515),
+    ("http-mgmt", 8008),
+    ("iis", 1027),
+    ("nrpe", 5666),
+    ("ldp", 646),
+    ("upnp", 5000),
+    ("pcanywheredata", 5631),
+    ("ipp", 631),
+    ("blackice-icecap", 8081),
+    ("nfs", 2049),
+    ("kerberos-sec", 88),
+    ("finger", 79),
+    ("vnc-http", 5800),
+    ("pop3pw", 106),
+    ("ccproxy-ftp", 2121),
+    ("nfsd-status", 1110),
+    ("x11", 6000),
+    ("login", 513),
+    ("ftps", 990),
+    ("wsdapi", 5357),
+    ("svrloc", 427),
+    ("klogin", 543),
+    ("kshell", 544),
+    ("admdog", 5101),
+    ("news", 144),
+    ("echo", 7),
+    ("ldap", 389),
+    ("postgresql", 5432),
+    ("redis", 6379),
+    ("mongod", 27017),
+    ("memcache", 11211),
+    ("elasticsearch", 9200),
+    ("ldaps", 636),
+    ("imap3", 220),
+    ("nntp", 119),
+    ("ntp", 123),
+    ("netbios-ns", 137),
+    ("snmp", 161),
+    ("daytime", 13),
+    ("discard", 9),
+    ("chargen", 19),
+    ("ftp-data", 20),
+    ("time", 37),
+    ("tacacs", 49),
+    ("gopher", 70),
+    ("exec", 512),
+    ("rsync", 873),
+    ("squid-http", 3128),
+    ("oracle-tns", 1521),
+    ("ms-sql-m", 1434),
+    ("radmin", 4899),
+    ("amqp", 5672),
+    ("xmpp-client", 5222),
+    ("irc", 6667),
+    ("ajp13", 8009),
+    ("jetdirect", 9100),
+    ("docker", 2375),
+    ("kubernetes", 6443),
+    ("zookeeper", 2181),
+    ("kafka", 9092),
+    ("winrm", 5985),
+    ("winrm-https", 5986),
+    ("globalcatldap", 3268),
+    ("iscsi", 3260),
+    ("mqtt", 1883),
+];
+
+/// UDP services, most frequently open first.
+const UDP: &[(&str, u16)] = &[
+    ("ipp", 631),
+    ("snmp", 161),
+    ("netbios-ns", 137),
+    ("ntp", 123),
+    ("netbios-dgm", 138),
+    ("ms-sql-m", 1434),
+    ("microsoft-ds", 445),
+    ("msrpc", 135),
+    ("dhcps", 67),
+    ("domain", 53),
+    ("netbios-ssn", 139),
+    ("isakmp", 500),
+    ("dhcpc", 68),
+    ("route", 520),
+    ("upnp", 1900),
+    ("nat-t-ike", 4500),
+    ("syslog", 514),
+    ("snmptrap", 162),
+    ("tftp", 69),
+    ("zeroconf", 5353),
+    ("rpcbind", 111),
+    ("l2tp", 1701),
+    ("puparp", 998),
+    ("vsinet", 996),
+    ("maitrd", 997),
+    ("applix", 999),
+    ("netassistant", 3283),
+    ("radius", 1812),
+    ("sip", 5060),
+    ("radius-old", 1645),
+    ("nfs", 2049),
+    ("radacct", 1813),
+    ("llmnr", 5355),
+    ("mdns", 5350),
+    ("memcache", 11211),
+    ("openvpn", 1194),
+    ("wireguard", 51820),
+    ("coap", 5683),
+    ("ssdp", 1901),
+    ("kerberos-sec", 88),
+];
+
+/// Common informal names accepted in port specs.
+const ALIASES: &[(&str, &str)] = &[
+    ("dns", "domain"),
+    ("rdp", "ms-wbt-server"),
+    ("smb", "microsoft-ds"),
+    ("mssql", "ms-sql-s"),
+    ("postgres", "postgresql"),
+    ("mongodb", "mongod"),
+    ("kerberos", "kerberos-sec"),
+    ("netbios", "netbios-ssn"),
+];
+
+fn table(proto: Protocol) -> &'static [(&'static str, u16)] {
+    match proto {
+        Protocol::Tcp => TCP,
+        Protocol::Udp => UDP,
+    }
+}
+
+/// Conventional service name for `port`, if it is in the table.
+pub fn service_name(port: u16, proto: Protocol) -> Option<&'static str> {
+    table(proto)
+        .iter()
+        .find(|(_, p)| *p == port)
+        .map(|(name, _)| *name)
+}
+
+/// Port for a service name or alias (case-insensitive). TCP entries win
+/// when a name exists for both protocols.
+pub fn port_for_service(name: &str) -> Option<u16> {
+    let name = name.trim().to_ascii_lowercase();
+    let canonical = ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name.as_str(), |(_, c)| *c);
+    TCP.iter()
+        .chain(UDP)
+        .find(|(n, _)| *n == canonical)
+        .map(|(_, p)| *p)
+}
+
+/// The `n` most frequently open ports for `proto`, sorted ascending.
+///
+/// If `n` exceeds the table, the remainder is filled with the lowest port
+/// numbers not already chosen.
+pub fn top_ports(n: usize, proto: Protocol) -> Vec<u16> {
+    let mut ports: Vec<u16> = table(proto).iter().take(n).map(|(_, p)| *p).collect();
+    let chosen: HashSet<u16> = ports.iter().copied().collect();
+    let fill = (1..=u16::MAX).filter(|p| !chosen.contains(p));
+    ports.extend(fill.take(n.saturating_sub(ports.len())));
+    ports.sort_unstable();
+    ports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_and_ports_round_trip() {
+        assert_eq!(port_for_service("ssh"), Some(22));
+        assert_eq!(port_for_service("HTTPS"), Some(443));
+        assert_eq!(service_name(22, Protocol::Tcp), Some("ssh"));
+        assert_eq!(service_name(161, Protocol::Udp), Some("snmp"));
+        assert_eq!(service_name(4, Protocol::Tcp), None);
+    }
+
+    #[test]
+    fn aliases_and_udp_only_names() {
+        assert_eq!(port_for_service("rdp"), Some(3389));
+        assert_eq!(port_for_service("dns"), Some(53));
+        assert_eq!(port_for_service("snmptrap"), Some(162));
+        assert_eq!(port_for_service("gibberish"), None);
+    }
+
+    #[test]
+    fn top_ports_prefix_by_frequency() {
+        assert_eq!(top_ports(3, Protocol::Tcp), vec![23, 80, 443]);
+        assert_eq!(top_ports(2, Protocol::Udp), vec![161, 631]);
+    }
+
+    #[test]
+    fn top_ports_beyond_table_fills_low_ports() {
+        let ports = top_ports(1000, Protocol::Tcp);
+        assert_eq!(ports.len(), 1000);
+        let mut dedup = ports.clone();
+        dedup.dedup();
+        assert_eq!(dedup.len(), 1000);
+        assert!(ports.contains(&27017));
+        assert_eq!(top_ports(70000, Protocol::Udp).len(), 65535);
+    }
+
+    #[test]
+    fn tables_have_no_duplicate_ports() {
+        for proto in [Protocol::Tcp, Protocol::Udp] {
+            let mut ports: Vec<_> = table(proto).iter().map(|(_, p)| *p).collect();
+            ports.sort_unstable();
+            let len = ports.len();
+            ports.dedup();
+            assert_eq!(ports.len(), len, "{proto} table has duplicate ports");
+        }
+    }
+}
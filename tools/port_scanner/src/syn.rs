@@ -0,0 +1,424 @@
+//! Half-open (SYN) scanning over raw sockets (`--syn`).
+//!
+//! Each probe is a single hand-built SYN instead of a full handshake, and
+//! the reply decides the state:
+//! - SYN/ACK → open; a RST goes back so no connection is ever established
+//! - RST → closed
+//! - nothing before the timeout → filtered
+//!
+//! One raw socket per address family is shared by the whole scan. A receive
+//! task reads every TCP segment the kernel copies to it and hands replies to
+//! the waiting probe by `(host, port)`. The initial sequence number is a
+//! keyed hash of the target, so only segments acknowledging *our* SYN count.
+//!
+//! The source port is reserved with a bound, never connected TCP socket so
+//! no local connection can pick it during the scan; the kernel answers
+//! SYN/ACKs to it with its own RST as well.
+//!
+//! [`SynEngine::bound`] sends from a chosen source address, port and
+//! interface instead (`--source-ip`, `--source-port`, `--interface`).
+//!
+//! Raw sockets need root or `CAP_NET_RAW`. [`SynEngine::new`] fails without
+//! them and the scanner falls back to connect probes.
+
+use crate::{
+    bind::LocalBind,
+    discovery::internet_checksum,
+    scanner::{PortResult, PortState, Protocol},
+};
+use socket2::{Domain, Protocol as SockProtocol, Socket, Type};
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    hash::BuildHasher,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{net::UdpSocket, sync::oneshot, task::JoinHandle, time::timeout};
+
+const FIN: u8 = 0x01;
+const SYN: u8 = 0x02;
+const RST: u8 = 0x04;
+const ACK: u8 = 0x10;
+
+/// Pause after a failed raw-socket read before trying again.
+const RECV_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Whether this process may open raw TCP sockets.
+pub fn syn_available() -> bool {
+    raw_socket(Domain::IPV4, &LocalBind::default()).is_ok()
+}
+
+/// Raw sockets, the pending probes and the receive tasks of one scan.
+#[derive(Debug)]
+pub struct SynEngine {
+    shared: Arc<Shared>,
+    receivers: Vec<JoinHandle<()>>,
+    /// Keeps the source port bound for the lifetime of the engine.
+    _reserved: Vec<Socket>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    v4: Option<UdpSocket>,
+    v6: Option<UdpSocket>,
+    src_port: u16,
+    src_ip: Option<IpAddr>,
+    cookie: RandomState,
+    pending: Mutex<HashMap<(IpAddr, u16), oneshot::Sender<PortState>>>,
+    /// Local address the kernel routes each target from.
+    sources: Mutex<HashMap<IpAddr, IpAddr>>,
+}
+
+impl SynEngine {
+    /// Open the raw sockets and start receiving. IPv6 is optional; IPv4 is
+    /// not, so the error from opening it is returned.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn new() -> io::Result<Self> {
+        Self::bound(&LocalBind::default())
+    }
+
+    /// Like [`SynEngine::new`], sending from `local`'s address, port and
+    /// interface where set.
+    pub fn bound(local: &LocalBind) -> io::Result<Self> {
+        let v4 = Some(raw_socket(Domain::IPV4, local)?);
+        let v6 = raw_socket(Domain::IPV6, local).ok();
+
+        let reserve = Socket::new(Domain::IPV4, Type::STREAM, Some(SockProtocol::TCP))?;
+        if local.port.is_some() {
+            reserve.set_reuse_address(true)?;
+        }
+        reserve.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, local.port.unwrap_or(0))).into())?;
+        let src_port = reserve
+            .local_addr()?
+            .as_socket()
+            .map(|a| a.port())
+            .ok_or_else(|| io::Error::other("reserved socket has no port"))?;
+        let mut reserved = vec![reserve];
+        if let Ok(s) = Socket::new(Domain::IPV6, Type::STREAM, Some(SockProtocol::TCP)) {
+            if s.set_only_v6(true).is_ok()
+                && s.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, src_port)).into())
+                    .is_ok()
+            {
+                reserved.push(s);
+            }
+        }
+
+        let shared = Arc::new(Shared {
+            v4,
+            v6,
+            src_port,
+            src_ip: local.ip,
+            cookie: RandomState::new(),
+            pending: Mutex::new(HashMap::new()),
+            sources: Mutex::new(HashMap::new()),
+        });
+        let mut receivers = vec![tokio::spawn(receive(Arc::clone(&shared), false))];
+        if shared.v6.is_some() {
+            receivers.push(tokio::spawn(receive(Arc::clone(&shared), true)));
+        }
+        Ok(Self {
+            shared,
+            receivers,
+            _reserved: reserved,
+        })
+    }
+
+    /// Source port every SYN is sent from.
+    pub fn source_port(&self) -> u16 {
+        self.shared.src_port
+    }
+
+    /// Send a SYN to `addr` and classify the reply, waiting up to `wait`.
+    ///
+    /// Errors mean the probe could not be sent at all (no raw socket for
+    /// the address family, no route, ...), not that the port is closed.
+    pub async fn probe(&self, addr: SocketAddr, wait: Duration) -> io::Result<PortResult> {
+        let shared = &self.shared;
+        let sock = shared
+            .socket(addr.ip())
+            .ok_or_else(|| io::Error::from(io::ErrorKind::Unsupported))?;
+        let src = SocketAddr::new(shared.source_for(addr.ip())?, shared.src_port);
+        let syn = tcp_segment(src, addr, shared.isn(addr), 0, SYN);
+
+        let key = (addr.ip(), addr.port());
+        let (tx, rx) = oneshot::channel();
+        shared.pending.lock().unwrap().insert(key, tx);
+        let started = Instant::now();
+        if let Err(e) = sock.send_to(&syn, SocketAddr::new(addr.ip(), 0)).await {
+            shared.pending.lock().unwrap().remove(&key);
+            return Err(e);
+        }
+        let state = match timeout(wait, rx).await {
+            Ok(Ok(state)) => state,
+            _ => PortState::Filtered,
+        };
+        let latency = started.elapsed();
+        shared.pending.lock().unwrap().remove(&key);
+        Ok(PortResult {
+            addr: addr.ip(),
+            port: addr.port(),
+            protocol: Protocol::Tcp,
+            state,
+            latency,
+            service: None,
+            tls: None,
+            http: None,
+        })
+    }
+}
+
+impl Drop for SynEngine {
+    fn drop(&mut self) {
+        for r in &self.receivers {
+            r.abort();
+        }
+    }
+}
+
+impl Shared {
+    fn socket(&self, ip: IpAddr) -> Option<&UdpSocket> {
+        match ip {
+            IpAddr::V4(_) => self.v4.as_ref(),
+            IpAddr::V6(_) => self.v6.as_ref(),
+        }
+    }
+
+    /// Initial sequence number for a SYN to `target`.
+    fn isn(&self, target: SocketAddr) -> u32 {
+        self.cookie
+            .hash_one((target.ip(), target.port(), self.src_port)) as u32
+    }
+
+    fn source_for(&self, dst: IpAddr) -> io::Result<IpAddr> {
+        if self.src_ip.is_some() {
+            return LocalBind {
+                ip: self.src_ip,
+                ..LocalBind::default()
+            }
+            .local_addr(dst)
+            .map(|a| a.ip());
+        }
+        if let Some(&src) = self.sources.lock().unwrap().get(&dst) {
+            return Ok(src);
+        }
+        let src = route_source(dst)?;
+        self.sources.lock().unwrap().insert(dst, src);
+        Ok(src)
+    }
+}
+
+/// Read replies from one family's raw socket until the engine is dropped.
+async fn receive(shared: Arc<Shared>, v6: bool) {
+    let sock = if v6 { &shared.v6 } else { &shared.v4 };
+    let Some(sock) = sock.as_ref() else {
+        return;
+    };
+    let mut buf = [0u8; 1500];
+    loop {
+        let Ok((n, from)) = sock.recv_from(&mut buf).await else {
+            // Errors here tend to persist (e.g. the interface went away);
+            // don't spin on them. Pending probes time out as filtered.
+            tokio::time::sleep(RECV_BACKOFF).await;
+            continue;
+        };
+        // IPv4 raw sockets deliver the IP header too; IPv6 ones don't.
+        let segment = if v6 {
+            &buf[..n]
+        } else {
+            let ihl = usize::from(buf[0] & 0x0f) * 4;
+            match buf[..n].get(ihl..) {
+                Some(s) => s,
+                None => continue,
+            }
+        };
+        let Some(reply) = Reply::parse(segment) else {
+            continue;
+        };
+        if reply.dst_port != shared.src_port {
+            continue;
+        }
+        let target = SocketAddr::new(from.ip(), reply.src_port);
+        let isn = shared.isn(target);
+        let Some(state) = reply.classify(isn) else {
+            continue;
+        };
+        if state == PortState::Open {
+            // Tear down before the target retransmits its SYN/ACK.
+            if let Ok(src) = shared.source_for(target.ip()) {
+                let src = SocketAddr::new(src, shared.src_port);
+                let rst = tcp_segment(src, target, isn.wrapping_add(1), 0, RST);
+                let _ = sock.send_to(&rst, SocketAddr::new(target.ip(), 0)).await;
+            }
+        }
+        let waiter = shared
+            .pending
+            .lock()
+            .unwrap()
+            .remove(&(target.ip(), target.port()));
+        if let Some(tx) = waiter {
+            let _ = tx.send(state);
+        }
+    }
+}
+
+/// Raw TCP socket wrapped for async use. Tokio has no raw socket type, but a
+/// UDP socket only ever uses its descriptor for `sendto`/`recvfrom`, which
+/// is exactly the interface a raw socket offers.
+fn raw_socket(domain: Domain, local: &LocalBind) -> io::Result<UdpSocket> {
+    let sock = Socket::new(domain, Type::RAW, Some(SockProtocol::TCP))?;
+    local.bind_interface(&sock)?;
+    sock.set_nonblocking(true)?;
+    UdpSocket::from_std(std::net::UdpSocket::from(sock))
+}
+
+/// Local address the kernel would use to reach `dst`, found by connecting a
+/// UDP socket (which sends nothing).
+fn route_source(dst: IpAddr) -> io::Result<IpAddr> {
+    let any: IpAddr = match dst {
+        IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        IpAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    };
+    let probe = std::net::UdpSocket::bind((any, 0))?;
+    probe.connect((dst, 9))?;
+    Ok(probe.local_addr()?.ip())
+}
+
+/// The fields of a received segment that matter for classification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Reply {
+    src_port: u16,
+    dst_port: u16,
+    ack: u32,
+    flags: u8,
+}
+
+impl Reply {
+    fn parse(seg: &[u8]) -> Option<Self> {
+        if seg.len() < 20 {
+            return None;
+        }
+        Some(Self {
+            src_port: u16::from_be_bytes([seg[0], seg[1]]),
+            dst_port: u16::from_be_bytes([seg[2], seg[3]]),
+            ack: u32::from_be_bytes([seg[8], seg[9], seg[10], seg[11]]),
+            flags: seg[13],
+        })
+    }
+
+    /// State this reply proves for a SYN sent with `isn`, if it answers it.
+    fn classify(&self, isn: u32) -> Option<PortState> {
+        if self.flags & ACK == 0 || self.ack != isn.wrapping_add(1) {
+            return None;
+        }
+        if self.flags & RST != 0 {
+            Some(PortState::Closed)
+        } else if self.flags & (SYN | FIN) == SYN {
+            Some(PortState::Open)
+        } else {
+            None
+        }
+    }
+}
+
+/// TCP header (plus an MSS option on SYNs) with the checksum filled in.
+fn tcp_segment(src: SocketAddr, dst: SocketAddr, seq: u32, ack: u32, flags: u8) -> Vec<u8> {
+    let words: u8 = if flags & SYN != 0 { 6 } else { 5 };
+    let mut seg = Vec::with_capacity(usize::from(words) * 4);
+    seg.extend_from_slice(&src.port().to_be_bytes());
+    seg.extend_from_slice(&dst.port().to_be_bytes());
+    seg.extend_from_slice(&seq.to_be_bytes());
+    seg.extend_from_slice(&ack.to_be_bytes());
+    seg.push(words << 4);
+    seg.push(flags);
+    seg.extend_from_slice(&1024u16.to_be_bytes()); // window
+    seg.extend_from_slice(&[0, 0, 0, 0]); // checksum, urgent pointer
+    if flags & SYN != 0 {
+        seg.extend_from_slice(&[2, 4, 0x05, 0xb4]); // MSS 1460
+    }
+
+    let mut pseudo = Vec::with_capacity(40 + seg.len());
+    match (src.ip(), dst.ip()) {
+        (IpAddr::V4(s), IpAddr::V4(d)) => {
+            pseudo.extend_from_slice(&s.octets());
+            pseudo.extend_from_slice(&d.octets());
+            pseudo.extend_from_slice(&[0, 6]);
+            pseudo.extend_from_slice(&(seg.len() as u16).to_be_bytes());
+        }
+        (s, d) => {
+            pseudo.extend_from_slice(&ipv6_octets(s));
+            pseudo.extend_from_slice(&ipv6_octets(d));
+            pseudo.extend_from_slice(&(seg.len() as u32).to_be_bytes());
+            pseudo.extend_from_slice(&[0, 0, 0, 6]);
+        }
+    }
+    pseudo.extend_from_slice(&seg);
+    let sum = internet_checksum(&pseudo);
+    seg[16..18].copy_from_slice(&sum.to_be_bytes());
+    seg
+}
+
+fn ipv6_octets(ip: IpAddr) -> [u8; 16] {
+    match ip {
+        IpAddr::V4(v4) => v4.to_ipv6_mapped().octets(),
+        IpAddr::V6(v6) => v6.octets(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sa(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn syn_segment_checksums_to_zero() {
+        let (src, dst) = (sa("10.0.0.1:40000"), sa("10.0.0.2:443"));
+        let seg = tcp_segment(src, dst, 0x1234_5678, 0, SYN);
+        assert_eq!(seg.len(), 24);
+        assert_eq!(seg[12] >> 4, 6);
+        assert_eq!(seg[13], SYN);
+
+        let mut pseudo = vec![10, 0, 0, 1, 10, 0, 0, 2, 0, 6, 0, 24];
+        pseudo.extend_from_slice(&seg);
+        assert_eq!(internet_checksum(&pseudo), 0);
+    }
+
+    #[test]
+    fn replies_must_acknowledge_our_syn() {
+        let isn = 1000;
+        let reply = |flags, ack| Reply {
+            src_port: 22,
+            dst_port: 40000,
+            ack,
+            flags,
+        };
+        assert_eq!(reply(SYN | ACK, 1001).classify(isn), Some(PortState::Open));
+        assert_eq!(
+            reply(RST | ACK, 1001).classify(isn),
+            Some(PortState::Closed)
+        );
+        assert_eq!(reply(SYN | ACK, 5).classify(isn), None);
+        assert_eq!(reply(SYN, 0).classify(isn), None);
+        assert_eq!(reply(FIN | ACK, 1001).classify(isn), None);
+    }
+
+    #[test]
+    fn parses_reply_header() {
+        let seg = tcp_segment(sa("10.0.0.2:443"), sa("10.0.0.1:40000"), 7, 1001, SYN | ACK);
+        assert_eq!(
+            Reply::parse(&seg),
+            Some(Reply {
+                src_port: 443,
+                dst_port: 40000,
+                ack: 1001,
+                flags: SYN | ACK,
+            })
+        );
+        assert_eq!(Reply::parse(&seg[..19]), None);
+    }
+}
@@ -0,0 +1,518 @@
+//! Target specification parsing and expansion.
+//!
+//! Accepted forms (comma or whitespace separated):
+//! - single addresses: `192.168.1.1`, `::1`
+//! - CIDR blocks: `10.0.0.0/24`, `2001:db8::/120`
+//! - dash ranges: `10.0.0.1-50` (last octet / hextet) or `10.0.0.1-10.0.1.20`
+//! - hostnames: `scanme.example.org` (resolved at expansion time; every
+//!   address it resolves to is scanned)
+//!
+//! [`expand_targets_in`] restricts the scan to one address family (`-4` /
+//! `-6`): hostnames keep only their addresses of that family and literal
+//! addresses of the other one are an error.
+
+use std::{
+    collections::HashSet,
+    fmt, fs, io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::Path,
+};
+use thiserror::Error;
+use tokio::net::lookup_host;
+
+/// Upper bound on the number of hosts a single scan may expand to, so a
+/// mistyped IPv6 prefix does not try to allocate the address space.
+pub const MAX_HOSTS: u128 = 1 << 20;
+
+#[derive(Debug, Error)]
+pub enum TargetError {
+    #[error("invalid target '{0}'")]
+    Invalid(String),
+    #[error("target '{spec}' expands to {count} hosts (limit {MAX_HOSTS})")]
+    TooManyHosts { spec: String, count: u128 },
+    #[error("failed to resolve '{host}': {source}")]
+    Resolve { host: String, source: io::Error },
+    #[error("failed to read target list {path}: {source}")]
+    ReadList { path: String, source: io::Error },
+    #[error("target '{spec}' is not an {family} address")]
+    WrongFamily { spec: String, family: IpFamily },
+    #[error("'{host}' has no {family} address")]
+    NoAddress { host: String, family: IpFamily },
+}
+
+/// Address families a scan may target.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IpFamily {
+    #[default]
+    Any,
+    V4,
+    V6,
+}
+
+impl IpFamily {
+    /// The family of `ip`.
+    pub fn of(ip: IpAddr) -> Self {
+        if ip.is_ipv4() {
+            Self::V4
+        } else {
+            Self::V6
+        }
+    }
+
+    pub fn matches(self, ip: IpAddr) -> bool {
+        self == Self::Any || self == Self::of(ip)
+    }
+}
+
+impl fmt::Display for IpFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Any => "IP",
+            Self::V4 => "IPv4",
+            Self::V6 => "IPv6",
+        })
+    }
+}
+
+/// Inclusive range of addresses within one address family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpRange {
+    start: IpAddr,
+    end: IpAddr,
+}
+
+impl IpRange {
+    /// Build a range; bounds are swapped if reversed. Returns `None` if the
+    /// two addresses belong to different families.
+    pub fn new(start: IpAddr, end: IpAddr) -> Option<Self> {
+        if start.is_ipv4() != end.is_ipv4() {
+            return None;
+        }
+        let (start, end) = if to_u128(start) <= to_u128(end) {
+            (start, end)
+        } else {
+            (end, start)
+        };
+        Some(Self { start, end })
+    }
+
+    pub fn single(ip: IpAddr) -> Self {
+        Self { start: ip, end: ip }
+    }
+
+    /// Range covering every address of `base/prefix` (network and broadcast
+    /// addresses included).
+    pub fn cidr(base: IpAddr, prefix: u8) -> Option<Self> {
+        let bits = if base.is_ipv4() { 32 } else { 128 };
+        if prefix > bits {
+            return None;
+        }
+        let host_bits = u32::from(bits - prefix);
+        let mask = if host_bits >= 128 {
+            u128::MAX
+        } else {
+            (1u128 << host_bits) - 1
+        };
+        let start = to_u128(base) & !mask;
+        let end = start | mask;
+        Some(Self {
+            start: from_u128(start, base.is_ipv4()),
+            end: from_u128(end, base.is_ipv4()),
+        })
+    }
+
+    pub fn start(&self) -> IpAddr {
+        self.start
+    }
+
+    pub fn end(&self) -> IpAddr {
+        self.end
+    }
+
+    /// Number of addresses in the range (saturating for a full IPv6 /0).
+    pub fn host_count(&self) -> u128 {
+        (to_u128(self.end) - to_u128(self.start)).saturating_add(1)
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        ip.is_ipv4() == self.start.is_ipv4()
+            && (to_u128(self.start)..=to_u128(self.end)).contains(&to_u128(ip))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = IpAddr> {
+        let v4 = self.start.is_ipv4();
+        (to_u128(self.start)..=to_u128(self.end)).map(move |n| from_u128(n, v4))
+    }
+}
+
+fn to_u128(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(v4) => u128::from(u32::from(v4)),
+        IpAddr::V6(v6) => u128::from(v6),
+    }
+}
+
+fn from_u128(n: u128, v4: bool) -> IpAddr {
+    if v4 {
+        IpAddr::V4(Ipv4Addr::from(n as u32))
+    } else {
+        IpAddr::V6(Ipv6Addr::from(n))
+    }
+}
+
+/// One parsed target token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TargetSpec {
+    Range(IpRange),
+    Host(String),
+}
+
+/// Parse a single token (no commas).
+pub fn parse_target_spec(token: &str) -> Result<TargetSpec, TargetError> {
+    let token = token.trim();
+    let invalid = || TargetError::Invalid(token.to_string());
+    if token.is_empty() {
+        return Err(invalid());
+    }
+
+    if let Some((base, prefix)) = token.split_once('/') {
+        let base: IpAddr = base.parse().map_err(|_| invalid())?;
+        let prefix: u8 = prefix.parse().map_err(|_| invalid())?;
+        return IpRange::cidr(base, prefix)
+            .map(TargetSpec::Range)
+            .ok_or_else(invalid);
+    }
+
+    if let Ok(ip) = token.parse::<IpAddr>() {
+        return Ok(TargetSpec::Range(IpRange::single(ip)));
+    }
+
+    if let Some((a, b)) = token.split_once('-') {
+        if let Ok(start) = a.parse::<IpAddr>() {
+            let end = parse_range_end(start, b).ok_or_else(invalid)?;
+            return IpRange::new(start, end)
+                .map(TargetSpec::Range)
+                .ok_or_else(invalid);
+        }
+    }
+
+    let is_hostname = token
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+        && !token.starts_with('-');
+    if is_hostname {
+        Ok(TargetSpec::Host(token.to_string()))
+    } else {
+        Err(invalid())
+    }
+}
+
+/// Right-hand side of a dash range: a full address, or just the last IPv4
+/// octet (decimal) / IPv6 hextet (hex).
+fn parse_range_end(start: IpAddr, end: &str) -> Option<IpAddr> {
+    if let Ok(ip) = end.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    match start {
+        IpAddr::V4(v4) => {
+            let last: u8 = end.parse().ok()?;
+            let mut o = v4.octets();
+            o[3] = last;
+            Some(IpAddr::V4(Ipv4Addr::from(o)))
+        }
+        IpAddr::V6(v6) => {
+            let last = u16::from_str_radix(end, 16).ok()?;
+            let mut s = v6.segments();
+            s[7] = last;
+            Some(IpAddr::V6(Ipv6Addr::from(s)))
+        }
+    }
+}
+
+/// Parse a comma- and/or whitespace-separated list of target tokens.
+pub fn parse_target_list(spec: &str) -> Result<Vec<TargetSpec>, TargetError> {
+    spec.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(parse_target_spec)
+        .collect()
+}
+
+/// Read targets from a file (`-iL`): any mix of separators, `#` comments.
+pub fn read_target_file(path: &Path) -> Result<Vec<TargetSpec>, TargetError> {
+    let text = fs::read_to_string(path).map_err(|source| TargetError::ReadList {
+        path: path.display().to_string(),
+        source,
+    })?;
+    let mut out = Vec::new();
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or("");
+        out.extend(parse_target_list(line)?);
+    }
+    Ok(out)
+}
+
+/// Resolve a hostname or IP literal to the first address it maps to.
+pub async fn resolve_host(host: &str) -> io::Result<IpAddr> {
+    lookup_host((host, 0))
+        .await?
+        .next()
+        .map(|sa| sa.ip())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no addresses found"))
+}
+
+/// Resolve a hostname or IP literal to every address it maps to, in the
+/// resolver's order without duplicates.
+pub async fn resolve_all(host: &str) -> io::Result<Vec<IpAddr>> {
+    let mut seen = HashSet::new();
+    let addrs: Vec<IpAddr> = lookup_host((host, 0))
+        .await?
+        .map(|sa| sa.ip())
+        .filter(|ip| seen.insert(*ip))
+        .collect();
+    if addrs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no addresses found",
+        ));
+    }
+    Ok(addrs)
+}
+
+/// `strict` turns a range outside `family` into an error instead of
+/// dropping it, for targets (as opposed to exclusions).
+async fn to_ranges(
+    specs: &[TargetSpec],
+    family: IpFamily,
+    strict: bool,
+) -> Result<Vec<IpRange>, TargetError> {
+    let mut out = Vec::with_capacity(specs.len());
+    for spec in specs {
+        match spec {
+            TargetSpec::Range(r) if family.matches(r.start) => out.push(*r),
+            TargetSpec::Range(r) if strict => {
+                let spec = if r.start == r.end {
+                    r.start.to_string()
+                } else {
+                    format!("{}-{}", r.start, r.end)
+                };
+                return Err(TargetError::WrongFamily { spec, family });
+            }
+            TargetSpec::Range(_) => {}
+            TargetSpec::Host(host) => {
+                let ips = resolve_all(host)
+                    .await
+                    .map_err(|source| TargetError::Resolve {
+                        host: host.clone(),
+                        source,
+                    })?;
+                let before = out.len();
+                out.extend(
+                    ips.into_iter()
+                        .filter(|&ip| family.matches(ip))
+                        .map(IpRange::single),
+                );
+                if strict && out.len() == before {
+                    return Err(TargetError::NoAddress {
+                        host: host.clone(),
+                        family,
+                    });
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Resolve and expand `include` into a deduplicated host list (first-seen
+/// order), dropping anything covered by `exclude`.
+pub async fn expand_targets(
+    include: &[TargetSpec],
+    exclude: &[TargetSpec],
+) -> Result<Vec<IpAddr>, TargetError> {
+    expand_targets_in(include, exclude, IpFamily::Any).await
+}
+
+/// [`expand_targets`] limited to `family`.
+pub async fn expand_targets_in(
+    include: &[TargetSpec],
+    exclude: &[TargetSpec],
+    family: IpFamily,
+) -> Result<Vec<IpAddr>, TargetError> {
+    let include = to_ranges(include, family, true).await?;
+    let exclude = to_ranges(exclude, family, false).await?;
+
+    let total: u128 = include.iter().map(IpRange::host_count).sum();
+    if total > MAX_HOSTS {
+        let spec = include
+            .iter()
+            .max_by_key(|r| r.host_count())
+            .map(|r| format!("{}-{}", r.start, r.end))
+            .unwrap_or_default();
+        return Err(TargetError::TooManyHosts { spec, count: total });
+    }
+
+    let mut seen = HashSet::new();
+    let mut hosts = Vec::new();
+    for range in &include {
+        for ip in range.iter() {
+            if exclude.iter().any(|ex| ex.contains(ip)) {
+                continue;
+            }
+            if seen.insert(ip) {
+                hosts.push(ip);
+            }
+        }
+    }
+    Ok(hosts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    async fn expand(spec: &str) -> Vec<IpAddr> {
+        expand_targets(&parse_target_list(spec).unwrap(), &[])
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn cidr_v4() {
+        let hosts = expand("10.0.0.5/30").await;
+        assert_eq!(
+            hosts,
+            vec![
+                ip("10.0.0.4"),
+                ip("10.0.0.5"),
+                ip("10.0.0.6"),
+                ip("10.0.0.7")
+            ]
+        );
+        assert_eq!(expand("10.0.0.5/32").await, vec![ip("10.0.0.5")]);
+    }
+
+    #[tokio::test]
+    async fn cidr_v6() {
+        let hosts = expand("2001:db8::/126").await;
+        assert_eq!(hosts.len(), 4);
+        assert_eq!(hosts[0], ip("2001:db8::"));
+        assert_eq!(hosts[3], ip("2001:db8::3"));
+    }
+
+    #[test]
+    fn cidr_prefix_out_of_range() {
+        assert!(parse_target_spec("10.0.0.0/33").is_err());
+        assert!(parse_target_spec("::/129").is_err());
+    }
+
+    #[tokio::test]
+    async fn dash_range_last_octet() {
+        let hosts = expand("10.0.0.1-3").await;
+        assert_eq!(hosts, vec![ip("10.0.0.1"), ip("10.0.0.2"), ip("10.0.0.3")]);
+    }
+
+    #[tokio::test]
+    async fn dash_range_full_addresses() {
+        let hosts = expand("10.0.0.254-10.0.1.1").await;
+        assert_eq!(
+            hosts,
+            vec![
+                ip("10.0.0.254"),
+                ip("10.0.0.255"),
+                ip("10.0.1.0"),
+                ip("10.0.1.1")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn dash_range_v6_hextet() {
+        let hosts = expand("fe80::a-c").await;
+        assert_eq!(hosts, vec![ip("fe80::a"), ip("fe80::b"), ip("fe80::c")]);
+    }
+
+    #[tokio::test]
+    async fn comma_list_dedups_in_order() {
+        let hosts = expand("10.0.0.2, 10.0.0.1,10.0.0.2").await;
+        assert_eq!(hosts, vec![ip("10.0.0.2"), ip("10.0.0.1")]);
+    }
+
+    #[tokio::test]
+    async fn exclusions_are_removed() {
+        let include = parse_target_list("10.0.0.0/29").unwrap();
+        let exclude = parse_target_list("10.0.0.0,10.0.0.6-7").unwrap();
+        let hosts = expand_targets(&include, &exclude).await.unwrap();
+        assert_eq!(hosts.first(), Some(&ip("10.0.0.1")));
+        assert_eq!(hosts.last(), Some(&ip("10.0.0.5")));
+        assert_eq!(hosts.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn huge_prefix_is_rejected() {
+        let specs = parse_target_list("2001:db8::/64").unwrap();
+        assert!(matches!(
+            expand_targets(&specs, &[]).await,
+            Err(TargetError::TooManyHosts { .. })
+        ));
+    }
+
+    #[test]
+    fn hostnames_and_garbage() {
+        assert_eq!(
+            parse_target_spec("scan-me.example.org").unwrap(),
+            TargetSpec::Host("scan-me.example.org".into())
+        );
+        assert!(parse_target_spec("10.0.0.1-abc!").is_err());
+        assert!(parse_target_spec("not a host").is_err());
+    }
+
+    #[test]
+    fn mixed_family_range_rejected() {
+        assert!(parse_target_spec("10.0.0.1-::1").is_err());
+    }
+
+    #[test]
+    fn target_file_with_comments() {
+        let path = std::env::temp_dir().join(format!("ps-targets-{}.txt", std::process::id()));
+        fs::write(&path, "# lab hosts\n10.0.0.1\n10.0.0.2,10.0.0.3 # dmz\n\n").unwrap();
+        let specs = read_target_file(&path).unwrap();
+        fs::remove_file(&path).ok();
+        assert_eq!(specs.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn resolves_ip_literal() {
+        assert_eq!(resolve_host("127.0.0.1").await.unwrap(), ip("127.0.0.1"));
+        assert_eq!(resolve_all("::1").await.unwrap(), vec![ip("::1")]);
+    }
+
+    #[tokio::test]
+    async fn family_filter() {
+        let specs = parse_target_list("10.0.0.1, ::1").unwrap();
+        let err = expand_targets_in(&specs, &[], IpFamily::V4)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "target '::1' is not an IPv4 address");
+
+        let v4 = parse_target_list("10.0.0.0/30").unwrap();
+        let exclude = parse_target_list("10.0.0.1, ::1").unwrap();
+        let hosts = expand_targets_in(&v4, &exclude, IpFamily::V4)
+            .await
+            .unwrap();
+        assert_eq!(hosts, vec![ip("10.0.0.0"), ip("10.0.0.2"), ip("10.0.0.3")]);
+    }
+
+    #[tokio::test]
+    async fn hostname_keeps_every_address_of_the_family() {
+        let host = vec![TargetSpec::Host("localhost".into())];
+        let all = expand_targets(&host, &[]).await.unwrap();
+        assert!(all.contains(&ip("127.0.0.1")));
+        let v4 = expand_targets_in(&host, &[], IpFamily::V4).await.unwrap();
+        assert!(v4.iter().all(IpAddr::is_ipv4));
+        assert_eq!(v4.len(), all.iter().filter(|a| a.is_ipv4()).count());
+    }
+}
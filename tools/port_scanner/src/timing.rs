@@ -0,0 +1,429 @@
+//! Adaptive timing and rate control.
+//!
+//! - [`RttEstimator`] derives a probe timeout from smoothed RTT and RTT
+//!   variance, the same way TCP computes its retransmission timeout
+//!   (RFC 6298): `timeout = srtt + 4 * rttvar`.
+//! - [`CongestionWindow`] is an AIMD limit on in-flight probes: it halves when
+//!   the recent timeout ratio spikes and grows back by one per window's worth
+//!   of answered probes.
+//! - [`RateLimiter`] paces probe starts to a hard packets-per-second cap.
+
+use crate::scanner::{PortResult, PortState};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::{sleep_until, Instant},
+};
+
+/// Smoothed RTT / variance tracker producing a clamped timeout.
+#[derive(Debug, Clone)]
+pub struct RttEstimator {
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    initial: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+impl RttEstimator {
+    /// `initial` is used until the first sample; results are kept in
+    /// `[min, max]`.
+    pub fn new(initial: Duration, min: Duration, max: Duration) -> Self {
+        Self {
+            srtt: None,
+            rttvar: Duration::ZERO,
+            initial,
+            min,
+            max: max.max(min),
+        }
+    }
+
+    /// Feed one RTT measurement.
+    pub fn observe(&mut self, sample: Duration) {
+        match self.srtt {
+            None => {
+                self.srtt = Some(sample);
+                self.rttvar = sample / 2;
+            }
+            Some(srtt) => {
+                let err = srtt.abs_diff(sample);
+                self.rttvar = (self.rttvar * 3 + err) / 4;
+                self.srtt = Some((srtt * 7 + sample) / 8);
+            }
+        }
+    }
+
+    pub fn srtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+
+    pub fn rttvar(&self) -> Duration {
+        self.rttvar
+    }
+
+    /// Current probe timeout.
+    pub fn timeout(&self) -> Duration {
+        let raw = match self.srtt {
+            Some(srtt) => srtt + self.rttvar * 4,
+            None => self.initial,
+        };
+        raw.clamp(self.min, self.max)
+    }
+}
+
+/// Number of recent outcomes the timeout ratio is computed over.
+const LOSS_SAMPLE: usize = 32;
+/// Timeout ratio above which the window is halved.
+const LOSS_THRESHOLD: f64 = 0.25;
+
+/// AIMD window over the number of in-flight probes.
+#[derive(Debug, Clone)]
+pub struct CongestionWindow {
+    window: usize,
+    min: usize,
+    max: usize,
+    recent: VecDeque<bool>,
+    credit: usize,
+}
+
+impl CongestionWindow {
+    /// Start fully open at `max`; never shrink below `min`.
+    pub fn new(min: usize, max: usize) -> Self {
+        let max = max.max(1);
+        Self {
+            window: max,
+            min: min.clamp(1, max),
+            max,
+            recent: VecDeque::with_capacity(LOSS_SAMPLE),
+            credit: 0,
+        }
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Record whether a probe timed out; returns the new window.
+    pub fn record(&mut self, timed_out: bool) -> usize {
+        if self.recent.len() == LOSS_SAMPLE {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(timed_out);
+
+        if timed_out {
+            let losses = self.recent.iter().filter(|t| **t).count();
+            if self.recent.len() == LOSS_SAMPLE
+                && losses as f64 / LOSS_SAMPLE as f64 > LOSS_THRESHOLD
+            {
+                self.window = (self.window / 2).max(self.min);
+                self.credit = 0;
+                // Judge the reduced window on fresh samples only.
+                self.recent.clear();
+            }
+        } else {
+            self.credit += 1;
+            if self.credit >= self.window {
+                self.window = (self.window + 1).min(self.max);
+                self.credit = 0;
+            }
+        }
+        self.window
+    }
+}
+
+/// Paces calls to [`RateLimiter::wait`] to at most `per_sec` per second.
+///
+/// A token bucket holding at most one token: unused time is never banked,
+/// so even after an idle gap no two probes go out much less than one period
+/// apart. Sleeps wake up to a timer tick (1 ms) late, so a deadline that
+/// slipped by less than that is kept instead of re-anchored to the wake-up
+/// time; otherwise sub-millisecond periods would run at one probe per tick.
+/// The cost is that a probe may go out up to one tick early.
+///
+/// Callers may share one limiter (e.g. behind an `Arc`); each `wait`
+/// reserves its own slot.
+#[derive(Debug)]
+pub struct RateLimiter {
+    per_sec: f64,
+    period: Duration,
+    /// Earliest start for the next probe.
+    next: Mutex<Instant>,
+}
+
+/// Granularity of `sleep_until`, and how far a deadline may lag behind.
+const TIMER_RESOLUTION: Duration = Duration::from_millis(1);
+
+impl RateLimiter {
+    pub fn new(per_sec: f64) -> Self {
+        Self {
+            per_sec,
+            period: Duration::try_from_secs_f64(1.0 / per_sec).unwrap_or(Duration::MAX),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn per_sec(&self) -> f64 {
+        self.per_sec
+    }
+
+    pub async fn wait(&self) {
+        let now = Instant::now();
+        let due = {
+            let mut next = self.next.lock().unwrap();
+            let floor = now.checked_sub(TIMER_RESOLUTION).unwrap_or(now);
+            let due = (*next).max(floor);
+            *next = due.checked_add(self.period).unwrap_or(due);
+            due
+        };
+        if due > now {
+            sleep_until(due).await;
+        }
+    }
+}
+
+/// Adaptive-mode settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdaptiveConfig {
+    pub min_timeout: Duration,
+    pub max_timeout: Duration,
+    /// Lower bound for the in-flight window when backing off.
+    pub min_concurrency: usize,
+}
+
+impl Default for AdaptiveConfig {
+    fn default() -> Self {
+        Self {
+            min_timeout: Duration::from_millis(50),
+            max_timeout: Duration::from_millis(3000),
+            min_concurrency: 8,
+        }
+    }
+}
+
+struct ControllerState {
+    rtt: RttEstimator,
+    cwnd: CongestionWindow,
+    /// Permits still to be withdrawn from the semaphore after a shrink that
+    /// found fewer idle permits than needed.
+    debt: usize,
+}
+
+/// Ties the estimator and window to the scan's semaphore.
+pub(crate) struct AdaptiveController {
+    state: Mutex<ControllerState>,
+    sem: Arc<Semaphore>,
+}
+
+impl AdaptiveController {
+    pub(crate) fn new(
+        cfg: AdaptiveConfig,
+        initial_timeout: Duration,
+        concurrency: usize,
+        sem: Arc<Semaphore>,
+    ) -> Self {
+        Self {
+            state: Mutex::new(ControllerState {
+                rtt: RttEstimator::new(initial_timeout, cfg.min_timeout, cfg.max_timeout),
+                cwnd: CongestionWindow::new(cfg.min_concurrency, concurrency),
+                debt: 0,
+            }),
+            sem,
+        }
+    }
+
+    pub(crate) fn timeout(&self) -> Duration {
+        self.state.lock().unwrap().rtt.timeout()
+    }
+
+    /// Learn from a finished attempt that ran with `used_timeout` and resize
+    /// the semaphore accordingly.
+    pub(crate) fn observe(&self, result: &PortResult, used_timeout: Duration) {
+        let mut st = self.state.lock().unwrap();
+        let answered = matches!(result.state, PortState::Open | PortState::Closed);
+        if answered {
+            st.rtt.observe(result.latency);
+        }
+
+        let before = st.cwnd.window();
+        let after = st.cwnd.record(result.timed_out(used_timeout));
+        if after < before {
+            let shrink = before - after;
+            let taken = self.sem.forget_permits(shrink);
+            st.debt += shrink - taken;
+        } else if after > before {
+            let grow = after - before;
+            let repaid = grow.min(st.debt);
+            st.debt -= repaid;
+            self.sem.add_permits(grow - repaid);
+        }
+    }
+
+    /// Return a probe's permit, or withdraw it if a shrink is still owed.
+    pub(crate) fn release(&self, permit: OwnedSemaphorePermit) {
+        let mut st = self.state.lock().unwrap();
+        if st.debt > 0 {
+            st.debt -= 1;
+            permit.forget();
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn window(&self) -> usize {
+        self.state.lock().unwrap().cwnd.window()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Protocol;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn rto_follows_rfc6298() {
+        let mut est = RttEstimator::new(ms(300), ms(1), ms(10_000));
+        assert_eq!(est.timeout(), ms(300));
+        est.observe(ms(100));
+        // srtt = 100, rttvar = 50 -> 300
+        assert_eq!(est.timeout(), ms(300));
+        est.observe(ms(100));
+        // rttvar = (150 + 0) / 4 = 37.5, srtt = 100
+        assert_eq!(est.srtt(), Some(ms(100)));
+        assert_eq!(est.rttvar(), Duration::from_micros(37_500));
+        assert_eq!(est.timeout(), ms(250));
+    }
+
+    #[test]
+    fn rto_is_clamped() {
+        let mut est = RttEstimator::new(ms(300), ms(50), ms(500));
+        est.observe(Duration::from_micros(100));
+        assert_eq!(est.timeout(), ms(50));
+        est.observe(ms(5_000));
+        assert_eq!(est.timeout(), ms(500));
+    }
+
+    #[test]
+    fn window_halves_on_timeout_spike() {
+        let mut w = CongestionWindow::new(4, 64);
+        for _ in 0..LOSS_SAMPLE / 2 {
+            w.record(false);
+        }
+        assert_eq!(w.window(), 64);
+        let mut last = 64;
+        for _ in 0..LOSS_SAMPLE / 2 {
+            last = w.record(true);
+        }
+        assert_eq!(last, 32);
+    }
+
+    #[test]
+    fn window_never_below_min_and_regrows() {
+        let mut w = CongestionWindow::new(4, 8);
+        for _ in 0..LOSS_SAMPLE * 4 {
+            w.record(true);
+        }
+        assert_eq!(w.window(), 4);
+        for _ in 0..4 {
+            w.record(false);
+        }
+        assert_eq!(w.window(), 5);
+    }
+
+    #[tokio::test]
+    async fn rate_limiter_paces_probes() {
+        let rl = RateLimiter::new(100.0);
+        let start = Instant::now();
+        for _ in 0..11 {
+            rl.wait().await;
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= ms(95), "{elapsed:?}");
+    }
+
+    #[tokio::test]
+    async fn rate_limiter_does_not_burst_after_idle() {
+        let rl = RateLimiter::new(20.0);
+        rl.wait().await;
+        tokio::time::sleep(ms(300)).await;
+        let start = Instant::now();
+        for _ in 0..3 {
+            rl.wait().await;
+        }
+        // One probe may go at once, the other two a period apart (less
+        // the one tick of slack).
+        let elapsed = start.elapsed();
+        assert!(elapsed >= ms(98), "{elapsed:?}");
+    }
+
+    #[tokio::test]
+    async fn rate_limiter_handles_sub_millisecond_periods() {
+        let rl = RateLimiter::new(10_000.0);
+        let start = Instant::now();
+        for _ in 0..1001 {
+            rl.wait().await;
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= ms(99), "{elapsed:?}");
+    }
+
+    #[tokio::test]
+    async fn shared_rate_limiter_spaces_all_callers() {
+        let rl = Arc::new(RateLimiter::new(50.0));
+        let start = Instant::now();
+        let waiters: Vec<_> = (0..6)
+            .map(|_| {
+                let rl = Arc::clone(&rl);
+                tokio::spawn(async move { rl.wait().await })
+            })
+            .collect();
+        for w in waiters {
+            w.await.unwrap();
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= ms(95), "{elapsed:?}");
+    }
+
+    #[tokio::test]
+    async fn controller_withdraws_permits_when_shrinking() {
+        let sem = Arc::new(Semaphore::new(16));
+        let cfg = AdaptiveConfig {
+            min_concurrency: 2,
+            ..AdaptiveConfig::default()
+        };
+        let ctl = AdaptiveController::new(cfg, ms(100), 16, Arc::clone(&sem));
+        let filtered = PortResult {
+            addr: [10, 0, 0, 1].into(),
+            port: 1,
+            protocol: Protocol::Tcp,
+            state: PortState::Filtered,
+            latency: ms(100),
+            service: None,
+            tls: None,
+            http: None,
+        };
+        // Hold every permit so the shrink has to be paid back on release.
+        let mut held = Vec::new();
+        for _ in 0..16 {
+            held.push(Arc::clone(&sem).acquire_owned().await.unwrap());
+        }
+        for _ in 0..LOSS_SAMPLE {
+            let permit = held.pop().unwrap_or_else(|| {
+                Arc::clone(&sem)
+                    .try_acquire_owned()
+                    .expect("permit available")
+            });
+            ctl.observe(&filtered, ms(100));
+            ctl.release(permit);
+        }
+        assert_eq!(ctl.window(), 8);
+        drop(held);
+        assert_eq!(sem.available_permits(), 8);
+    }
+}
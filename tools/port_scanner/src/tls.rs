@@ -0,0 +1,432 @@
+//! TLS handshake inspection for open ports (`--tls`).
+//!
+//! A fresh connection is opened and a full handshake attempted with
+//! certificate verification switched off: the point is to see what the
+//! server presents, not to trust it. The negotiated protocol version and
+//! cipher suite come from the session; the leaf certificate is parsed for
+//! subject, issuer, SANs, validity period and public key size. Servers that
+//! do not speak TLS simply fail the handshake.
+//!
+//! rustls only negotiates TLS 1.2 and 1.3 with modern AEAD suites. When a
+//! server turns it down (an alert, an old version in its ServerHello, or a
+//! dropped connection), a second connection offers TLS 1.0 to 1.2 with
+//! legacy CBC, 3DES and RC4 suites. That handshake is never completed: up
+//! to TLS 1.2 the ServerHello and certificate travel in plaintext, so the
+//! version, suite and leaf certificate are read straight off the wire.
+//! SSL 2/3-only servers and suites outside that list still go unreported.
+
+use crate::banner::{identify_tls, legacy_client_hello};
+use chrono::{DateTime, Utc};
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, ProtocolVersion, SignatureScheme,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::{timeout, timeout_at, Instant},
+};
+use tokio_rustls::{client::TlsStream, TlsConnector};
+use x509_parser::{extensions::GeneralName, prelude::*, public_key::PublicKey};
+
+/// What a TLS server presented during the handshake.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlsInfo {
+    /// Negotiated protocol, e.g. `TLS 1.3`.
+    pub version: String,
+    /// Negotiated cipher suite, e.g. `TLS13_AES_256_GCM_SHA384`.
+    pub cipher: String,
+    /// Leaf certificate subject, e.g. `CN=www.example.org`.
+    pub subject: String,
+    pub issuer: String,
+    /// DNS names and IP addresses from the subjectAltName extension.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub san: Vec<String>,
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+    /// `RSA`, `EC`, ... or `unknown`.
+    pub key_type: String,
+    /// Public key size in bits (0 if unknown).
+    pub key_bits: usize,
+    /// Subject and issuer are the same name.
+    pub self_signed: bool,
+    /// `not_after` lies in the past.
+    pub expired: bool,
+}
+
+impl TlsInfo {
+    /// One-line summary for console output.
+    pub fn summary(&self) -> String {
+        let mut s = format!(
+            "{} {} | {} | issuer {} | {} {}-bit | valid {} to {}",
+            self.version,
+            self.cipher,
+            self.subject,
+            self.issuer,
+            self.key_type,
+            self.key_bits,
+            self.not_before.format("%Y-%m-%d"),
+            self.not_after.format("%Y-%m-%d")
+        );
+        if !self.san.is_empty() {
+            s.push_str(&format!(" | SAN {}", self.san.join(",")));
+        }
+        if self.self_signed {
+            s.push_str(" [self-signed]");
+        }
+        if self.expired {
+            s.push_str(" [expired]");
+        }
+        s
+    }
+}
+
+/// Connect to `addr` and complete a TLS handshake, each step bounded by
+/// `wait`. Servers rustls can't negotiate with get a legacy ClientHello on
+/// a second connection.
+pub async fn inspect_tls(addr: SocketAddr, wait: Duration) -> io::Result<TlsInfo> {
+    let connect = || async {
+        timeout(wait, TcpStream::connect(addr))
+            .await
+            .map_err(|_| io::ErrorKind::TimedOut)?
+    };
+    match tls_handshake(connect().await?, addr.ip(), wait).await {
+        Err(e) if wants_legacy_retry(&e) => legacy_handshake(connect().await?, wait).await,
+        res => res,
+    }
+}
+
+/// Whether a failed rustls handshake came from a server that may still
+/// speak an older TLS version, rather than from a non-TLS service.
+pub(crate) fn wants_legacy_retry(e: &io::Error) -> bool {
+    match e
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+    {
+        Some(rustls::Error::AlertReceived(_) | rustls::Error::PeerIncompatible(_)) => true,
+        Some(_) => false,
+        None => matches!(
+            e.kind(),
+            io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset
+        ),
+    }
+}
+
+/// Send a legacy ClientHello over `tcp` and read the version, cipher suite
+/// and leaf certificate from the plaintext reply, bounded by `wait`.
+pub(crate) async fn legacy_handshake(mut tcp: TcpStream, wait: Duration) -> io::Result<TlsInfo> {
+    let deadline = Instant::now() + wait;
+    timeout_at(deadline, tcp.write_all(&legacy_client_hello()))
+        .await
+        .map_err(|_| io::ErrorKind::TimedOut)??;
+
+    let mut messages = Vec::new();
+    let mut hello = None;
+    loop {
+        let mut header = [0u8; 5];
+        timeout_at(deadline, tcp.read_exact(&mut header))
+            .await
+            .map_err(|_| io::ErrorKind::TimedOut)??;
+        let mut body = vec![0; u16::from_be_bytes([header[3], header[4]]) as usize];
+        timeout_at(deadline, tcp.read_exact(&mut body))
+            .await
+            .map_err(|_| io::ErrorKind::TimedOut)??;
+        match header[0] {
+            0x16 => messages.extend(body),
+            0x15 => return Err(invalid("server refused every offered version and suite")),
+            _ => return Err(invalid("not a TLS handshake")),
+        }
+        if messages.len() > MAX_LEGACY_HANDSHAKE {
+            return Err(invalid("handshake too large"));
+        }
+        // Handshake messages may span records; act on the complete ones.
+        let mut at = 0;
+        while let Some(head) = messages.get(at..at + 4) {
+            let len = u32::from_be_bytes([0, head[1], head[2], head[3]]) as usize;
+            let Some(msg) = messages.get(at..at + 4 + len) else {
+                break;
+            };
+            match msg[0] {
+                2 => hello = Some(server_hello(msg)?),
+                11 => {
+                    let (version, cipher) = hello
+                        .take()
+                        .ok_or_else(|| invalid("certificate before ServerHello"))?;
+                    return cert_info(leaf_certificate(msg)?, version, cipher);
+                }
+                // ServerHelloDone without a certificate: an anonymous suite.
+                14 => return Err(invalid("server sent no certificate")),
+                _ => {}
+            }
+            at += 4 + len;
+        }
+        messages.drain(..at);
+    }
+}
+
+/// Upper bound on buffered handshake bytes in [`legacy_handshake`].
+const MAX_LEGACY_HANDSHAKE: usize = 64 * 1024;
+
+/// Version and cipher suite names from a ServerHello message.
+fn server_hello(msg: &[u8]) -> io::Result<(String, String)> {
+    // The banner code already knows how to name the version.
+    let mut record = vec![0x16, 0x03, 0x01];
+    record.extend((msg.len() as u16).to_be_bytes());
+    record.extend(msg);
+    let version = identify_tls(&record).and_then(|info| info.version);
+    // type(1) + length(3) + version(2) + random(32), then the session id.
+    let sid_len = msg.get(38).map(|&n| n as usize);
+    let suite = sid_len.and_then(|n| msg.get(39 + n..41 + n));
+    match (version, suite) {
+        (Some(version), Some(suite)) => Ok((
+            version,
+            suite_name(u16::from_be_bytes([suite[0], suite[1]])),
+        )),
+        _ => Err(invalid("bad ServerHello")),
+    }
+}
+
+/// First DER certificate of a Certificate message.
+fn leaf_certificate(msg: &[u8]) -> io::Result<&[u8]> {
+    // type(1) + length(3) + list length(3), then length-prefixed entries.
+    let len = msg
+        .get(7..10)
+        .map(|l| u32::from_be_bytes([0, l[0], l[1], l[2]]) as usize)
+        .ok_or_else(|| invalid("empty certificate list"))?;
+    msg.get(10..10 + len)
+        .ok_or_else(|| invalid("truncated certificate"))
+}
+
+/// IANA name of a suite offered by the legacy ClientHello.
+fn suite_name(id: u16) -> String {
+    let name = match id {
+        0x0004 => "TLS_RSA_WITH_RC4_128_MD5",
+        0x0005 => "TLS_RSA_WITH_RC4_128_SHA",
+        0x000a => "TLS_RSA_WITH_3DES_EDE_CBC_SHA",
+        0x002f => "TLS_RSA_WITH_AES_128_CBC_SHA",
+        0x0033 => "TLS_DHE_RSA_WITH_AES_128_CBC_SHA",
+        0x0035 => "TLS_RSA_WITH_AES_256_CBC_SHA",
+        0x0039 => "TLS_DHE_RSA_WITH_AES_256_CBC_SHA",
+        0x009c => "TLS_RSA_WITH_AES_128_GCM_SHA256",
+        0xc009 => "TLS_ECDHE_ECDSA_WITH_AES_128_CBC_SHA",
+        0xc00a => "TLS_ECDHE_ECDSA_WITH_AES_256_CBC_SHA",
+        0xc013 => "TLS_ECDHE_RSA_WITH_AES_128_CBC_SHA",
+        0xc014 => "TLS_ECDHE_RSA_WITH_AES_256_CBC_SHA",
+        0xc02b => "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256",
+        0xc02f => "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256",
+        other => return format!("0x{other:04x}"),
+    };
+    name.to_string()
+}
+
+/// Complete a TLS handshake with `ip` over an already connected stream
+/// (e.g. a proxy tunnel), bounded by `wait`.
+pub async fn tls_handshake(tcp: TcpStream, ip: IpAddr, wait: Duration) -> io::Result<TlsInfo> {
+    let tls = tls_connect(tcp, ip, wait).await?;
+    let (_, session) = tls.get_ref();
+    let version = match session.protocol_version() {
+        Some(ProtocolVersion::TLSv1_3) => "TLS 1.3".to_string(),
+        Some(ProtocolVersion::TLSv1_2) => "TLS 1.2".to_string(),
+        Some(v) => format!("{v:?}"),
+        None => "unknown".to_string(),
+    };
+    let cipher = session
+        .negotiated_cipher_suite()
+        .map(|s| format!("{:?}", s.suite()))
+        .unwrap_or_default();
+    let leaf = session
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .ok_or_else(|| invalid("server sent no certificate"))?;
+    cert_info(leaf, version, cipher)
+}
+
+/// Handshake without inspecting anything, for protocols spoken over TLS
+/// (`--http-info` on HTTPS ports). Certificates are not verified.
+pub(crate) async fn tls_connect(
+    tcp: TcpStream,
+    ip: IpAddr,
+    wait: Duration,
+) -> io::Result<TlsStream<TcpStream>> {
+    let connector = TlsConnector::from(client_config());
+    let name = ServerName::IpAddress(ip.into());
+    timeout(wait, connector.connect(name, tcp))
+        .await
+        .map_err(|_| io::ErrorKind::TimedOut)?
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Pull the interesting fields out of a DER certificate.
+fn cert_info(der: &[u8], version: String, cipher: String) -> io::Result<TlsInfo> {
+    let (_, cert) =
+        parse_x509_certificate(der).map_err(|e| invalid(format!("bad certificate: {e}")))?;
+
+    let san = match cert.subject_alternative_name() {
+        Ok(Some(ext)) => ext.value.general_names.iter().map(general_name).collect(),
+        _ => Vec::new(),
+    };
+    let validity = cert.validity();
+    let not_before = DateTime::from_timestamp(validity.not_before.timestamp(), 0)
+        .ok_or_else(|| invalid("notBefore out of range"))?;
+    let not_after = DateTime::from_timestamp(validity.not_after.timestamp(), 0)
+        .ok_or_else(|| invalid("notAfter out of range"))?;
+    let spki = cert.public_key();
+    let (key_type, key_bits) = match spki.parsed() {
+        Ok(PublicKey::RSA(rsa)) => ("RSA", significant_bits(rsa.modulus)),
+        Ok(key @ PublicKey::EC(_)) => ("EC", key.key_size()),
+        Ok(key @ PublicKey::DSA(_)) => ("DSA", key.key_size()),
+        // x509-parser has no variant for the EdDSA curves.
+        Ok(_) | Err(_) => match spki.algorithm.algorithm.to_id_string().as_str() {
+            "1.3.101.112" => ("Ed25519", 256),
+            "1.3.101.113" => ("Ed448", 456),
+            _ => ("unknown", 0),
+        },
+    };
+
+    Ok(TlsInfo {
+        version,
+        cipher,
+        subject: cert.subject().to_string(),
+        issuer: cert.issuer().to_string(),
+        san,
+        not_before,
+        not_after,
+        key_type: key_type.to_string(),
+        key_bits,
+        self_signed: cert.subject().as_raw() == cert.issuer().as_raw(),
+        expired: not_after < Utc::now(),
+    })
+}
+
+fn general_name(name: &GeneralName) -> String {
+    match name {
+        GeneralName::DNSName(s) | GeneralName::RFC822Name(s) | GeneralName::URI(s) => s.to_string(),
+        GeneralName::IPAddress(b) => match b.len() {
+            4 => IpAddr::from(Ipv4Addr::from(<[u8; 4]>::try_from(*b).unwrap())).to_string(),
+            16 => IpAddr::from(Ipv6Addr::from(<[u8; 16]>::try_from(*b).unwrap())).to_string(),
+            _ => name.to_string(),
+        },
+        other => other.to_string(),
+    }
+}
+
+/// Bit length of a big-endian unsigned integer (DER may add a leading 0).
+fn significant_bits(be: &[u8]) -> usize {
+    let Some(pos) = be.iter().position(|&b| b != 0) else {
+        return 0;
+    };
+    (be.len() - pos - 1) * 8 + (8 - be[pos].leading_zeros() as usize)
+}
+
+/// Client config shared by every probe; built on first use.
+fn client_config() -> Arc<ClientConfig> {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+    CONFIG
+        .get_or_init(|| {
+            let provider = Arc::new(ring::default_provider());
+            let config = ClientConfig::builder_with_provider(Arc::clone(&provider))
+                .with_safe_default_protocol_versions()
+                .expect("ring provider supports the default protocol versions")
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(AcceptAnyCert(provider)))
+                .with_no_client_auth();
+            Arc::new(config)
+        })
+        .clone()
+}
+
+/// Accepts every certificate but still checks handshake signatures, so the
+/// session is only established with a server holding the certificate's key.
+#[derive(Debug)]
+struct AcceptAnyCert(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bit_length_ignores_der_padding() {
+        assert_eq!(significant_bits(&[0x00, 0x80, 0x00]), 16);
+        assert_eq!(significant_bits(&[0x01, 0xff]), 9);
+        assert_eq!(significant_bits(&[0, 0]), 0);
+    }
+
+    #[test]
+    fn summary_flags_self_signed_and_expired() {
+        let info = TlsInfo {
+            version: "TLS 1.2".into(),
+            cipher: "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256".into(),
+            subject: "CN=old.test".into(),
+            issuer: "CN=old.test".into(),
+            san: vec!["old.test".into()],
+            not_before: DateTime::from_timestamp(1_500_000_000, 0).unwrap(),
+            not_after: DateTime::from_timestamp(1_600_000_000, 0).unwrap(),
+            key_type: "RSA".into(),
+            key_bits: 2048,
+            self_signed: true,
+            expired: true,
+        };
+        assert_eq!(
+            info.summary(),
+            "TLS 1.2 TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256 | CN=old.test | issuer CN=old.test \
+             | RSA 2048-bit | valid 2017-07-14 to 2020-09-13 | SAN old.test [self-signed] [expired]"
+        );
+    }
+}
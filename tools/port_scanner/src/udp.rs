@@ -0,0 +1,226 @@
+//! UDP probing.
+//!
+//! Each probe uses its own connected `UdpSocket` so the kernel can report
+//! an ICMP port-unreachable for that exact destination as `ECONNREFUSED`
+//! on the next receive. Classification:
+//! - any datagram back → open
+//! - `ECONNREFUSED` → closed
+//! - other ICMP errors (host/net unreachable, ...) → filtered
+//! - silence until the timeout → open|filtered
+
+use crate::{
+    bind::{domain_for, LocalBind},
+    scanner::{PortResult, PortState, Protocol},
+};
+use socket2::{Protocol as SockProtocol, Socket, Type};
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+use tokio::{io::Interest, net::UdpSocket, time::timeout};
+
+/// Protocol-appropriate payload for `port`; services that ignore malformed
+/// input would otherwise look identical to a firewall drop.
+pub fn payload_for_port(port: u16) -> Vec<u8> {
+    match port {
+        53 => dns_query(),
+        123 => ntp_request(),
+        161 => snmp_get(),
+        _ => Vec::new(),
+    }
+}
+
+/// DNS standard query for the root `NS` record.
+fn dns_query() -> Vec<u8> {
+    vec![
+        0x13, 0x37, // id
+        0x01, 0x00, // flags: RD
+        0x00, 0x01, // qdcount
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // an/ns/ar count
+        0x00, // root name
+        0x00, 0x02, // type NS
+        0x00, 0x01, // class IN
+    ]
+}
+
+/// 48-byte NTPv3 client request.
+fn ntp_request() -> Vec<u8> {
+    let mut pkt = vec![0u8; 48];
+    pkt[0] = 0x1b; // LI=0, VN=3, Mode=3 (client)
+    pkt
+}
+
+/// SNMPv1 GetRequest for `sysDescr.0` with community `public`.
+fn snmp_get() -> Vec<u8> {
+    // OID 1.3.6.1.2.1.1.1.0
+    let oid = [0x2b, 0x06, 0x01, 0x02, 0x01, 0x01, 0x01, 0x00];
+    let varbind = der(0x30, &[der(0x06, &oid), der(0x05, &[])].concat());
+    let varbinds = der(0x30, &varbind);
+    let pdu = der(
+        0xa0,
+        &[
+            der(0x02, &[0x13, 0x37, 0x13, 0x37]), // request id
+            der(0x02, &[0x00]),                   // error status
+            der(0x02, &[0x00]),                   // error index
+            varbinds,
+        ]
+        .concat(),
+    );
+    der(
+        0x30,
+        &[der(0x02, &[0x00]), der(0x04, b"public"), pdu].concat(),
+    )
+}
+
+/// Short-form DER TLV (all SNMP probe pieces are < 128 bytes).
+fn der(tag: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag, body.len() as u8];
+    out.extend_from_slice(body);
+    out
+}
+
+/// Send the port's payload and classify the reply (or its absence).
+pub async fn probe_udp(addr: SocketAddr, timeout_dur: Duration) -> PortResult {
+    probe_udp_from(addr, timeout_dur, &LocalBind::default()).await
+}
+
+/// [`probe_udp`] sent from `local`.
+pub async fn probe_udp_from(
+    addr: SocketAddr,
+    timeout_dur: Duration,
+    local: &LocalBind,
+) -> PortResult {
+    let started = Instant::now();
+    let payload = payload_for_port(addr.port());
+    let state = match exchange(addr, &payload, timeout_dur, local).await {
+        Ok(Some(_)) => PortState::Open,
+        Ok(None) => PortState::OpenFiltered,
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => PortState::Closed,
+        Err(_) => PortState::Filtered,
+    };
+    PortResult {
+        addr: addr.ip(),
+        port: addr.port(),
+        protocol: Protocol::Udp,
+        state,
+        latency: started.elapsed(),
+        service: None,
+        tls: None,
+        http: None,
+    }
+}
+
+/// Returns the reply length, `None` on timeout, or the socket error.
+async fn exchange(
+    addr: SocketAddr,
+    payload: &[u8],
+    wait: Duration,
+    local: &LocalBind,
+) -> io::Result<Option<usize>> {
+    let sock = bind_udp(addr.ip(), local).await?;
+    sock.connect(addr).await?;
+    sock.send(payload).await?;
+    match timeout(wait, recv_or_error(&sock)).await {
+        Ok(Ok(n)) => Ok(Some(n)),
+        Ok(Err(e)) => Err(e),
+        Err(_elapsed) => Ok(None),
+    }
+}
+
+/// Unconnected socket of `target`'s family, bound as `local` asks.
+async fn bind_udp(target: IpAddr, local: &LocalBind) -> io::Result<UdpSocket> {
+    if local.is_default() {
+        let any = match target {
+            IpAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+            IpAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+        };
+        return UdpSocket::bind(any).await;
+    }
+    let sock = Socket::new(domain_for(target), Type::DGRAM, Some(SockProtocol::UDP))?;
+    local.apply(&sock, target, true)?;
+    sock.set_nonblocking(true)?;
+    UdpSocket::from_std(sock.into())
+}
+
+/// Wait for a datagram or a pending socket error. A plain `recv` is only
+/// woken by readability, so an ICMP error queued on the socket would go
+/// unnoticed until the timeout.
+async fn recv_or_error(sock: &UdpSocket) -> io::Result<usize> {
+    let mut buf = [0u8; 1500];
+    loop {
+        let ready = sock.ready(Interest::READABLE | Interest::ERROR).await?;
+        if ready.is_error() {
+            if let Some(e) = sock.take_error()? {
+                return Err(e);
+            }
+        }
+        if ready.is_readable() {
+            match sock.try_recv(&mut buf) {
+                Ok(n) => return Ok(n),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAIT: Duration = Duration::from_millis(300);
+
+    fn localhost(port: u16) -> SocketAddr {
+        SocketAddr::new([127, 0, 0, 1].into(), port)
+    }
+
+    #[test]
+    fn payloads_by_port() {
+        assert_eq!(payload_for_port(53).len(), 17);
+        assert_eq!(payload_for_port(123)[0], 0x1b);
+        assert!(payload_for_port(9999).is_empty());
+    }
+
+    #[test]
+    fn snmp_get_is_well_formed() {
+        let pkt = snmp_get();
+        assert_eq!(pkt[0], 0x30);
+        assert_eq!(pkt[1] as usize, pkt.len() - 2);
+        assert!(pkt.windows(6).any(|w| w == b"public"));
+    }
+
+    #[tokio::test]
+    async fn responding_port_is_open() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = server.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 64];
+            if let Ok((_, peer)) = server.recv_from(&mut buf).await {
+                server.send_to(b"pong", peer).await.ok();
+            }
+        });
+        let r = probe_udp(localhost(port), WAIT).await;
+        assert_eq!(r.state, PortState::Open);
+        assert_eq!(r.protocol, Protocol::Udp);
+    }
+
+    #[tokio::test]
+    async fn silent_port_is_open_filtered() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = server.local_addr().unwrap().port();
+        let r = probe_udp(localhost(port), WAIT).await;
+        drop(server);
+        assert_eq!(r.state, PortState::OpenFiltered);
+    }
+
+    #[tokio::test]
+    async fn unreachable_port_is_closed() {
+        let port = {
+            let s = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            s.local_addr().unwrap().port()
+        };
+        let r = probe_udp(localhost(port), WAIT).await;
+        assert_eq!(r.state, PortState::Closed);
+    }
+}
@@ -0,0 +1,97 @@
+use port_scanner::{grab_banner, PortState, Scanner};
+use std::{net::SocketAddr, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tokio_stream::StreamExt;
+
+const WAIT: Duration = Duration::from_millis(500);
+
+/// Listener that sends `greeting` on accept, then answers the first request
+/// with `reply`.
+async fn stub(greeting: &'static [u8], reply: &'static [u8]) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut sock, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                if !greeting.is_empty() {
+                    sock.write_all(greeting).await.ok();
+                }
+                let mut buf = [0u8; 1024];
+                if matches!(sock.read(&mut buf).await, Ok(n) if n > 0) && !reply.is_empty() {
+                    sock.write_all(reply).await.ok();
+                }
+            });
+        }
+    });
+    addr
+}
+
+async fn grab(addr: SocketAddr, port_hint: u16) -> Option<port_scanner::ServiceInfo> {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    grab_banner(stream, port_hint, WAIT).await
+}
+
+#[tokio::test]
+async fn ssh_greeting() {
+    let addr = stub(b"SSH-2.0-OpenSSH_9.6p1\r\n", b"").await;
+    let info = grab(addr, addr.port()).await.unwrap();
+    assert_eq!(info.name, "ssh");
+    assert_eq!(info.version.as_deref(), Some("OpenSSH_9.6p1"));
+}
+
+#[tokio::test]
+async fn smtp_greeting() {
+    let addr = stub(b"220 mail.test ESMTP Postfix\r\n", b"").await;
+    assert_eq!(grab(addr, addr.port()).await.unwrap().name, "smtp");
+}
+
+#[tokio::test]
+async fn silent_http_gets_head_probe() {
+    let addr = stub(b"", b"HTTP/1.0 200 OK\r\nServer: stub/1.0\r\n\r\n").await;
+    let info = grab(addr, addr.port()).await.unwrap();
+    assert_eq!(info.name, "http");
+    assert_eq!(info.version.as_deref(), Some("stub/1.0"));
+}
+
+#[tokio::test]
+async fn redis_ping_on_6379_hint() {
+    let addr = stub(b"", b"+PONG\r\n").await;
+    assert_eq!(grab(addr, 6379).await.unwrap().name, "redis");
+}
+
+#[tokio::test]
+async fn tls_client_hello_on_443_hint() {
+    // Minimal ServerHello: TLS 1.2, no extensions.
+    const HELLO: &[u8] = &[
+        0x16, 0x03, 0x03, 0x00, 0x2a, 0x02, 0x00, 0x00, 0x26, 0x03, 0x03, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x00, 0xc0, 0x2f,
+        0x00,
+    ];
+    let addr = stub(b"", HELLO).await;
+    let info = grab(addr, 443).await.unwrap();
+    assert_eq!(info.name, "tls");
+    assert_eq!(info.version.as_deref(), Some("TLS 1.2"));
+}
+
+#[tokio::test]
+async fn silent_service_yields_none() {
+    let addr = stub(b"", b"").await;
+    assert!(grab(addr, addr.port()).await.is_none());
+}
+
+#[tokio::test]
+async fn scanner_attaches_service_to_open_ports() {
+    let addr = stub(b"+OK POP3 ready\r\n", b"").await;
+    let scanner = Scanner::new()
+        .with_targets([addr.ip()])
+        .with_ports(&[addr.port()])
+        .with_banners(true)
+        .with_banner_timeout(WAIT);
+    let results: Vec<_> = scanner.scan().collect().await;
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].state, PortState::Open);
+    assert_eq!(results[0].service.as_ref().unwrap().name, "pop3");
+}
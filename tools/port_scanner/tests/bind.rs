@@ -0,0 +1,95 @@
+use port_scanner::{LocalBind, PortState, Protocol, Scanner};
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+use tokio::{
+    net::{TcpListener, UdpSocket},
+    sync::mpsc,
+};
+use tokio_stream::StreamExt;
+
+const WAIT: Duration = Duration::from_secs(1);
+
+fn free_port() -> u16 {
+    let l = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    l.local_addr().unwrap().port()
+}
+
+/// Listener that reports the peer address of every connection.
+async fn peer_reporter() -> (SocketAddr, mpsc::UnboundedReceiver<SocketAddr>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((_, peer)) = listener.accept().await {
+            let _ = tx.send(peer);
+        }
+    });
+    (addr, rx)
+}
+
+fn source(ip: &str, port: u16) -> LocalBind {
+    LocalBind {
+        ip: Some(ip.parse().unwrap()),
+        port: Some(port),
+        interface: None,
+    }
+}
+
+#[tokio::test]
+async fn tcp_probes_come_from_the_source_address_and_port() {
+    let (a, mut peers_a) = peer_reporter().await;
+    let (b, mut peers_b) = peer_reporter().await;
+    let port = free_port();
+    let expected = SocketAddr::from(([127, 0, 0, 2], port));
+
+    let results: Vec<_> = Scanner::new()
+        .with_targets(["127.0.0.1".parse().unwrap()])
+        .with_ports(&[a.port(), b.port()])
+        .with_timeout(WAIT)
+        .with_local_bind(source("127.0.0.2", port))
+        .scan()
+        .collect()
+        .await;
+    assert!(results.iter().all(|r| r.state == PortState::Open));
+    assert_eq!(peers_a.recv().await, Some(expected));
+    assert_eq!(peers_b.recv().await, Some(expected));
+}
+
+#[tokio::test]
+async fn udp_probes_come_from_the_source_address_and_port() {
+    let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let target = server.local_addr().unwrap();
+    let port = free_port();
+    let expected = SocketAddr::from(([127, 0, 0, 3], port));
+    // Only answer the expected source; anything else stays open|filtered.
+    tokio::spawn(async move {
+        let mut buf = [0u8; 512];
+        let (_, peer) = server.recv_from(&mut buf).await.unwrap();
+        if peer == expected {
+            server.send_to(b"ok", peer).await.unwrap();
+        }
+    });
+
+    let results: Vec<_> = Scanner::new()
+        .with_targets([target.ip()])
+        .with_ports(&[target.port()])
+        .with_protocol(Protocol::Udp)
+        .with_timeout(WAIT)
+        .with_local_bind(source("127.0.0.3", port))
+        .scan()
+        .collect()
+        .await;
+    assert_eq!(results[0].state, PortState::Open);
+}
+
+#[tokio::test]
+async fn source_of_the_other_family_cannot_connect() {
+    let (a, _peers) = peer_reporter().await;
+    let v6 = LocalBind {
+        ip: Some(IpAddr::from([0u16, 0, 0, 0, 0, 0, 0, 1])),
+        ..LocalBind::default()
+    };
+    assert!(v6.connect(a).await.is_err());
+}
@@ -0,0 +1,149 @@
+use port_scanner::{
+    inspect_http, OutputFormat, PortState, ReportWriter, ScanMeta, Scanner, Scheme,
+};
+use rustls::{
+    crypto::ring,
+    pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer},
+    ServerConfig,
+};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpListener,
+};
+use tokio_rustls::TlsAcceptor;
+use tokio_stream::StreamExt;
+
+const WAIT: Duration = Duration::from_secs(2);
+
+/// Answer one request on `sock` with `response`, then close.
+async fn respond<S: AsyncRead + AsyncWrite + Unpin>(mut sock: S, response: &[u8]) {
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        match sock.read_u8().await {
+            Ok(b) => head.push(b),
+            Err(_) => return,
+        }
+    }
+    sock.write_all(response).await.ok();
+    sock.shutdown().await.ok();
+}
+
+/// Plain HTTP server giving every request the same `response`.
+async fn http_server(response: &'static [u8]) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((sock, _)) = listener.accept().await {
+            tokio::spawn(respond(sock, response));
+        }
+    });
+    addr
+}
+
+/// HTTPS server with a self-signed certificate.
+async fn https_server(response: &'static [u8]) -> SocketAddr {
+    let rcgen::CertifiedKey { cert, key_pair } =
+        rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key_pair.serialize_der()));
+    let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert.der().clone()], key)
+        .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((sock, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                if let Ok(tls) = acceptor.accept(sock).await {
+                    respond(tls, response).await;
+                }
+            });
+        }
+    });
+    addr
+}
+
+#[tokio::test]
+async fn scanner_fingerprints_http_found_by_banners() {
+    let addr = http_server(
+        b"HTTP/1.1 200 OK\r\nServer: Apache/2.4.58\r\nX-Powered-By: PHP/8.3.1\r\n\
+          Content-Security-Policy: default-src 'self'\r\nContent-Length: 43\r\n\r\n\
+          <html><title>Intranet Portal</title></html>",
+    )
+    .await;
+    let scanner = Scanner::new()
+        .with_targets([addr.ip()])
+        .with_ports(&[addr.port()])
+        .with_banners(true)
+        .with_banner_timeout(Duration::from_millis(500))
+        .with_http_info(true);
+    let results: Vec<_> = scanner.scan().collect().await;
+
+    assert_eq!(results[0].state, PortState::Open);
+    assert_eq!(results[0].service.as_ref().unwrap().name, "http");
+    let http = results[0].http.as_ref().unwrap();
+    assert_eq!(http.scheme, Scheme::Http);
+    assert_eq!(http.status, 200);
+    assert_eq!(http.server.as_deref(), Some("Apache/2.4.58"));
+    assert_eq!(http.powered_by.as_deref(), Some("PHP/8.3.1"));
+    assert_eq!(http.title.as_deref(), Some("Intranet Portal"));
+    assert_eq!(http.missing_headers, ["X-Frame-Options"]);
+
+    let mut w = ReportWriter::new(
+        OutputFormat::NmapXml,
+        Vec::new(),
+        ScanMeta::new(&scanner, "x"),
+    )
+    .unwrap();
+    w.write(&results[0]).unwrap();
+    let xml = String::from_utf8(w.finish(Default::default()).unwrap()).unwrap();
+    assert!(xml.contains(r#"<script id="http-title" output="Intranet Portal">"#));
+    assert!(xml.contains("<elem>Apache/2.4.58</elem><elem>PHP/8.3.1</elem>"));
+    assert!(xml.contains(r#"<elem key="missing">X-Frame-Options</elem>"#));
+
+    let json = serde_json::to_string(&port_scanner::output::PortRecord::from(&results[0])).unwrap();
+    assert!(json.contains(r#""title":"Intranet Portal""#), "{json}");
+}
+
+#[tokio::test]
+async fn https_redirect_and_missing_hsts() {
+    let addr = https_server(
+        b"HTTP/1.1 302 Found\r\nLocation: /login\r\nX-Frame-Options: SAMEORIGIN\r\n\
+          Content-Length: 0\r\n\r\n",
+    )
+    .await;
+    let http = inspect_http(addr, Scheme::Https, WAIT).await.unwrap();
+    assert_eq!((http.status, http.reason.as_str()), (302, "Found"));
+    assert_eq!(http.redirect.as_deref(), Some("/login"));
+    assert_eq!(http.title, None);
+    assert_eq!(http.missing_headers, ["HSTS", "CSP"]);
+}
+
+#[tokio::test]
+async fn non_web_ports_are_left_alone() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut sock, _)) = listener.accept().await {
+            sock.write_all(b"SSH-2.0-OpenSSH_9.6\r\n").await.ok();
+            let mut buf = [0u8; 64];
+            let _ = sock.read(&mut buf).await;
+        }
+    });
+    let results: Vec<_> = Scanner::new()
+        .with_targets([addr.ip()])
+        .with_ports(&[addr.port()])
+        .with_banners(true)
+        .with_http_info(true)
+        .scan()
+        .collect()
+        .await;
+    assert_eq!(results[0].service.as_ref().unwrap().name, "ssh");
+    assert!(results[0].http.is_none());
+}
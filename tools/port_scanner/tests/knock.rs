@@ -0,0 +1,102 @@
+use port_scanner::{Knock, Knocker, PortState};
+use std::{
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::net::{TcpListener, UdpSocket};
+
+/// Stub knock daemon on 127.0.0.1: a TCP knock port, a UDP knock port and a
+/// second TCP knock port. Once all three were hit in order it starts
+/// listening on the protected port. Returns the three knocks and the
+/// protected port.
+async fn knock_daemon() -> ([Knock; 3], u16) {
+    let first = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let second = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let third = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let protected = {
+        let l = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        l.local_addr().unwrap().port()
+    };
+    let knocks = [
+        Knock::tcp(first.local_addr().unwrap().port()),
+        Knock::udp(second.local_addr().unwrap().port()),
+        Knock::tcp(third.local_addr().unwrap().port()),
+    ];
+
+    // Steps seen so far; any knock out of order resets it.
+    let progress = Arc::new(Mutex::new(0));
+    let step = |expected: usize, progress: &Mutex<usize>| {
+        let mut p = progress.lock().unwrap();
+        *p = if *p == expected { expected + 1 } else { 0 };
+        *p == 3
+    };
+
+    let p = Arc::clone(&progress);
+    tokio::spawn(async move {
+        while first.accept().await.is_ok() {
+            step(0, &p);
+        }
+    });
+    let p = Arc::clone(&progress);
+    tokio::spawn(async move {
+        let mut buf = [0u8; 64];
+        while second.recv_from(&mut buf).await.is_ok() {
+            step(1, &p);
+        }
+    });
+    tokio::spawn(async move {
+        while third.accept().await.is_ok() {
+            if step(2, &progress) {
+                let open = TcpListener::bind(("127.0.0.1", protected)).await.unwrap();
+                while open.accept().await.is_ok() {}
+            }
+        }
+    });
+    (knocks, protected)
+}
+
+fn localhost() -> IpAddr {
+    [127, 0, 0, 1].into()
+}
+
+#[tokio::test]
+async fn correct_sequence_opens_the_port() {
+    let (knocks, protected) = knock_daemon().await;
+    let report = Knocker::new(knocks)
+        .with_delay(Duration::from_millis(50))
+        .run(localhost(), protected)
+        .await;
+
+    assert_eq!(report.before.state, PortState::Closed);
+    assert_eq!(report.knocks.len(), 3);
+    assert_eq!(report.knocks[1].state, PortState::OpenFiltered);
+    assert_eq!(report.after.state, PortState::Open);
+    assert!(report.opened());
+}
+
+#[tokio::test]
+async fn wrong_order_leaves_the_port_closed() {
+    let (knocks, protected) = knock_daemon().await;
+    let report = Knocker::new([knocks[0], knocks[2], knocks[1]])
+        .with_delay(Duration::from_millis(50))
+        .run(localhost(), protected)
+        .await;
+
+    assert_eq!(report.after.state, PortState::Closed);
+    assert!(!report.opened());
+}
+
+#[tokio::test]
+async fn already_open_port_is_not_a_working_knock() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let open = listener.local_addr().unwrap();
+    tokio::spawn(async move { while listener.accept().await.is_ok() {} });
+    let report = Knocker::new([Knock::tcp(1)])
+        .with_delay(Duration::ZERO)
+        .run(localhost(), open.port())
+        .await;
+    assert_eq!(report.before.state, PortState::Open);
+    assert_eq!(report.after.state, PortState::Open);
+    assert!(!report.opened());
+}
@@ -0,0 +1,181 @@
+use port_scanner::{PortState, Proxy, ProxyError, ProxyKind, Scanner};
+use std::{net::SocketAddr, time::Duration};
+use tokio::{
+    io::{copy_bidirectional, AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tokio_stream::StreamExt;
+
+const WAIT: Duration = Duration::from_secs(1);
+
+/// Listener that greets every connection like an SSH server.
+async fn ssh_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut sock, _)) = listener.accept().await {
+            sock.write_all(b"SSH-2.0-OpenSSH_9.6\r\n").await.ok();
+            let mut buf = [0u8; 64];
+            let _ = sock.read(&mut buf).await;
+        }
+    });
+    addr
+}
+
+async fn unused_port() -> u16 {
+    let l = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    l.local_addr().unwrap().port()
+}
+
+/// Minimal IPv4-only SOCKS5 server, optionally requiring `user`/`pass`.
+async fn socks5_proxy(creds: Option<(&'static str, &'static str)>) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut client, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut head = [0u8; 2];
+                client.read_exact(&mut head).await?;
+                let mut methods = vec![0u8; head[1] as usize];
+                client.read_exact(&mut methods).await?;
+                if let Some((user, pass)) = creds {
+                    client.write_all(&[5, 2]).await?;
+                    let mut buf = [0u8; 512];
+                    let n = client.read(&mut buf).await?;
+                    let ulen = buf[1] as usize;
+                    let ok = &buf[2..2 + ulen] == user.as_bytes()
+                        && &buf[3 + ulen..n] == pass.as_bytes();
+                    client.write_all(&[1, if ok { 0 } else { 1 }]).await?;
+                    if !ok {
+                        return Ok(());
+                    }
+                } else {
+                    client.write_all(&[5, 0]).await?;
+                }
+                let mut req = [0u8; 10];
+                client.read_exact(&mut req).await?;
+                let ip = [req[4], req[5], req[6], req[7]];
+                let port = u16::from_be_bytes([req[8], req[9]]);
+                match TcpStream::connect(SocketAddr::from((ip, port))).await {
+                    Ok(mut upstream) => {
+                        client.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).await?;
+                        copy_bidirectional(&mut client, &mut upstream).await?;
+                    }
+                    Err(_) => client.write_all(&[5, 5, 0, 1, 0, 0, 0, 0, 0, 0]).await?,
+                }
+                Ok::<_, std::io::Error>(())
+            });
+        }
+    });
+    addr
+}
+
+/// Minimal HTTP CONNECT proxy: 200 when the upstream connect works, 502
+/// otherwise.
+async fn http_proxy() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut client, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut head = Vec::new();
+                while !head.ends_with(b"\r\n\r\n") {
+                    head.push(client.read_u8().await?);
+                }
+                let head = String::from_utf8(head).unwrap();
+                let target: SocketAddr = head.split(' ').nth(1).unwrap().parse().unwrap();
+                match TcpStream::connect(target).await {
+                    Ok(mut upstream) => {
+                        client
+                            .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                            .await?;
+                        copy_bidirectional(&mut client, &mut upstream).await?;
+                    }
+                    Err(_) => {
+                        client
+                            .write_all(b"HTTP/1.1 502 Bad Gateway\r\n\r\n")
+                            .await?
+                    }
+                }
+                Ok::<_, std::io::Error>(())
+            });
+        }
+    });
+    addr
+}
+
+async fn scan_via(proxy: Proxy, ports: &[u16]) -> Vec<port_scanner::PortResult> {
+    let mut results: Vec<_> = Scanner::new()
+        .with_targets(["127.0.0.1".parse().unwrap()])
+        .with_ports(ports)
+        .with_timeout(WAIT)
+        .with_banners(true)
+        .with_banner_timeout(Duration::from_millis(300))
+        .with_proxy(proxy)
+        .scan()
+        .collect()
+        .await;
+    results.sort_by_key(|r| ports.iter().position(|&p| p == r.port));
+    results
+}
+
+#[tokio::test]
+async fn socks5_tunnel_classifies_ports_and_grabs_banners() {
+    let ssh = ssh_server().await;
+    let closed = unused_port().await;
+    let proxy = Proxy::new(ProxyKind::Socks5, socks5_proxy(None).await);
+
+    let results = scan_via(proxy, &[ssh.port(), closed]).await;
+    assert_eq!(results[0].state, PortState::Open);
+    let svc = results[0].service.as_ref().unwrap();
+    assert_eq!(svc.version.as_deref(), Some("OpenSSH_9.6"));
+    assert_eq!(results[1].state, PortState::Closed);
+}
+
+#[tokio::test]
+async fn http_connect_tunnel_classifies_ports_and_grabs_banners() {
+    let ssh = ssh_server().await;
+    let closed = unused_port().await;
+    let proxy = Proxy::new(ProxyKind::Http, http_proxy().await);
+
+    let results = scan_via(proxy, &[ssh.port(), closed]).await;
+    assert_eq!(results[0].state, PortState::Open);
+    assert_eq!(results[0].service.as_ref().unwrap().name, "ssh");
+    assert_eq!(results[1].state, PortState::Closed);
+}
+
+#[tokio::test]
+async fn socks5_credentials() {
+    let ssh = ssh_server().await;
+    let addr = socks5_proxy(Some(("scan", "hunter2"))).await;
+
+    let good = Proxy::new(ProxyKind::Socks5, addr).with_auth("scan", "hunter2");
+    good.check(WAIT).await.unwrap();
+    assert_eq!(
+        scan_via(good, &[ssh.port()]).await[0].state,
+        PortState::Open
+    );
+
+    let bad = Proxy::new(ProxyKind::Socks5, addr).with_auth("scan", "wrong");
+    assert!(matches!(bad.check(WAIT).await, Err(ProxyError::Auth)));
+    assert_eq!(
+        scan_via(bad, &[ssh.port()]).await[0].state,
+        PortState::ProxyError
+    );
+}
+
+#[tokio::test]
+async fn unreachable_proxy_is_not_a_closed_port() {
+    let ssh = ssh_server().await;
+    let dead = SocketAddr::from(([127, 0, 0, 1], unused_port().await));
+    let proxy = Proxy::new(ProxyKind::Socks5, dead);
+
+    assert!(matches!(
+        proxy.check(WAIT).await,
+        Err(ProxyError::Unreachable(_))
+    ));
+    assert_eq!(
+        scan_via(proxy, &[ssh.port()]).await[0].state,
+        PortState::ProxyError
+    );
+}
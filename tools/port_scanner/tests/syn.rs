@@ -0,0 +1,74 @@
+use port_scanner::{syn_available, PortState, Scanner, SynEngine};
+use std::{net::SocketAddr, time::Duration};
+use tokio::{io::AsyncWriteExt, net::TcpListener, time::timeout};
+use tokio_stream::StreamExt;
+
+const WAIT: Duration = Duration::from_secs(1);
+
+async fn unused_port() -> u16 {
+    let l = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    l.local_addr().unwrap().port()
+}
+
+/// Raw sockets need privileges the test runner may not have.
+fn privileged() -> bool {
+    if !syn_available() {
+        eprintln!("skipping: no CAP_NET_RAW");
+        return false;
+    }
+    true
+}
+
+#[tokio::test]
+async fn syn_probe_classifies_loopback_ports_without_connecting() {
+    if !privileged() {
+        return;
+    }
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let open = listener.local_addr().unwrap();
+    let closed = SocketAddr::from(([127, 0, 0, 1], unused_port().await));
+
+    let engine = SynEngine::new().unwrap();
+    let r = engine.probe(open, WAIT).await.unwrap();
+    assert_eq!(r.state, PortState::Open);
+    assert_eq!(
+        engine.probe(closed, WAIT).await.unwrap().state,
+        PortState::Closed
+    );
+    // The handshake was never completed, so nothing is waiting in the
+    // accept queue.
+    assert!(timeout(Duration::from_millis(200), listener.accept())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn syn_scan_reports_every_port_and_still_grabs_banners() {
+    if !privileged() {
+        return;
+    }
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let open = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((mut sock, _)) = listener.accept().await {
+            sock.write_all(b"SSH-2.0-OpenSSH_9.6\r\n").await.ok();
+            tokio::time::sleep(WAIT).await;
+        }
+    });
+    let closed = unused_port().await;
+
+    let scanner = Scanner::new()
+        .with_targets(["127.0.0.1".parse().unwrap()])
+        .with_ports(&[open, closed])
+        .with_timeout(WAIT)
+        .with_syn(true)
+        .with_banners(true)
+        .with_banner_timeout(Duration::from_millis(300));
+    let results: Vec<_> = scanner.scan().collect().await;
+    assert_eq!(results.len(), 2);
+    let open_result = results.iter().find(|r| r.port == open).unwrap();
+    assert_eq!(open_result.state, PortState::Open);
+    assert_eq!(open_result.service.as_ref().unwrap().name, "ssh");
+    let closed_result = results.iter().find(|r| r.port == closed).unwrap();
+    assert_eq!(closed_result.state, PortState::Closed);
+}
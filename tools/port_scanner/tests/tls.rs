@@ -0,0 +1,161 @@
+use port_scanner::{inspect_tls, OutputFormat, PortState, ReportWriter, ScanMeta, Scanner};
+use rustls::{
+    crypto::ring,
+    pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer},
+    ServerConfig,
+};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use tokio_rustls::TlsAcceptor;
+use tokio_stream::StreamExt;
+
+const WAIT: Duration = Duration::from_secs(2);
+
+/// TLS listener presenting a fresh self-signed certificate for `names`.
+async fn tls_server(names: &[&str]) -> SocketAddr {
+    let names = names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+    let rcgen::CertifiedKey { cert, key_pair } = rcgen::generate_simple_self_signed(names).unwrap();
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key_pair.serialize_der()));
+    let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert.der().clone()], key)
+        .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((sock, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                if let Ok(mut tls) = acceptor.accept(sock).await {
+                    tls.shutdown().await.ok();
+                }
+            });
+        }
+    });
+    addr
+}
+
+/// Server that only speaks TLS 1.0: it answers any ClientHello with a
+/// TLS 1.0 ServerHello, its certificate and ServerHelloDone, with the
+/// messages split across records at an awkward point.
+async fn tls10_server(name: &str) -> SocketAddr {
+    let cert = rcgen::generate_simple_self_signed(vec![name.to_string()])
+        .unwrap()
+        .cert;
+    let der = cert.der().to_vec();
+    let handshake = |kind: u8, body: &[u8]| {
+        let mut msg = vec![kind];
+        msg.extend(&(body.len() as u32).to_be_bytes()[1..]);
+        msg.extend(body);
+        msg
+    };
+    let mut hello = vec![0x03, 0x01];
+    hello.extend([7u8; 32]);
+    hello.extend([0x00, 0x00, 0x2f, 0x00]); // no session id, AES128-SHA, null
+    let mut entry = (der.len() as u32).to_be_bytes()[1..].to_vec();
+    entry.extend(&der);
+    let mut list = (entry.len() as u32).to_be_bytes()[1..].to_vec();
+    list.extend(entry);
+
+    let mut flight = handshake(2, &hello);
+    flight.extend(handshake(11, &list));
+    flight.extend(handshake(14, &[]));
+    let mut records = Vec::new();
+    for chunk in flight.chunks(flight.len() / 2 + 3) {
+        records.extend([0x16, 0x03, 0x01]);
+        records.extend((chunk.len() as u16).to_be_bytes());
+        records.extend(chunk);
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut sock, _)) = listener.accept().await {
+            let records = records.clone();
+            tokio::spawn(async move {
+                let mut buf = [0u8; 4096];
+                if sock.read(&mut buf).await.unwrap_or(0) > 0 {
+                    sock.write_all(&records).await.ok();
+                }
+                sock.read_to_end(&mut Vec::new()).await.ok();
+            });
+        }
+    });
+    addr
+}
+
+#[tokio::test]
+async fn reports_handshake_and_certificate() {
+    let addr = tls_server(&["localhost", "127.0.0.1"]).await;
+    let info = inspect_tls(addr, WAIT).await.unwrap();
+    assert_eq!(info.version, "TLS 1.3");
+    assert!(info.cipher.starts_with("TLS13_"), "{}", info.cipher);
+    assert_eq!(info.san, ["localhost", "127.0.0.1"]);
+    assert_eq!(info.subject, info.issuer);
+    assert!(info.self_signed);
+    assert!(!info.expired);
+    assert!(info.not_before < info.not_after);
+    // rcgen's default key is ECDSA P-256.
+    assert_eq!((info.key_type.as_str(), info.key_bits), ("EC", 256));
+}
+
+#[tokio::test]
+async fn legacy_only_server_still_reports_version_and_certificate() {
+    let addr = tls10_server("legacy.test").await;
+    let info = inspect_tls(addr, WAIT).await.unwrap();
+    assert_eq!(info.version, "TLS 1.0");
+    assert_eq!(info.cipher, "TLS_RSA_WITH_AES_128_CBC_SHA");
+    assert_eq!(info.san, ["legacy.test"]);
+
+    let scanner = Scanner::new()
+        .with_targets([addr.ip()])
+        .with_ports(&[addr.port()])
+        .with_tls(true)
+        .with_banner_timeout(WAIT);
+    let results: Vec<_> = scanner.scan().collect().await;
+    assert_eq!(results[0].tls.as_ref().unwrap().version, "TLS 1.0");
+}
+
+#[tokio::test]
+async fn plain_tcp_fails_the_handshake() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut sock, _)) = listener.accept().await {
+            sock.write_all(b"SSH-2.0-OpenSSH_9.6\r\n").await.ok();
+        }
+    });
+    assert!(inspect_tls(addr, WAIT).await.is_err());
+}
+
+#[tokio::test]
+async fn scanner_attaches_tls_to_open_ports() {
+    let addr = tls_server(&["scan.test"]).await;
+    let scanner = Scanner::new()
+        .with_targets([addr.ip()])
+        .with_ports(&[addr.port()])
+        .with_tls(true)
+        .with_banner_timeout(WAIT);
+    let results: Vec<_> = scanner.scan().collect().await;
+    assert_eq!(results[0].state, PortState::Open);
+    let tls = results[0].tls.as_ref().unwrap();
+    assert_eq!(tls.san, ["scan.test"]);
+
+    let mut w = ReportWriter::new(
+        OutputFormat::NmapXml,
+        Vec::new(),
+        ScanMeta::new(&scanner, "x"),
+    )
+    .unwrap();
+    w.write(&results[0]).unwrap();
+    let xml = String::from_utf8(w.finish(Default::default()).unwrap()).unwrap();
+    assert!(xml.contains(r#"<script id="ssl-cert""#));
+    assert!(xml.contains(r#"<elem key="san">scan.test</elem>"#));
+}